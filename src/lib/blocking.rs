@@ -0,0 +1,186 @@
+use crate::models::{ListEnd, RespValue};
+use bytes::Bytes;
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use tokio::sync::oneshot;
+
+// A key of a given database
+type Slot = (usize, Bytes);
+
+/// What a blocked client does to one of its keys once it holds something.
+#[derive(Debug, Clone)]
+pub enum BlockedOp {
+    // BLPOP and BRPOP pop a single element, BLMPOP up to a count of them
+    Pop {
+        end: ListEnd,
+        count: Option<usize>,
+    },
+    Move {
+        destination: Bytes,
+        from: ListEnd,
+        to: ListEnd,
+    },
+    // BZPOPMIN and BZPOPMAX pop a single member, BZMPOP up to a count of them
+    ZPop {
+        max: bool,
+        count: Option<usize>,
+    },
+}
+
+impl BlockedOp {
+    /// Whether the operation pops from sorted sets rather than lists.
+    pub fn is_zset(&self) -> bool {
+        matches!(self, BlockedOp::ZPop { .. })
+    }
+}
+
+/// A client blocked on one or more keys of database `index`.
+pub struct Waiter {
+    pub index: usize,
+    pub keys: Vec<Bytes>,
+    pub op: BlockedOp,
+
+    // Taken by whoever serves the client, so it's served once even when waiting on several keys
+    reply: Mutex<Option<oneshot::Sender<RespValue>>>,
+}
+
+impl Waiter {
+    /// Whether the client is still around to take a reply.
+    pub fn is_waiting(&self) -> bool {
+        self.reply
+            .lock()
+            .unwrap()
+            .as_ref()
+            .is_some_and(|reply| !reply.is_closed())
+    }
+
+    pub fn serve(&self, reply: RespValue) {
+        if let Some(sender) = self.reply.lock().unwrap().take() {
+            let _ = sender.send(reply);
+        }
+    }
+}
+
+/// Clients blocked on keys, queued per key in the order they blocked.
+///
+/// Writes that may give waiters what they're after signal the key, and the keys signalled are
+/// served once the command is done, before any other command runs.
+#[derive(Default)]
+pub struct BlockedClients {
+    waiters: Mutex<HashMap<Slot, VecDeque<Arc<Waiter>>>>,
+    ready: Mutex<VecDeque<Slot>>,
+}
+
+impl BlockedClients {
+    /// Queues a client on each of `keys`. It stays queued until the registration is dropped,
+    /// which is also how a timed out or disconnected client leaves.
+    pub fn register(
+        &self,
+        index: usize,
+        keys: Vec<Bytes>,
+        op: BlockedOp,
+    ) -> (Registration<'_>, oneshot::Receiver<RespValue>) {
+        let (sender, receiver) = oneshot::channel();
+        let waiter = Arc::new(Waiter {
+            index,
+            keys,
+            op,
+            reply: Mutex::new(Some(sender)),
+        });
+
+        let mut waiters = self.waiters.lock().unwrap();
+        for key in &waiter.keys {
+            let queue = waiters.entry((index, key.clone())).or_default();
+            // A key given twice still gets a single place in line
+            if !queue.iter().any(|queued| Arc::ptr_eq(queued, &waiter)) {
+                queue.push_back(waiter.clone());
+            }
+        }
+
+        let registration = Registration {
+            blocked: self,
+            waiter,
+        };
+        (registration, receiver)
+    }
+
+    /// Notes that `key` may now have something for its waiters, if it has any.
+    pub fn signal(&self, index: usize, key: &Bytes) {
+        if !self
+            .waiters
+            .lock()
+            .unwrap()
+            .contains_key(&(index, key.clone()))
+        {
+            return;
+        }
+
+        let mut ready = self.ready.lock().unwrap();
+        if !ready.iter().any(|(i, k)| *i == index && k == key) {
+            ready.push_back((index, key.clone()));
+        }
+    }
+
+    /// Signals every key waited on in database `index`, whose contents were swapped wholesale.
+    pub fn signal_all(&self, index: usize) {
+        let keys: Vec<Bytes> = self
+            .waiters
+            .lock()
+            .unwrap()
+            .keys()
+            .filter(|(i, _)| *i == index)
+            .map(|(_, key)| key.clone())
+            .collect();
+        for key in keys {
+            self.signal(index, &key);
+        }
+    }
+
+    /// Takes the key signalled the earliest.
+    pub fn next_ready(&self) -> Option<Slot> {
+        self.ready.lock().unwrap().pop_front()
+    }
+
+    /// Returns the longest waiting client blocked on `key` that is still around and whose
+    /// operation `fits`.
+    pub fn first_waiter(
+        &self,
+        index: usize,
+        key: &Bytes,
+        fits: impl Fn(&BlockedOp) -> bool,
+    ) -> Option<Arc<Waiter>> {
+        let waiters = self.waiters.lock().unwrap();
+        waiters
+            .get(&(index, key.clone()))?
+            .iter()
+            .find(|waiter| waiter.is_waiting() && fits(&waiter.op))
+            .cloned()
+    }
+
+    /// Takes `waiter` off the queues of all its keys.
+    pub fn unregister(&self, waiter: &Arc<Waiter>) {
+        let mut waiters = self.waiters.lock().unwrap();
+        for key in &waiter.keys {
+            let slot = (waiter.index, key.clone());
+            let Some(queue) = waiters.get_mut(&slot) else {
+                continue;
+            };
+            queue.retain(|queued| !Arc::ptr_eq(queued, waiter));
+            if queue.is_empty() {
+                waiters.remove(&slot);
+            }
+        }
+    }
+}
+
+/// Keeps a client queued on its keys for as long as it's alive.
+pub struct Registration<'a> {
+    blocked: &'a BlockedClients,
+    waiter: Arc<Waiter>,
+}
+
+impl Drop for Registration<'_> {
+    fn drop(&mut self) {
+        self.blocked.unregister(&self.waiter);
+    }
+}
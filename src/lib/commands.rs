@@ -0,0 +1,3507 @@
+use crate::models::Command::*;
+use crate::models::{
+    Aggregate, Command, CopyParams, ExpireParams, Expiry, HelloParams, LInsertParams, LPosParams,
+    LexBound, ListEnd, RedisError, RespValue, ScanParams, ScoreBound, SetCondition, SetOp,
+    SetParams, ZAddParams, ZCombineParams, ZRangeBy, ZRangeParams,
+};
+use bytes::Bytes;
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Static description of a command: how it's validated, parsed and advertised to clients.
+pub struct CommandSpec {
+    pub name: &'static str,
+
+    // Counts the command name itself: positive is exact, negative is a minimum
+    pub arity: i32,
+
+    pub flags: &'static [&'static str],
+
+    // Legacy key positions as reported by COMMAND INFO. A negative last key counts
+    // from the end, and a first key of 0 means the command takes no keys
+    pub first_key: i32,
+    pub last_key: i32,
+    pub step: i32,
+
+    pub acl_categories: &'static [&'static str],
+    pub group: &'static str,
+    pub summary: &'static str,
+    pub since: &'static str,
+
+    pub subcommands: &'static [CommandSpec],
+
+    // Receives the arguments following the command (and subcommand) name
+    pub parse: fn(&[Bytes]) -> Result<Command, RedisError>,
+}
+
+impl CommandSpec {
+    pub fn is_write(&self) -> bool {
+        self.flags.contains(&"write")
+    }
+
+    pub fn accepts(&self, argc: usize) -> bool {
+        let argc = argc as i32;
+        if self.arity >= 0 {
+            argc == self.arity
+        } else {
+            argc >= -self.arity
+        }
+    }
+
+    /// Whether the command takes any keys, at fixed positions or not.
+    pub fn has_keys(&self) -> bool {
+        self.first_key > 0 || self.flags.contains(&"movablekeys")
+    }
+
+    /// Positions within `argv` (command name included) holding key names. Empty if the
+    /// arguments don't say where the keys are, such as with a numkeys past the last argument.
+    pub fn key_positions(&self, argv: &[Bytes]) -> Vec<usize> {
+        if self.flags.contains(&"movablekeys") {
+            return numkeys_key_positions(self.name, argv);
+        }
+        if self.first_key <= 0 {
+            return vec![];
+        }
+
+        let argc = argv.len();
+        let last = if self.last_key < 0 {
+            argc as i32 + self.last_key
+        } else {
+            self.last_key
+        };
+        (self.first_key..=last.min(argc as i32 - 1))
+            .step_by(self.step.max(1) as usize)
+            .map(|position| position as usize)
+            .collect()
+    }
+
+    /// The reply to COMMAND INFO, in the layout Redis 7 uses.
+    pub fn info(&self, parent: Option<&CommandSpec>) -> RespValue {
+        let name = match parent {
+            Some(parent) => format!("{}|{}", parent.name, self.name),
+            None => self.name.to_string(),
+        };
+        let flags = self
+            .flags
+            .iter()
+            .map(|flag| RespValue::SimpleString(flag.to_string()))
+            .collect();
+        let acl_categories = self
+            .acl_categories
+            .iter()
+            .map(|category| RespValue::SimpleString(category.to_string()))
+            .collect();
+        let subcommands = self
+            .subcommands
+            .iter()
+            .map(|subcommand| subcommand.info(Some(self)))
+            .collect();
+
+        RespValue::Array(vec![
+            RespValue::bulk(name),
+            RespValue::Integer(self.arity as i64),
+            RespValue::Set(flags),
+            RespValue::Integer(self.first_key as i64),
+            RespValue::Integer(self.last_key as i64),
+            RespValue::Integer(self.step as i64),
+            RespValue::Set(acl_categories),
+            // Command tips and key specs
+            RespValue::Set(vec![]),
+            RespValue::Array(vec![]),
+            RespValue::Array(subcommands),
+        ])
+    }
+
+    /// The reply to COMMAND DOCS for this command.
+    pub fn docs(&self) -> RespValue {
+        let mut docs = vec![
+            (RespValue::bulk("summary"), RespValue::bulk(self.summary)),
+            (RespValue::bulk("since"), RespValue::bulk(self.since)),
+            (RespValue::bulk("group"), RespValue::bulk(self.group)),
+        ];
+        if !self.subcommands.is_empty() {
+            let subcommands = self
+                .subcommands
+                .iter()
+                .map(|subcommand| {
+                    (
+                        RespValue::bulk(format!("{}|{}", self.name, subcommand.name)),
+                        subcommand.docs(),
+                    )
+                })
+                .collect();
+            docs.push((RespValue::bulk("subcommands"), RespValue::Map(subcommands)));
+        }
+
+        RespValue::Map(docs)
+    }
+}
+
+// Keys of the commands that say how many they take with a numkeys argument, along with any key
+// before it, such as the destination of ZUNIONSTORE
+fn numkeys_key_positions(name: &str, argv: &[Bytes]) -> Vec<usize> {
+    let (numkeys_at, mut positions) = match name {
+        "zunionstore" | "zinterstore" | "zdiffstore" => (2, vec![1]),
+        // The timeout comes first
+        "blmpop" | "bzmpop" => (2, vec![]),
+        _ => (1, vec![]),
+    };
+    let first = numkeys_at + 1;
+    let numkeys = argv
+        .get(numkeys_at)
+        .and_then(|numkeys| to_string(numkeys).parse::<usize>().ok())
+        .filter(|numkeys| *numkeys > 0 && first + numkeys <= argv.len());
+    let Some(numkeys) = numkeys else {
+        return vec![];
+    };
+    positions.extend(first..first + numkeys);
+    positions
+}
+
+pub fn lookup(name: &[u8]) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE
+        .iter()
+        .find(|spec| spec.name.as_bytes().eq_ignore_ascii_case(name))
+}
+
+/// Resolves `argv` to the spec that handles it, descending into subcommands.
+///
+/// Arity is left unchecked so callers can word their own errors.
+pub fn resolve(argv: &[Bytes]) -> Result<(&'static CommandSpec, usize), RedisError> {
+    let Some(spec) = lookup(&argv[0]) else {
+        let beginning: String = argv[1..]
+            .iter()
+            .take(20)
+            .map(|arg| format!("'{}' ", to_string(arg)))
+            .collect();
+        return Err(RedisError::UnknownCommand(to_string(&argv[0]), beginning));
+    };
+
+    if spec.subcommands.is_empty() || argv.len() < 2 {
+        return Ok((spec, 1));
+    }
+
+    match spec
+        .subcommands
+        .iter()
+        .find(|subcommand| subcommand.name.as_bytes().eq_ignore_ascii_case(&argv[1]))
+    {
+        Some(subcommand) => Ok((subcommand, 2)),
+        None => Err(RedisError::Err(format!(
+            "unknown subcommand '{}'. Try {} HELP.",
+            to_string(&argv[1]),
+            spec.name.to_uppercase()
+        ))),
+    }
+}
+
+/// Looks `argv` up in the command table, validates its arity and parses it.
+pub fn parse(argv: &[Bytes]) -> Result<(&'static CommandSpec, Command), RedisError> {
+    let (spec, consumed) = resolve(argv)?;
+    if !spec.accepts(argv.len()) {
+        let name = match consumed {
+            1 => spec.name.to_string(),
+            _ => format!("{}|{}", to_keyword(&argv[0]), spec.name),
+        };
+        return Err(RedisError::WrongArity(name));
+    }
+
+    let command = (spec.parse)(&argv[consumed..])?;
+    Ok((spec, command))
+}
+
+pub static COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec {
+        name: "ping",
+        arity: -1,
+        flags: &["fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@fast", "@connection"],
+        group: "connection",
+        summary: "Returns the server's liveliness response.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| match args {
+            [] => Ok(Ping(None)),
+            [message] => Ok(Ping(Some(message.clone()))),
+            _ => Err(RedisError::WrongArity("ping".to_string())),
+        },
+    },
+    CommandSpec {
+        name: "echo",
+        arity: 2,
+        flags: &["fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@fast", "@connection"],
+        group: "connection",
+        summary: "Returns the given string.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Echo(args[0].clone())),
+    },
+    CommandSpec {
+        name: "hello",
+        arity: -1,
+        flags: &["noscript", "loading", "stale", "fast", "no_auth", "allow_busy"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@fast", "@connection"],
+        group: "connection",
+        summary: "Handshakes with the Redis server.",
+        since: "6.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Hello(build_hello_params(args)?)),
+    },
+    CommandSpec {
+        name: "get",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@string", "@fast"],
+        group: "string",
+        summary: "Returns the string value of a key.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Get(args[0].clone())),
+    },
+    CommandSpec {
+        name: "set",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@slow"],
+        group: "string",
+        summary: "Sets the string value of a key, ignoring its type. The key is created if it doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Set(build_set_params(args)?)),
+    },
+    CommandSpec {
+        name: "setnx",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Set the string value of a key only when the key doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SetNx(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "setex",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@slow"],
+        group: "string",
+        summary: "Sets the string value and expiration time of a key. Creates the key if it doesn't exist.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(Set(SetParams {
+                key: args[0].clone(),
+                value: args[2].clone(),
+                expiry: Some(parse_expiry("ex", &args[1], "setex")?),
+                condition: None,
+                get: false,
+            }))
+        },
+    },
+    CommandSpec {
+        name: "psetex",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@slow"],
+        group: "string",
+        summary: "Sets both string value and expiration time in milliseconds of a key. The key is created if it doesn't exist.",
+        since: "2.6.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(Set(SetParams {
+                key: args[0].clone(),
+                value: args[2].clone(),
+                expiry: Some(parse_expiry("px", &args[1], "psetex")?),
+                condition: None,
+                get: false,
+            }))
+        },
+    },
+    CommandSpec {
+        name: "getset",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Returns the previous string value of a key after setting it to a new value.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(GetSet(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "getdel",
+        arity: 2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Returns the string value of a key after deleting the key.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| Ok(GetDel(args[0].clone())),
+    },
+    CommandSpec {
+        name: "getex",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Returns the string value of a key after setting its expiration time.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| Ok(GetEx(args[0].clone(), parse_getex_expiry(&args[1..])?)),
+    },
+    CommandSpec {
+        name: "append",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Appends a string to the value of a key. Creates the key if it doesn't exist.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Append(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "strlen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@string", "@fast"],
+        group: "string",
+        summary: "Returns the length of a string value.",
+        since: "2.2.0",
+        subcommands: &[],
+        parse: |args| Ok(StrLen(args[0].clone())),
+    },
+    CommandSpec {
+        name: "getrange",
+        arity: 4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@string", "@slow"],
+        group: "string",
+        summary: "Returns a substring of the string stored at a key.",
+        since: "2.4.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(GetRange(
+                args[0].clone(),
+                parse_integer(&args[1])?,
+                parse_integer(&args[2])?,
+            ))
+        },
+    },
+    CommandSpec {
+        name: "setrange",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@slow"],
+        group: "string",
+        summary: "Overwrites a part of a string value with another by an offset. Creates the key if it doesn't exist.",
+        since: "2.2.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(SetRange(
+                args[0].clone(),
+                parse_integer(&args[1])?,
+                args[2].clone(),
+            ))
+        },
+    },
+    CommandSpec {
+        name: "mget",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@read", "@string", "@fast"],
+        group: "string",
+        summary: "Atomically returns the string values of one or more keys.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(MGet(args.to_vec())),
+    },
+    CommandSpec {
+        name: "mset",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 2,
+        acl_categories: &["@write", "@string", "@slow"],
+        group: "string",
+        summary: "Atomically creates or modifies the string values of one or more keys.",
+        since: "1.0.1",
+        subcommands: &[],
+        parse: |args| Ok(MSet(parse_pairs(args, "mset")?)),
+    },
+    CommandSpec {
+        name: "msetnx",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 2,
+        acl_categories: &["@write", "@string", "@slow"],
+        group: "string",
+        summary: "Atomically modifies the string values of one or more keys only when all keys don't exist.",
+        since: "1.0.1",
+        subcommands: &[],
+        parse: |args| Ok(MSetNx(parse_pairs(args, "msetnx")?)),
+    },
+    CommandSpec {
+        name: "incr",
+        arity: 2,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Increments the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(IncrBy(args[0].clone(), 1)),
+    },
+    CommandSpec {
+        name: "decr",
+        arity: 2,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Decrements the integer value of a key by one. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(IncrBy(args[0].clone(), -1)),
+    },
+    CommandSpec {
+        name: "incrby",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Increments the integer value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(IncrBy(args[0].clone(), parse_integer(&args[1])?)),
+    },
+    CommandSpec {
+        name: "decrby",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Decrements a number from the integer value of a key. Uses 0 as initial value if the key doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let decrement: i64 = parse_integer(&args[1])?;
+            let increment = decrement
+                .checked_neg()
+                .ok_or_else(|| RedisError::Err("decrement would overflow".to_string()))?;
+            Ok(IncrBy(args[0].clone(), increment))
+        },
+    },
+    CommandSpec {
+        name: "incrbyfloat",
+        arity: 3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@string", "@fast"],
+        group: "string",
+        summary: "Increment the floating point value of a key by a number. Uses 0 as initial value if the key doesn't exist.",
+        since: "2.6.0",
+        subcommands: &[],
+        parse: |args| Ok(IncrByFloat(args[0].clone(), parse_float(&args[1])?)),
+    },
+    CommandSpec {
+        name: "lpush",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@fast"],
+        group: "list",
+        summary: "Prepends one or more elements to a list. Creates the key if it doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(LPush(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "rpush",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@fast"],
+        group: "list",
+        summary: "Appends one or more elements to a list. Creates the key if it doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(RPush(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "lpushx",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@fast"],
+        group: "list",
+        summary: "Prepends one or more elements to a list only when the list exists.",
+        since: "2.2.0",
+        subcommands: &[],
+        parse: |args| Ok(LPushX(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "rpushx",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@fast"],
+        group: "list",
+        summary: "Appends an element to a list only when the list exists.",
+        since: "2.2.0",
+        subcommands: &[],
+        parse: |args| Ok(RPushX(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "lpop",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@fast"],
+        group: "list",
+        summary: "Returns the first elements in a list after removing it. Deletes the list if the last element was popped.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| match args {
+            [key] => Ok(LPop(key.clone(), None)),
+            [key, count] => Ok(LPop(key.clone(), Some(parse_positive(count)?))),
+            _ => Err(RedisError::WrongArity("lpop".to_string())),
+        },
+    },
+    CommandSpec {
+        name: "rpop",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@fast"],
+        group: "list",
+        summary: "Returns and removes the last elements of a list. Deletes the list if the last element was popped.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| match args {
+            [key] => Ok(RPop(key.clone(), None)),
+            [key, count] => Ok(RPop(key.clone(), Some(parse_positive(count)?))),
+            _ => Err(RedisError::WrongArity("rpop".to_string())),
+        },
+    },
+    CommandSpec {
+        name: "lrange",
+        arity: 4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@list", "@slow"],
+        group: "list",
+        summary: "Returns a range of elements from a list.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(LRange(
+                args[0].clone(),
+                parse_integer(&args[1])?,
+                parse_integer(&args[2])?,
+            ))
+        },
+    },
+    CommandSpec {
+        name: "llen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@list", "@fast"],
+        group: "list",
+        summary: "Returns the length of a list.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(LLen(args[0].clone())),
+    },
+    CommandSpec {
+        name: "lindex",
+        arity: 3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@list", "@slow"],
+        group: "list",
+        summary: "Returns an element from a list by its index.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(LIndex(args[0].clone(), parse_integer(&args[1])?)),
+    },
+    CommandSpec {
+        name: "lset",
+        arity: 4,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow"],
+        group: "list",
+        summary: "Sets the value of an element in a list by its index.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(LSet(
+                args[0].clone(),
+                parse_integer(&args[1])?,
+                args[2].clone(),
+            ))
+        },
+    },
+    CommandSpec {
+        name: "linsert",
+        arity: 5,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow"],
+        group: "list",
+        summary: "Inserts an element before or after another element in a list.",
+        since: "2.2.0",
+        subcommands: &[],
+        parse: |args| {
+            let before = match to_keyword(&args[1]).as_str() {
+                "before" => true,
+                "after" => false,
+                _ => return Err(RedisError::Syntax),
+            };
+            Ok(LInsert(LInsertParams {
+                key: args[0].clone(),
+                before,
+                pivot: args[2].clone(),
+                element: args[3].clone(),
+            }))
+        },
+    },
+    CommandSpec {
+        name: "lrem",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow"],
+        group: "list",
+        summary: "Removes elements from a list. Deletes the list if the last element was removed.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(LRem(
+                args[0].clone(),
+                parse_integer(&args[1])?,
+                args[2].clone(),
+            ))
+        },
+    },
+    CommandSpec {
+        name: "ltrim",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow"],
+        group: "list",
+        summary: "Removes elements from both ends a list. Deletes the list if all elements were trimmed.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(LTrim(
+                args[0].clone(),
+                parse_integer(&args[1])?,
+                parse_integer(&args[2])?,
+            ))
+        },
+    },
+    CommandSpec {
+        name: "lpos",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@list", "@slow"],
+        group: "list",
+        summary: "Returns the index of matching elements in a list.",
+        since: "6.0.6",
+        subcommands: &[],
+        parse: |args| Ok(LPos(build_lpos_params(args)?)),
+    },
+    CommandSpec {
+        name: "lmove",
+        arity: 5,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow"],
+        group: "list",
+        summary: "Returns an element after popping it from one list and pushing it to another. Deletes the list if the last element was moved.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(LMove(
+                args[0].clone(),
+                args[1].clone(),
+                parse_list_end(&args[2])?,
+                parse_list_end(&args[3])?,
+            ))
+        },
+    },
+    CommandSpec {
+        name: "rpoplpush",
+        arity: 3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow"],
+        group: "list",
+        summary: "Returns the last element of a list after removing and pushing it to another list. Deletes the list if the last element was popped.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| Ok(LMove(args[0].clone(), args[1].clone(), ListEnd::Right, ListEnd::Left)),
+    },
+    CommandSpec {
+        name: "lmpop",
+        arity: -4,
+        flags: &["write", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@write", "@list", "@slow"],
+        group: "list",
+        summary: "Returns multiple elements from a list after removing them. Deletes the list if the last element was popped.",
+        since: "7.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let (keys, rest) = parse_numkeys(args)?;
+            let (end, count) = parse_mpop_options(rest, parse_list_end)?;
+            Ok(LMPop(keys, end, count))
+        },
+    },
+    CommandSpec {
+        name: "blpop",
+        arity: -3,
+        flags: &["write", "blocking"],
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow", "@blocking"],
+        group: "list",
+        summary: "Removes and returns the first element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let (timeout, keys) = args.split_last().unwrap();
+            Ok(BLPop(keys.to_vec(), parse_timeout(timeout)?))
+        },
+    },
+    CommandSpec {
+        name: "brpop",
+        arity: -3,
+        flags: &["write", "blocking"],
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow", "@blocking"],
+        group: "list",
+        summary: "Removes and returns the last element in a list. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let (timeout, keys) = args.split_last().unwrap();
+            Ok(BRPop(keys.to_vec(), parse_timeout(timeout)?))
+        },
+    },
+    CommandSpec {
+        name: "blmove",
+        arity: 6,
+        flags: &["write", "denyoom", "blocking"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow", "@blocking"],
+        group: "list",
+        summary: "Pops an element from a list, pushes it to another list and returns it. Blocks until an element is available otherwise. Deletes the list if the last element was moved.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(BLMove(
+                args[0].clone(),
+                args[1].clone(),
+                parse_list_end(&args[2])?,
+                parse_list_end(&args[3])?,
+                parse_timeout(&args[4])?,
+            ))
+        },
+    },
+    CommandSpec {
+        name: "brpoplpush",
+        arity: 4,
+        flags: &["write", "denyoom", "blocking"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        acl_categories: &["@write", "@list", "@slow", "@blocking"],
+        group: "list",
+        summary: "Pops an element from a list, pushes it to another list and returns it. Block until an element is available otherwise. Deletes the list if the last element was popped.",
+        since: "2.2.0",
+        subcommands: &[],
+        parse: |args| {
+            let timeout = parse_timeout(&args[2])?;
+            Ok(BLMove(args[0].clone(), args[1].clone(), ListEnd::Right, ListEnd::Left, timeout))
+        },
+    },
+    CommandSpec {
+        name: "blmpop",
+        arity: -5,
+        flags: &["write", "blocking", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@write", "@list", "@slow", "@blocking"],
+        group: "list",
+        summary: "Pops the first element from one of multiple lists. Blocks until an element is available otherwise. Deletes the list if the last element was popped.",
+        since: "7.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let timeout = parse_timeout(&args[0])?;
+            let (keys, rest) = parse_numkeys(&args[1..])?;
+            let (end, count) = parse_mpop_options(rest, parse_list_end)?;
+            Ok(BLMPop(keys, end, count, timeout))
+        },
+    },
+    CommandSpec {
+        name: "hset",
+        arity: -4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Creates or modifies the value of a field in a hash.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HSet(args[0].clone(), parse_pairs(&args[1..], "hset")?)),
+    },
+    CommandSpec {
+        name: "hsetnx",
+        arity: 4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Sets the value of a field in a hash only when the field doesn't exist.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HSetNx(args[0].clone(), args[1].clone(), args[2].clone())),
+    },
+    CommandSpec {
+        name: "hget",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@fast"],
+        group: "hash",
+        summary: "Returns the value of a field in a hash.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HGet(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "hmget",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@fast"],
+        group: "hash",
+        summary: "Returns the values of all fields in a hash.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HMGet(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "hmset",
+        arity: -4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Sets the values of multiple fields.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HMSet(args[0].clone(), parse_pairs(&args[1..], "hmset")?)),
+    },
+    CommandSpec {
+        name: "hdel",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Deletes one or more fields and their values from a hash. Deletes the hash if no fields remain.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HDel(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "hlen",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@fast"],
+        group: "hash",
+        summary: "Returns the number of fields in a hash.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HLen(args[0].clone())),
+    },
+    CommandSpec {
+        name: "hstrlen",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@fast"],
+        group: "hash",
+        summary: "Returns the length of the value of a field.",
+        since: "3.2.0",
+        subcommands: &[],
+        parse: |args| Ok(HStrLen(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "hexists",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@fast"],
+        group: "hash",
+        summary: "Determines whether a field exists in a hash.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HExists(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "hkeys",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@slow"],
+        group: "hash",
+        summary: "Returns all fields in a hash.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HKeys(args[0].clone())),
+    },
+    CommandSpec {
+        name: "hvals",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@slow"],
+        group: "hash",
+        summary: "Returns all values in a hash.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HVals(args[0].clone())),
+    },
+    CommandSpec {
+        name: "hgetall",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@slow"],
+        group: "hash",
+        summary: "Returns all fields and values in a hash.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| Ok(HGetAll(args[0].clone())),
+    },
+    CommandSpec {
+        name: "hincrby",
+        arity: 4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Increments the integer value of a field in a hash by a number. Uses 0 as initial value if the field doesn't exist.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(HIncrBy(args[0].clone(), args[1].clone(), parse_integer(&args[2])?))
+        },
+    },
+    CommandSpec {
+        name: "hincrbyfloat",
+        arity: 4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Increments the floating point value of a field by a number. Uses 0 as initial value if the field doesn't exist.",
+        since: "2.6.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(HIncrByFloat(args[0].clone(), args[1].clone(), parse_float(&args[2])?))
+        },
+    },
+    CommandSpec {
+        name: "hscan",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@slow"],
+        group: "hash",
+        summary: "Iterates over fields and values of a hash.",
+        since: "2.8.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(HScan(args[0].clone(), build_scan_params(&args[1..], &["novalues"])?))
+        },
+    },
+    CommandSpec {
+        name: "hrandfield",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@slow"],
+        group: "hash",
+        summary: "Returns one or more random fields from a hash.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| {
+            let (count, withvalues) = match &args[1..] {
+                [] => (None, false),
+                [count] => {
+                    let count: i64 = parse_integer(count)?;
+                    // The count's magnitude is taken, which the smallest integer has none of
+                    if count == i64::MIN {
+                        return Err(RedisError::Err("value is out of range".to_string()));
+                    }
+                    (Some(count), false)
+                }
+                [count, option] if to_keyword(option) == "withvalues" => {
+                    let count: i64 = parse_integer(count)?;
+                    // Each pick takes two entries of the reply, which mustn't overflow
+                    if count < -(i64::MAX / 2) {
+                        return Err(RedisError::Err("value is out of range".to_string()));
+                    }
+                    (Some(count), true)
+                }
+                _ => return Err(RedisError::Syntax),
+            };
+            Ok(HRandField(args[0].clone(), count, withvalues))
+        },
+    },
+    CommandSpec {
+        name: "hexpire",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Set expiry for hash field using relative time to expire (seconds)",
+        since: "7.4.0",
+        subcommands: &[],
+        parse: |args| {
+            let (params, fields) = build_hexpire_params(args, Expiry::Ex)?;
+            Ok(HExpire(params, fields))
+        },
+    },
+    CommandSpec {
+        name: "hpexpire",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Set expiry for hash field using relative time to expire (milliseconds)",
+        since: "7.4.0",
+        subcommands: &[],
+        parse: |args| {
+            let (params, fields) = build_hexpire_params(args, Expiry::Px)?;
+            Ok(HExpire(params, fields))
+        },
+    },
+    CommandSpec {
+        name: "hexpireat",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Set expiry for hash field using an absolute Unix timestamp (seconds)",
+        since: "7.4.0",
+        subcommands: &[],
+        parse: |args| {
+            let (params, fields) = build_hexpire_params(args, Expiry::ExAt)?;
+            Ok(HExpire(params, fields))
+        },
+    },
+    CommandSpec {
+        name: "hpexpireat",
+        arity: -6,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Set expiry for hash field using an absolute Unix timestamp (milliseconds)",
+        since: "7.4.0",
+        subcommands: &[],
+        parse: |args| {
+            let (params, fields) = build_hexpire_params(args, Expiry::PxAt)?;
+            Ok(HExpire(params, fields))
+        },
+    },
+    CommandSpec {
+        name: "httl",
+        arity: -5,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@fast"],
+        group: "hash",
+        summary: "Returns the TTL in seconds of a hash field.",
+        since: "7.4.0",
+        subcommands: &[],
+        parse: |args| Ok(HTtl(args[0].clone(), parse_fields(&args[1..])?)),
+    },
+    CommandSpec {
+        name: "hpttl",
+        arity: -5,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@hash", "@fast"],
+        group: "hash",
+        summary: "Returns the TTL in milliseconds of a hash field.",
+        since: "7.4.0",
+        subcommands: &[],
+        parse: |args| Ok(HPTtl(args[0].clone(), parse_fields(&args[1..])?)),
+    },
+    CommandSpec {
+        name: "hpersist",
+        arity: -5,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@hash", "@fast"],
+        group: "hash",
+        summary: "Removes the expiration time for each specified field",
+        since: "7.4.0",
+        subcommands: &[],
+        parse: |args| Ok(HPersist(args[0].clone(), parse_fields(&args[1..])?)),
+    },
+    CommandSpec {
+        name: "sadd",
+        arity: -3,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@set", "@fast"],
+        group: "set",
+        summary: "Adds one or more members to a set. Creates the key if it doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SAdd(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "srem",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@set", "@fast"],
+        group: "set",
+        summary: "Removes one or more members from a set. Deletes the set if the last member was removed.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SRem(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "smembers",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@set", "@slow"],
+        group: "set",
+        summary: "Returns all members of a set.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SMembers(args[0].clone())),
+    },
+    CommandSpec {
+        name: "sismember",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@set", "@fast"],
+        group: "set",
+        summary: "Determines whether a member belongs to a set.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SIsMember(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "smismember",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@set", "@fast"],
+        group: "set",
+        summary: "Determines whether multiple members belong to a set.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| Ok(SMIsMember(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "scard",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@set", "@fast"],
+        group: "set",
+        summary: "Returns the number of members in a set.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SCard(args[0].clone())),
+    },
+    CommandSpec {
+        name: "spop",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@set", "@fast"],
+        group: "set",
+        summary: "Returns one or more random members from a set after removing them. Deletes the set if the last member was popped.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| match &args[1..] {
+            [] => Ok(SPop(args[0].clone(), None)),
+            [count] => Ok(SPop(args[0].clone(), Some(parse_positive(count)?))),
+            _ => Err(RedisError::Syntax),
+        },
+    },
+    CommandSpec {
+        name: "srandmember",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@set", "@slow"],
+        group: "set",
+        summary: "Get one or multiple random members from a set",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| match &args[1..] {
+            [] => Ok(SRandMember(args[0].clone(), None)),
+            [count] => {
+                let count: i64 = parse_integer(count)?;
+                // The count's magnitude is taken, which the smallest integer has none of
+                if count == i64::MIN {
+                    return Err(RedisError::Err("value is out of range".to_string()));
+                }
+                Ok(SRandMember(args[0].clone(), Some(count)))
+            }
+            _ => Err(RedisError::Syntax),
+        },
+    },
+    CommandSpec {
+        name: "smove",
+        arity: 4,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        acl_categories: &["@write", "@set", "@fast"],
+        group: "set",
+        summary: "Moves a member from one set to another.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SMove(args[0].clone(), args[1].clone(), args[2].clone())),
+    },
+    CommandSpec {
+        name: "sinter",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@read", "@set", "@slow"],
+        group: "set",
+        summary: "Returns the intersect of multiple sets.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SCombine(SetOp::Inter, args.to_vec())),
+    },
+    CommandSpec {
+        name: "sintercard",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@read", "@set", "@slow"],
+        group: "set",
+        summary: "Returns the number of members of the intersect of multiple sets.",
+        since: "7.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let (keys, rest) = parse_numkeys(args)?;
+            let limit = match rest {
+                [] => 0,
+                [option, limit] if to_keyword(option) == "limit" => {
+                    let limit: i64 = parse_integer(limit)?;
+                    usize::try_from(limit).map_err(|_| {
+                        RedisError::Err("LIMIT can't be negative".to_string())
+                    })?
+                }
+                _ => return Err(RedisError::Syntax),
+            };
+            Ok(SInterCard(keys, limit))
+        },
+    },
+    CommandSpec {
+        name: "sinterstore",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@write", "@set", "@slow"],
+        group: "set",
+        summary: "Stores the intersect of multiple sets in a key.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SCombineStore(SetOp::Inter, args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "sunion",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@read", "@set", "@slow"],
+        group: "set",
+        summary: "Returns the union of multiple sets.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SCombine(SetOp::Union, args.to_vec())),
+    },
+    CommandSpec {
+        name: "sunionstore",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@write", "@set", "@slow"],
+        group: "set",
+        summary: "Stores the union of multiple sets in a key.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SCombineStore(SetOp::Union, args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "sdiff",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@read", "@set", "@slow"],
+        group: "set",
+        summary: "Returns the difference of multiple sets.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SCombine(SetOp::Diff, args.to_vec())),
+    },
+    CommandSpec {
+        name: "sdiffstore",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@write", "@set", "@slow"],
+        group: "set",
+        summary: "Stores the difference of multiple sets in a key.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(SCombineStore(SetOp::Diff, args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "sscan",
+        arity: -3,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@set", "@slow"],
+        group: "set",
+        summary: "Iterates over members of a set.",
+        since: "2.8.0",
+        subcommands: &[],
+        parse: |args| Ok(SScan(args[0].clone(), build_scan_params(&args[1..], &[])?)),
+    },
+    CommandSpec {
+        name: "zadd",
+        arity: -4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Adds one or more members to a sorted set, or updates their scores. Creates the key if it doesn't exist.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZAdd(build_zadd_params(args)?)),
+    },
+    CommandSpec {
+        name: "zrem",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Removes one or more members from a sorted set. Deletes the sorted set if all members were removed.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZRem(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "zscore",
+        arity: 3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Returns the score of a member in a sorted set.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZScore(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "zmscore",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Returns the score of one or more members in a sorted set.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZMScore(args[0].clone(), args[1..].to_vec())),
+    },
+    CommandSpec {
+        name: "zincrby",
+        arity: 4,
+        flags: &["write", "denyoom", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Increments the score of a member in a sorted set.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| {
+            Ok(ZAdd(ZAddParams {
+                key: args[0].clone(),
+                nx: false,
+                xx: false,
+                gt: false,
+                lt: false,
+                ch: false,
+                incr: true,
+                pairs: vec![(parse_float(&args[1])?, args[2].clone())],
+            }))
+        },
+    },
+    CommandSpec {
+        name: "zcard",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Returns the number of members in a sorted set.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZCard(args[0].clone())),
+    },
+    CommandSpec {
+        name: "zrank",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Returns the index of a member in a sorted set ordered by ascending scores.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let withscore = match &args[2..] {
+                [] => false,
+                [option] if to_keyword(option) == "withscore" => true,
+                _ => return Err(RedisError::Syntax),
+            };
+            Ok(ZRank(args[0].clone(), args[1].clone(), withscore))
+        },
+    },
+    CommandSpec {
+        name: "zrevrank",
+        arity: -3,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Returns the index of a member in a sorted set ordered by descending scores.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let withscore = match &args[2..] {
+                [] => false,
+                [option] if to_keyword(option) == "withscore" => true,
+                _ => return Err(RedisError::Syntax),
+            };
+            Ok(ZRevRank(args[0].clone(), args[1].clone(), withscore))
+        },
+    },
+    CommandSpec {
+        name: "zrange",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns members in a sorted set within a range of indexes.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZRange(build_zrange_params(args, None)?)),
+    },
+    CommandSpec {
+        name: "zrangestore",
+        arity: -5,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Stores a range of members from sorted set in a key.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| {
+            let params = build_zrange_params(&args[1..], None)?;
+            if params.withscores {
+                return Err(RedisError::Syntax);
+            }
+            Ok(ZRangeStore(args[0].clone(), params))
+        },
+    },
+    CommandSpec {
+        name: "zrevrange",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns members in a sorted set within a range of indexes in reverse order.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZRange(build_zrange_params(args, Some((RangeKind::Rank, true)))?)),
+    },
+    CommandSpec {
+        name: "zrangebyscore",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns members in a sorted set within a range of scores.",
+        since: "1.0.5",
+        subcommands: &[],
+        parse: |args| Ok(ZRange(build_zrange_params(args, Some((RangeKind::Score, false)))?)),
+    },
+    CommandSpec {
+        name: "zrevrangebyscore",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns members in a sorted set within a range of scores in reverse order.",
+        since: "2.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZRange(build_zrange_params(args, Some((RangeKind::Score, true)))?)),
+    },
+    CommandSpec {
+        name: "zrangebylex",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns members in a sorted set within a lexicographical range.",
+        since: "2.8.9",
+        subcommands: &[],
+        parse: |args| Ok(ZRange(build_zrange_params(args, Some((RangeKind::Lex, false)))?)),
+    },
+    CommandSpec {
+        name: "zrevrangebylex",
+        arity: -4,
+        flags: &["readonly"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns members in a sorted set within a lexicographical range in reverse order.",
+        since: "2.8.9",
+        subcommands: &[],
+        parse: |args| Ok(ZRange(build_zrange_params(args, Some((RangeKind::Lex, true)))?)),
+    },
+    CommandSpec {
+        name: "zcount",
+        arity: 4,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Returns the count of members in a sorted set that have scores within a range.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let by = ZRangeBy::Score(parse_score_bound(&args[1])?, parse_score_bound(&args[2])?);
+            Ok(ZCount(args[0].clone(), by))
+        },
+    },
+    CommandSpec {
+        name: "zlexcount",
+        arity: 4,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@read", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Returns the number of members in a sorted set within a lexicographical range.",
+        since: "2.8.9",
+        subcommands: &[],
+        parse: |args| {
+            let by = ZRangeBy::Lex(parse_lex_bound(&args[1])?, parse_lex_bound(&args[2])?);
+            Ok(ZCount(args[0].clone(), by))
+        },
+    },
+    CommandSpec {
+        name: "zremrangebyrank",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Removes members in a sorted set within a range of indexes. Deletes the sorted set if all members were removed.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let by = ZRangeBy::Rank(parse_integer(&args[1])?, parse_integer(&args[2])?);
+            Ok(ZRemRange(args[0].clone(), by))
+        },
+    },
+    CommandSpec {
+        name: "zremrangebyscore",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Removes members in a sorted set within a range of scores. Deletes the sorted set if all members were removed.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| {
+            let by = ZRangeBy::Score(parse_score_bound(&args[1])?, parse_score_bound(&args[2])?);
+            Ok(ZRemRange(args[0].clone(), by))
+        },
+    },
+    CommandSpec {
+        name: "zremrangebylex",
+        arity: 4,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Removes members in a sorted set within a lexicographical range. Deletes the sorted set if all members were removed.",
+        since: "2.8.9",
+        subcommands: &[],
+        parse: |args| {
+            let by = ZRangeBy::Lex(parse_lex_bound(&args[1])?, parse_lex_bound(&args[2])?);
+            Ok(ZRemRange(args[0].clone(), by))
+        },
+    },
+    CommandSpec {
+        name: "zpopmin",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Returns the lowest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+        since: "5.0.0",
+        subcommands: &[],
+        parse: |args| match &args[1..] {
+            [] => Ok(ZPopMin(args[0].clone(), None)),
+            [count] => Ok(ZPopMin(args[0].clone(), Some(parse_positive(count)?))),
+            _ => Err(RedisError::Syntax),
+        },
+    },
+    CommandSpec {
+        name: "zpopmax",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@fast"],
+        group: "sorted-set",
+        summary: "Returns the highest-scoring members from a sorted set after removing them. Deletes the sorted set if the last member was popped.",
+        since: "5.0.0",
+        subcommands: &[],
+        parse: |args| match &args[1..] {
+            [] => Ok(ZPopMax(args[0].clone(), None)),
+            [count] => Ok(ZPopMax(args[0].clone(), Some(parse_positive(count)?))),
+            _ => Err(RedisError::Syntax),
+        },
+    },
+    CommandSpec {
+        name: "zmpop",
+        arity: -4,
+        flags: &["write", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@write", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns the highest- or lowest-scoring members from one or more sorted sets after removing them. Deletes the sorted set if the last member was popped.",
+        since: "7.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let (keys, rest) = parse_numkeys(args)?;
+            let (max, count) = parse_mpop_options(rest, parse_zset_end)?;
+            Ok(ZMPop(keys, max, count))
+        },
+    },
+    CommandSpec {
+        name: "zunion",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns the union of multiple sorted sets.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZCombine(build_zcombine_params(args, SetOp::Union, "zunion", false)?)),
+    },
+    CommandSpec {
+        name: "zunionstore",
+        arity: -4,
+        flags: &["write", "denyoom", "movablekeys"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Stores the union of multiple sorted sets in a key.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let params = build_zcombine_params(&args[1..], SetOp::Union, "zunionstore", true)?;
+            Ok(ZCombineStore(args[0].clone(), params))
+        },
+    },
+    CommandSpec {
+        name: "zinter",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns the intersect of multiple sorted sets.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZCombine(build_zcombine_params(args, SetOp::Inter, "zinter", false)?)),
+    },
+    CommandSpec {
+        name: "zinterstore",
+        arity: -4,
+        flags: &["write", "denyoom", "movablekeys"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Stores the intersect of multiple sorted sets in a key.",
+        since: "2.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let params = build_zcombine_params(&args[1..], SetOp::Inter, "zinterstore", true)?;
+            Ok(ZCombineStore(args[0].clone(), params))
+        },
+    },
+    CommandSpec {
+        name: "zintercard",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns the number of members of the intersect of multiple sorted sets.",
+        since: "7.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let (keys, rest) = parse_zset_numkeys(args, "zintercard")?;
+            let limit = match rest {
+                [] => 0,
+                [option, limit] if to_keyword(option) == "limit" => {
+                    let limit: i64 = parse_integer(limit)?;
+                    usize::try_from(limit)
+                        .map_err(|_| RedisError::Err("LIMIT can't be negative".to_string()))?
+                }
+                _ => return Err(RedisError::Syntax),
+            };
+            Ok(ZInterCard(keys, limit))
+        },
+    },
+    CommandSpec {
+        name: "zdiff",
+        arity: -3,
+        flags: &["readonly", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@read", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Returns the difference between multiple sorted sets.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| Ok(ZCombine(build_zcombine_params(args, SetOp::Diff, "zdiff", false)?)),
+    },
+    CommandSpec {
+        name: "zdiffstore",
+        arity: -4,
+        flags: &["write", "denyoom", "movablekeys"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@slow"],
+        group: "sorted-set",
+        summary: "Stores the difference of multiple sorted sets in a key.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| {
+            let params = build_zcombine_params(&args[1..], SetOp::Diff, "zdiffstore", true)?;
+            Ok(ZCombineStore(args[0].clone(), params))
+        },
+    },
+    CommandSpec {
+        name: "bzpopmin",
+        arity: -3,
+        flags: &["write", "blocking", "fast"],
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@fast", "@blocking"],
+        group: "sorted-set",
+        summary: "Removes and returns the member with the lowest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+        since: "5.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let (timeout, keys) = args.split_last().unwrap();
+            Ok(BZPopMin(keys.to_vec(), parse_timeout(timeout)?))
+        },
+    },
+    CommandSpec {
+        name: "bzpopmax",
+        arity: -3,
+        flags: &["write", "blocking", "fast"],
+        first_key: 1,
+        last_key: -2,
+        step: 1,
+        acl_categories: &["@write", "@sortedset", "@fast", "@blocking"],
+        group: "sorted-set",
+        summary: "Removes and returns the member with the highest score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+        since: "5.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let (timeout, keys) = args.split_last().unwrap();
+            Ok(BZPopMax(keys.to_vec(), parse_timeout(timeout)?))
+        },
+    },
+    CommandSpec {
+        name: "bzmpop",
+        arity: -5,
+        flags: &["write", "blocking", "movablekeys"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@write", "@sortedset", "@slow", "@blocking"],
+        group: "sorted-set",
+        summary: "Removes and returns a member by score from one or more sorted sets. Blocks until a member is available otherwise. Deletes the sorted set if the last element was popped.",
+        since: "7.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let timeout = parse_timeout(&args[0])?;
+            let (keys, rest) = parse_numkeys(&args[1..])?;
+            let (max, count) = parse_mpop_options(rest, parse_zset_end)?;
+            Ok(BZMPop(keys, max, count, timeout))
+        },
+    },
+    CommandSpec {
+        name: "del",
+        arity: -2,
+        flags: &["write"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@slow"],
+        group: "generic",
+        summary: "Deletes one or more keys.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Del(args.to_vec())),
+    },
+    CommandSpec {
+        name: "unlink",
+        arity: -2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@fast"],
+        group: "generic",
+        summary: "Asynchronously deletes one or more keys.",
+        since: "4.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Unlink(args.to_vec())),
+    },
+    CommandSpec {
+        name: "exists",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@keyspace", "@read", "@fast"],
+        group: "generic",
+        summary: "Determines whether one or more keys exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Exists(args.to_vec())),
+    },
+    CommandSpec {
+        name: "type",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@read", "@fast"],
+        group: "generic",
+        summary: "Determines the type of value stored at a key.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Type(args[0].clone())),
+    },
+    CommandSpec {
+        name: "rename",
+        arity: 3,
+        flags: &["write"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@slow"],
+        group: "generic",
+        summary: "Renames a key and overwrites the destination.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Rename(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "renamenx",
+        arity: 3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@fast"],
+        group: "generic",
+        summary: "Renames a key only when the target key name doesn't exist.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(RenameNx(args[0].clone(), args[1].clone())),
+    },
+    CommandSpec {
+        name: "copy",
+        arity: -3,
+        flags: &["write", "denyoom"],
+        first_key: 1,
+        last_key: 2,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@slow"],
+        group: "generic",
+        summary: "Copies the value of a key to a new key.",
+        since: "6.2.0",
+        subcommands: &[],
+        parse: |args| Ok(Copy(build_copy_params(args)?)),
+    },
+    CommandSpec {
+        name: "touch",
+        arity: -2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: -1,
+        step: 1,
+        acl_categories: &["@keyspace", "@read", "@fast"],
+        group: "generic",
+        summary: "Returns the number of existing keys out of those specified after updating the time they were last accessed.",
+        since: "3.2.1",
+        subcommands: &[],
+        parse: |args| Ok(Touch(args.to_vec())),
+    },
+    CommandSpec {
+        name: "expire",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@fast"],
+        group: "generic",
+        summary: "Sets the expiration time of a key in seconds.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Expire(build_expire_params(args, Expiry::Ex)?)),
+    },
+    CommandSpec {
+        name: "pexpire",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@fast"],
+        group: "generic",
+        summary: "Sets the expiration time of a key in milliseconds.",
+        since: "2.6.0",
+        subcommands: &[],
+        parse: |args| Ok(Expire(build_expire_params(args, Expiry::Px)?)),
+    },
+    CommandSpec {
+        name: "expireat",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@fast"],
+        group: "generic",
+        summary: "Sets the expiration time of a key to a Unix timestamp.",
+        since: "1.2.0",
+        subcommands: &[],
+        parse: |args| Ok(Expire(build_expire_params(args, Expiry::ExAt)?)),
+    },
+    CommandSpec {
+        name: "pexpireat",
+        arity: -3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@fast"],
+        group: "generic",
+        summary: "Sets the expiration time of a key to a Unix milliseconds timestamp.",
+        since: "2.6.0",
+        subcommands: &[],
+        parse: |args| Ok(Expire(build_expire_params(args, Expiry::PxAt)?)),
+    },
+    CommandSpec {
+        name: "ttl",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@read", "@fast"],
+        group: "generic",
+        summary: "Returns the expiration time in seconds of a key.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Ttl(args[0].clone())),
+    },
+    CommandSpec {
+        name: "pttl",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@read", "@fast"],
+        group: "generic",
+        summary: "Returns the expiration time in milliseconds of a key.",
+        since: "2.6.0",
+        subcommands: &[],
+        parse: |args| Ok(PTtl(args[0].clone())),
+    },
+    CommandSpec {
+        name: "expiretime",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@read", "@fast"],
+        group: "generic",
+        summary: "Returns the expiration time of a key as a Unix timestamp.",
+        since: "7.0.0",
+        subcommands: &[],
+        parse: |args| Ok(ExpireTime(args[0].clone())),
+    },
+    CommandSpec {
+        name: "pexpiretime",
+        arity: 2,
+        flags: &["readonly", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@read", "@fast"],
+        group: "generic",
+        summary: "Returns the expiration time of a key as a Unix milliseconds timestamp.",
+        since: "7.0.0",
+        subcommands: &[],
+        parse: |args| Ok(PExpireTime(args[0].clone())),
+    },
+    CommandSpec {
+        name: "persist",
+        arity: 2,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@fast"],
+        group: "generic",
+        summary: "Removes the expiration time of a key.",
+        since: "2.2.0",
+        subcommands: &[],
+        parse: |args| Ok(Persist(args[0].clone())),
+    },
+    CommandSpec {
+        name: "keys",
+        arity: 2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@keyspace", "@read", "@slow", "@dangerous"],
+        group: "generic",
+        summary: "Returns all key names that match a pattern.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Keys(args[0].clone())),
+    },
+    CommandSpec {
+        name: "scan",
+        arity: -2,
+        flags: &["readonly"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@keyspace", "@read", "@slow"],
+        group: "generic",
+        summary: "Iterates over the key names in the database.",
+        since: "2.8.0",
+        subcommands: &[],
+        parse: |args| Ok(Scan(build_scan_params(args, &["type"])?)),
+    },
+    CommandSpec {
+        name: "select",
+        arity: 2,
+        flags: &["loading", "stale", "fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@fast", "@connection"],
+        group: "connection",
+        summary: "Changes the selected database.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Select(parse_integer(&args[0])?)),
+    },
+    CommandSpec {
+        name: "move",
+        arity: 3,
+        flags: &["write", "fast"],
+        first_key: 1,
+        last_key: 1,
+        step: 1,
+        acl_categories: &["@keyspace", "@write", "@fast"],
+        group: "generic",
+        summary: "Moves a key to another database.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Move(args[0].clone(), parse_integer(&args[1])?)),
+    },
+    CommandSpec {
+        name: "swapdb",
+        arity: 3,
+        flags: &["write", "fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@keyspace", "@write", "@fast", "@dangerous"],
+        group: "server",
+        summary: "Swaps two Redis databases.",
+        since: "4.0.0",
+        subcommands: &[],
+        parse: |args| {
+            let first = parse_integer(&args[0])
+                .map_err(|_| RedisError::Err("invalid first DB index".to_string()))?;
+            let second = parse_integer(&args[1])
+                .map_err(|_| RedisError::Err("invalid second DB index".to_string()))?;
+            Ok(SwapDb(first, second))
+        },
+    },
+    CommandSpec {
+        name: "flushdb",
+        arity: -1,
+        flags: &["write"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@keyspace", "@write", "@slow", "@dangerous"],
+        group: "server",
+        summary: "Remove all keys from the current database.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| parse_flush_mode(args).map(|_| FlushDb),
+    },
+    CommandSpec {
+        name: "flushall",
+        arity: -1,
+        flags: &["write"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@keyspace", "@write", "@slow", "@dangerous"],
+        group: "server",
+        summary: "Removes all keys from all databases.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| parse_flush_mode(args).map(|_| FlushAll),
+    },
+    CommandSpec {
+        name: "dbsize",
+        arity: 1,
+        flags: &["readonly", "fast"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@keyspace", "@read", "@fast"],
+        group: "server",
+        summary: "Returns the number of keys in the database.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |_| Ok(DbSize),
+    },
+    CommandSpec {
+        name: "info",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@slow", "@dangerous"],
+        group: "server",
+        summary: "Returns information and statistics about the server.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Info(args.first().map(to_keyword).unwrap_or_default())),
+    },
+    CommandSpec {
+        name: "save",
+        arity: 1,
+        flags: &["admin", "noscript", "no_async_loading", "no_multi"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@admin", "@slow", "@dangerous"],
+        group: "server",
+        summary: "Synchronously saves the database(s) to disk.",
+        since: "1.0.0",
+        subcommands: &[],
+        parse: |_| Ok(Save),
+    },
+    CommandSpec {
+        name: "config",
+        arity: -2,
+        flags: &[],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@slow"],
+        group: "server",
+        summary: "A container for server configuration commands.",
+        since: "2.0.0",
+        subcommands: &[
+        CommandSpec {
+            name: "get",
+            arity: -3,
+            flags: &["admin", "noscript", "loading", "stale"],
+            first_key: 0,
+            last_key: 0,
+            step: 0,
+            acl_categories: &["@admin", "@slow", "@dangerous"],
+            group: "server",
+            summary: "Returns the effective values of configuration parameters.",
+            since: "2.0.0",
+            subcommands: &[],
+            parse: |args| Ok(Config(to_keyword(&args[0]))),
+        },
+        CommandSpec {
+            name: "set",
+            arity: -4,
+            flags: &["admin", "noscript", "loading", "stale"],
+            first_key: 0,
+            last_key: 0,
+            step: 0,
+            acl_categories: &["@admin", "@slow", "@dangerous"],
+            group: "server",
+            summary: "Sets configuration parameters in-flight.",
+            since: "2.0.0",
+            subcommands: &[],
+            parse: |args| {
+                if !args.len().is_multiple_of(2) {
+                    return Err(RedisError::Err(format!(
+                        "Unknown option or number of arguments for CONFIG SET - '{}'",
+                        to_string(&args[args.len() - 1])
+                    )));
+                }
+                let params = args
+                    .chunks(2)
+                    .map(|pair| (to_keyword(&pair[0]), pair[1].clone()))
+                    .collect();
+                Ok(ConfigSet(params))
+            },
+        }],
+        parse: |_| Err(RedisError::WrongArity("config".to_string())),
+    },
+    CommandSpec {
+        name: "wait",
+        arity: 3,
+        flags: &["noscript"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@slow", "@connection"],
+        group: "generic",
+        summary: "Blocks until the asynchronous replication of all preceding write commands sent by the connection is completed.",
+        since: "3.0.0",
+        subcommands: &[],
+        parse: |args| Ok(Wait(parse_integer(&args[0])?, parse_integer(&args[1])?)),
+    },
+    CommandSpec {
+        name: "replconf",
+        arity: -1,
+        flags: &["admin", "noscript", "loading", "stale", "allow_busy"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@admin", "@slow", "@dangerous"],
+        group: "server",
+        summary: "An internal command for configuring the replication stream.",
+        since: "3.0.0",
+        subcommands: &[],
+        parse: |args| match args {
+            [key, value, ..] => Ok(ReplConf(to_string(key), to_string(value))),
+            _ => Err(RedisError::Syntax),
+        },
+    },
+    CommandSpec {
+        name: "psync",
+        arity: -3,
+        flags: &["admin", "noscript", "no_async_loading", "no_multi"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@admin", "@slow", "@dangerous"],
+        group: "server",
+        summary: "An internal command used in replication.",
+        since: "2.8.0",
+        subcommands: &[],
+        parse: |args| Ok(PSync(to_string(&args[0]), to_string(&args[1]))),
+    },
+    CommandSpec {
+        name: "command",
+        arity: -1,
+        flags: &["loading", "stale"],
+        first_key: 0,
+        last_key: 0,
+        step: 0,
+        acl_categories: &["@slow", "@connection"],
+        group: "server",
+        summary: "Returns detailed information about all commands.",
+        since: "2.8.13",
+        subcommands: &[
+            CommandSpec {
+                name: "count",
+                arity: 2,
+                flags: &["loading", "stale"],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                acl_categories: &["@slow", "@connection"],
+                group: "server",
+                summary: "Returns a count of commands.",
+                since: "2.8.13",
+                subcommands: &[],
+                parse: |_| Ok(CommandCount),
+            },
+            CommandSpec {
+                name: "info",
+                arity: -2,
+                flags: &["loading", "stale"],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                acl_categories: &["@slow", "@connection"],
+                group: "server",
+                summary: "Returns information about one, multiple or all commands.",
+                since: "2.8.13",
+                subcommands: &[],
+                parse: |args| Ok(CommandInfo(args.to_vec())),
+            },
+            CommandSpec {
+                name: "docs",
+                arity: -2,
+                flags: &["loading", "stale"],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                acl_categories: &["@slow", "@connection"],
+                group: "server",
+                summary: "Returns documentary information about one, multiple or all commands.",
+                since: "7.0.0",
+                subcommands: &[],
+                parse: |args| Ok(CommandDocs(args.to_vec())),
+            },
+            CommandSpec {
+                name: "getkeys",
+                arity: -3,
+                flags: &["loading", "stale"],
+                first_key: 0,
+                last_key: 0,
+                step: 0,
+                acl_categories: &["@slow", "@connection"],
+                group: "server",
+                summary: "Extracts the key names from an arbitrary command.",
+                since: "2.8.13",
+                subcommands: &[],
+                parse: |args| Ok(CommandGetKeys(args.to_vec())),
+            },
+        ],
+        parse: |_| Ok(CommandList),
+    },
+];
+
+pub(crate) fn parse_integer<T: FromStr>(arg: &Bytes) -> Result<T, RedisError> {
+    to_string(arg).parse().map_err(|_| RedisError::NotInteger)
+}
+
+/// Parses a float the way Redis does for INCRBYFLOAT and friends, which accept infinities but
+/// not NaN.
+pub(crate) fn parse_float(arg: &Bytes) -> Result<f64, RedisError> {
+    to_string(arg)
+        .parse()
+        .ok()
+        .filter(|value: &f64| !value.is_nan())
+        .ok_or(RedisError::NotFloat)
+}
+
+/// Parses the timeout of a blocking command, given in seconds. None stands for 0, which blocks
+/// forever.
+fn parse_timeout(arg: &Bytes) -> Result<Option<Duration>, RedisError> {
+    let seconds: f64 = to_string(arg)
+        .parse()
+        .ok()
+        .filter(|seconds: &f64| seconds.is_finite())
+        .ok_or_else(|| RedisError::Err("timeout is not a float or out of range".to_string()))?;
+    if seconds < 0.0 {
+        return Err(RedisError::Err("timeout is negative".to_string()));
+    }
+
+    let timeout = Duration::try_from_secs_f64(seconds)
+        .map_err(|_| RedisError::Err("timeout is out of range".to_string()))?;
+    // Redis only keeps the timeout to the millisecond, rounding up so that only 0 blocks for good.
+    // Whole nanoseconds are rounded rather than the float, which may land just past a millisecond
+    let millis = timeout.as_nanos().div_ceil(1_000_000);
+    let timeout = u64::try_from(millis)
+        .map(Duration::from_millis)
+        .map_err(|_| RedisError::Err("timeout is out of range".to_string()))?;
+    Ok((!timeout.is_zero()).then_some(timeout))
+}
+
+fn build_set_params(args: &[Bytes]) -> Result<SetParams, RedisError> {
+    let mut params = SetParams {
+        key: args[0].clone(),
+        value: args[1].clone(),
+        expiry: None,
+        condition: None,
+        get: false,
+    };
+
+    let mut i = 2;
+    while i < args.len() {
+        let option = to_keyword(&args[i]);
+        match option.as_str() {
+            "nx" | "xx" if params.condition.is_none() => {
+                params.condition = Some(if option == "nx" {
+                    SetCondition::Nx
+                } else {
+                    SetCondition::Xx
+                });
+            }
+            "get" => params.get = true,
+            "keepttl" if params.expiry.is_none() => params.expiry = Some(Expiry::KeepTtl),
+            "ex" | "px" | "exat" | "pxat" if params.expiry.is_none() && i + 1 < args.len() => {
+                i += 1;
+                params.expiry = Some(parse_expiry(&option, &args[i], "set")?);
+            }
+            _ => return Err(RedisError::Syntax),
+        }
+        i += 1;
+    }
+
+    Ok(params)
+}
+
+fn build_expire_params(
+    args: &[Bytes],
+    expiry: fn(i64) -> Expiry,
+) -> Result<ExpireParams, RedisError> {
+    let mut params = ExpireParams {
+        key: args[0].clone(),
+        expiry: expiry(parse_integer(&args[1])?),
+        nx: false,
+        xx: false,
+        gt: false,
+        lt: false,
+    };
+
+    for option in &args[2..] {
+        match to_keyword(option).as_str() {
+            "nx" => params.nx = true,
+            "xx" => params.xx = true,
+            "gt" => params.gt = true,
+            "lt" => params.lt = true,
+            _ => {
+                return Err(RedisError::Err(format!(
+                    "Unsupported option {}",
+                    to_string(option)
+                )))
+            }
+        }
+    }
+
+    if params.nx && (params.xx || params.gt || params.lt) {
+        return Err(RedisError::Err(
+            "NX and XX, GT or LT options at the same time are not compatible".to_string(),
+        ));
+    }
+    if params.gt && params.lt {
+        return Err(RedisError::Err(
+            "GT and LT options at the same time are not compatible".to_string(),
+        ));
+    }
+
+    Ok(params)
+}
+
+fn build_copy_params(args: &[Bytes]) -> Result<CopyParams, RedisError> {
+    let mut params = CopyParams {
+        source: args[0].clone(),
+        destination: args[1].clone(),
+        db: None,
+        replace: false,
+    };
+
+    let mut i = 2;
+    while i < args.len() {
+        match to_keyword(&args[i]).as_str() {
+            "replace" => params.replace = true,
+            "db" if i + 1 < args.len() => {
+                i += 1;
+                params.db = Some(parse_integer(&args[i])?);
+            }
+            _ => return Err(RedisError::Syntax),
+        }
+        i += 1;
+    }
+
+    Ok(params)
+}
+
+// Flushing is always synchronous, so ASYNC and SYNC are accepted but make no difference
+fn parse_flush_mode(args: &[Bytes]) -> Result<(), RedisError> {
+    match args {
+        [] => Ok(()),
+        [mode] if matches!(to_keyword(mode).as_str(), "async" | "sync") => Ok(()),
+        _ => Err(RedisError::Syntax),
+    }
+}
+
+/// Parses the arguments of HEXPIRE and friends: a key and time like EXPIRE takes, then at
+/// most one condition and the fields.
+fn build_hexpire_params(
+    args: &[Bytes],
+    expiry: fn(i64) -> Expiry,
+) -> Result<(ExpireParams, Vec<Bytes>), RedisError> {
+    let time: i64 = parse_integer(&args[1])?;
+    if time < 0 {
+        return Err(RedisError::Err(
+            "invalid expire time, must be >= 0".to_string(),
+        ));
+    }
+
+    let options = match to_keyword(&args[2]).as_str() {
+        "fields" => 0,
+        _ => 1,
+    };
+    let fields = parse_fields(&args[2 + options..])?;
+    let params = build_expire_params(&args[..2 + options], expiry)?;
+    Ok((params, fields))
+}
+
+/// Parses the `FIELDS numfields field [field ...]` closing the hash field expiry commands.
+fn parse_fields(args: &[Bytes]) -> Result<Vec<Bytes>, RedisError> {
+    if args
+        .first()
+        .is_none_or(|keyword| to_keyword(keyword) != "fields")
+    {
+        return Err(RedisError::Err(
+            "Mandatory argument FIELDS is missing or not at the right position".to_string(),
+        ));
+    }
+
+    let count = args
+        .get(1)
+        .and_then(|count| parse_integer::<i64>(count).ok())
+        .filter(|count| *count > 0)
+        .ok_or_else(|| {
+            RedisError::Err("Parameter `numFields` should be greater than 0".to_string())
+        })?;
+    if count as usize != args.len() - 2 {
+        return Err(RedisError::Err(
+            "The `numfields` parameter must match the number of arguments".to_string(),
+        ));
+    }
+    Ok(args[2..].to_vec())
+}
+
+/// Parses a cursor followed by the options shared by the SCAN family, along with the `extra`
+/// ones only some of its commands take.
+fn build_scan_params(args: &[Bytes], extra: &[&str]) -> Result<ScanParams, RedisError> {
+    let mut params = ScanParams {
+        cursor: to_string(&args[0])
+            .parse()
+            .map_err(|_| RedisError::Err("invalid cursor".to_string()))?,
+        pattern: None,
+        count: 10,
+        type_name: None,
+        novalues: false,
+    };
+
+    let mut i = 1;
+    while i < args.len() {
+        let option = to_keyword(&args[i]);
+        if !matches!(option.as_str(), "match" | "count") && !extra.contains(&option.as_str()) {
+            return Err(RedisError::Syntax);
+        }
+        // The only option without a value
+        if option == "novalues" {
+            params.novalues = true;
+            i += 1;
+            continue;
+        }
+
+        let Some(value) = args.get(i + 1) else {
+            return Err(RedisError::Syntax);
+        };
+        match option.as_str() {
+            "match" => params.pattern = Some(value.clone()),
+            "count" => {
+                let count: i64 = parse_integer(value)?;
+                if count < 1 {
+                    return Err(RedisError::Syntax);
+                }
+                params.count = count as usize;
+            }
+            "type" => params.type_name = Some(to_keyword(value)),
+            _ => return Err(RedisError::Syntax),
+        }
+        i += 2;
+    }
+
+    Ok(params)
+}
+
+/// Parses the expiry option of GETEX, of which there may be at most one.
+fn parse_getex_expiry(args: &[Bytes]) -> Result<Option<Expiry>, RedisError> {
+    match args {
+        [] => Ok(None),
+        [option] if to_keyword(option) == "persist" => Ok(Some(Expiry::Persist)),
+        [option, value] => {
+            let option = to_keyword(option);
+            match option.as_str() {
+                "ex" | "px" | "exat" | "pxat" => Ok(Some(parse_expiry(&option, value, "getex")?)),
+                _ => Err(RedisError::Syntax),
+            }
+        }
+        _ => Err(RedisError::Syntax),
+    }
+}
+
+/// Pairs up the alternating keys and values given to MSET and MSETNX.
+fn parse_pairs(args: &[Bytes], command: &str) -> Result<Vec<(Bytes, Bytes)>, RedisError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(RedisError::WrongArity(command.to_string()));
+    }
+    Ok(args
+        .chunks(2)
+        .map(|pair| (pair[0].clone(), pair[1].clone()))
+        .collect())
+}
+
+/// Parses a count that may be zero but not negative, such as the one LPOP takes.
+fn parse_positive(arg: &Bytes) -> Result<usize, RedisError> {
+    let count: i64 = parse_integer(arg)?;
+    usize::try_from(count)
+        .map_err(|_| RedisError::Err("value is out of range, must be positive".to_string()))
+}
+
+fn parse_list_end(arg: &Bytes) -> Result<ListEnd, RedisError> {
+    match to_keyword(arg).as_str() {
+        "left" => Ok(ListEnd::Left),
+        "right" => Ok(ListEnd::Right),
+        _ => Err(RedisError::Syntax),
+    }
+}
+
+/// Parses the MIN or MAX of ZMPOP, which is true when it asks for the highest scores.
+fn parse_zset_end(arg: &Bytes) -> Result<bool, RedisError> {
+    match to_keyword(arg).as_str() {
+        "min" => Ok(false),
+        "max" => Ok(true),
+        _ => Err(RedisError::Syntax),
+    }
+}
+
+/// Splits off the keys announced by the `numkeys` argument leading `args`, returning them
+/// along with the arguments that follow.
+fn parse_numkeys(args: &[Bytes]) -> Result<(Vec<Bytes>, &[Bytes]), RedisError> {
+    let numkeys: i64 = parse_integer(&args[0])?;
+    if numkeys <= 0 {
+        return Err(RedisError::Err(
+            "numkeys should be greater than 0".to_string(),
+        ));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys >= args.len() {
+        return Err(RedisError::Err(
+            "Number of keys can't be greater than number of args".to_string(),
+        ));
+    }
+    Ok((args[1..=numkeys].to_vec(), &args[numkeys + 1..]))
+}
+
+/// Parses the end to pop from and the optional COUNT of LMPOP and friends.
+fn parse_mpop_options<T>(
+    args: &[Bytes],
+    parse_end: fn(&Bytes) -> Result<T, RedisError>,
+) -> Result<(T, usize), RedisError> {
+    let Some((end, options)) = args.split_first() else {
+        return Err(RedisError::Syntax);
+    };
+    let end = parse_end(end)?;
+
+    match options {
+        [] => Ok((end, 1)),
+        [option, count] if to_keyword(option) == "count" => {
+            let count: i64 = parse_integer(count)?;
+            if count <= 0 {
+                return Err(RedisError::Err(
+                    "count should be greater than 0".to_string(),
+                ));
+            }
+            Ok((end, count as usize))
+        }
+        _ => Err(RedisError::Syntax),
+    }
+}
+
+fn build_zadd_params(args: &[Bytes]) -> Result<ZAddParams, RedisError> {
+    let mut params = ZAddParams {
+        key: args[0].clone(),
+        nx: false,
+        xx: false,
+        gt: false,
+        lt: false,
+        ch: false,
+        incr: false,
+        pairs: vec![],
+    };
+
+    // Options run up to the first score
+    let mut i = 1;
+    while i < args.len() {
+        match to_keyword(&args[i]).as_str() {
+            "nx" => params.nx = true,
+            "xx" => params.xx = true,
+            "gt" => params.gt = true,
+            "lt" => params.lt = true,
+            "ch" => params.ch = true,
+            "incr" => params.incr = true,
+            _ => break,
+        }
+        i += 1;
+    }
+
+    let pairs = &args[i..];
+    if pairs.is_empty() || !pairs.len().is_multiple_of(2) {
+        return Err(RedisError::Syntax);
+    }
+    if params.nx && params.xx {
+        return Err(RedisError::Err(
+            "XX and NX options at the same time are not compatible".to_string(),
+        ));
+    }
+    if [params.nx, params.gt, params.lt]
+        .iter()
+        .filter(|set| **set)
+        .count()
+        > 1
+    {
+        return Err(RedisError::Err(
+            "GT, LT, and/or NX options at the same time are not compatible".to_string(),
+        ));
+    }
+    if params.incr && pairs.len() > 2 {
+        return Err(RedisError::Err(
+            "INCR option supports a single increment-element pair".to_string(),
+        ));
+    }
+
+    params.pairs = pairs
+        .chunks(2)
+        .map(|pair| Ok((parse_float(&pair[0])?, pair[1].clone())))
+        .collect::<Result<_, RedisError>>()?;
+    Ok(params)
+}
+
+/// Parses ZUNION, ZINTER, ZDIFF and their STORE forms, starting from `numkeys`. ZDIFF takes no
+/// WEIGHTS or AGGREGATE, and the STORE forms no WITHSCORES.
+fn build_zcombine_params(
+    args: &[Bytes],
+    op: SetOp,
+    command: &str,
+    store: bool,
+) -> Result<ZCombineParams, RedisError> {
+    let (keys, options) = parse_zset_numkeys(args, command)?;
+    let mut params = ZCombineParams {
+        op,
+        weights: None,
+        aggregate: Aggregate::Sum,
+        withscores: false,
+        keys,
+    };
+
+    let mut i = 0;
+    while i < options.len() {
+        let remaining = options.len() - i - 1;
+        match to_keyword(&options[i]).as_str() {
+            "weights" if op != SetOp::Diff && remaining >= params.keys.len() => {
+                let weights = &options[i + 1..=i + params.keys.len()];
+                params.weights = Some(
+                    weights
+                        .iter()
+                        .map(|weight| {
+                            parse_float(weight).map_err(|_| {
+                                RedisError::Err("weight value is not a float".to_string())
+                            })
+                        })
+                        .collect::<Result<_, _>>()?,
+                );
+                i += weights.len();
+            }
+            "aggregate" if op != SetOp::Diff && remaining >= 1 => {
+                params.aggregate = match to_keyword(&options[i + 1]).as_str() {
+                    "sum" => Aggregate::Sum,
+                    "min" => Aggregate::Min,
+                    "max" => Aggregate::Max,
+                    _ => return Err(RedisError::Syntax),
+                };
+                i += 1;
+            }
+            "withscores" if !store => params.withscores = true,
+            _ => return Err(RedisError::Syntax),
+        }
+        i += 1;
+    }
+    Ok(params)
+}
+
+/// Like `parse_numkeys`, with the errors the sorted set commands give.
+fn parse_zset_numkeys<'a>(
+    args: &'a [Bytes],
+    command: &str,
+) -> Result<(Vec<Bytes>, &'a [Bytes]), RedisError> {
+    let numkeys: i64 = parse_integer(&args[0])?;
+    if numkeys < 1 {
+        return Err(RedisError::Err(format!(
+            "at least 1 input key is needed for '{}' command",
+            command
+        )));
+    }
+    let numkeys = numkeys as usize;
+    if numkeys >= args.len() {
+        return Err(RedisError::Syntax);
+    }
+    Ok((args[1..=numkeys].to_vec(), &args[numkeys + 1..]))
+}
+
+/// What ZRANGE picks members by.
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum RangeKind {
+    Rank,
+    Score,
+    Lex,
+}
+
+/// Parses ZRANGE and its older forms such as ZREVRANGEBYSCORE, which imply the kind of range
+/// and its direction rather than taking them as options.
+fn build_zrange_params(
+    args: &[Bytes],
+    legacy: Option<(RangeKind, bool)>,
+) -> Result<ZRangeParams, RedisError> {
+    let (mut kind, mut rev) = legacy.unwrap_or((RangeKind::Rank, false));
+    let mut limit = None;
+    let mut withscores = false;
+
+    let mut i = 3;
+    while i < args.len() {
+        match to_keyword(&args[i]).as_str() {
+            "byscore" if legacy.is_none() => kind = RangeKind::Score,
+            "bylex" if legacy.is_none() => kind = RangeKind::Lex,
+            "rev" if legacy.is_none() => rev = true,
+            "withscores" => withscores = true,
+            "limit" if i + 2 < args.len() => {
+                limit = Some((parse_integer(&args[i + 1])?, parse_integer(&args[i + 2])?));
+                i += 2;
+            }
+            _ => return Err(RedisError::Syntax),
+        }
+        i += 1;
+    }
+
+    if limit.is_some() && kind == RangeKind::Rank {
+        return Err(RedisError::Err(
+            "syntax error, LIMIT is only supported in combination with either BYSCORE or BYLEX"
+                .to_string(),
+        ));
+    }
+    if withscores && kind == RangeKind::Lex {
+        return Err(RedisError::Err(
+            "syntax error, WITHSCORES not supported in combination with BYLEX".to_string(),
+        ));
+    }
+
+    // Reversed score and lex ranges are given from the upper end down
+    let (start, stop) = (&args[1], &args[2]);
+    let (min, max) = if rev { (stop, start) } else { (start, stop) };
+    let by = match kind {
+        RangeKind::Rank => ZRangeBy::Rank(parse_integer(start)?, parse_integer(stop)?),
+        RangeKind::Score => ZRangeBy::Score(parse_score_bound(min)?, parse_score_bound(max)?),
+        RangeKind::Lex => ZRangeBy::Lex(parse_lex_bound(min)?, parse_lex_bound(max)?),
+    };
+    Ok(ZRangeParams {
+        key: args[0].clone(),
+        by,
+        rev,
+        limit,
+        withscores,
+    })
+}
+
+/// Parses a score range bound, which a leading `(` makes exclusive.
+fn parse_score_bound(arg: &Bytes) -> Result<ScoreBound, RedisError> {
+    let (exclusive, score) = match arg.strip_prefix(b"(") {
+        Some(score) => (true, Bytes::copy_from_slice(score)),
+        None => (false, arg.clone()),
+    };
+    let score = parse_float(&score)
+        .map_err(|_| RedisError::Err("min or max is not a float".to_string()))?;
+    Ok(ScoreBound { score, exclusive })
+}
+
+fn parse_lex_bound(arg: &Bytes) -> Result<LexBound, RedisError> {
+    match arg.first() {
+        Some(b'-') if arg.len() == 1 => Ok(LexBound::Min),
+        Some(b'+') if arg.len() == 1 => Ok(LexBound::Max),
+        Some(b'[') => Ok(LexBound::Inclusive(arg.slice(1..))),
+        Some(b'(') => Ok(LexBound::Exclusive(arg.slice(1..))),
+        _ => Err(RedisError::Err(
+            "min or max not valid string range item".to_string(),
+        )),
+    }
+}
+
+fn build_lpos_params(args: &[Bytes]) -> Result<LPosParams, RedisError> {
+    let mut params = LPosParams {
+        key: args[0].clone(),
+        element: args[1].clone(),
+        rank: 1,
+        count: None,
+        maxlen: 0,
+    };
+
+    let mut i = 2;
+    while i < args.len() {
+        let Some(value) = args.get(i + 1) else {
+            return Err(RedisError::Syntax);
+        };
+        match to_keyword(&args[i]).as_str() {
+            "rank" => {
+                params.rank = parse_integer(value)?;
+                if params.rank == 0 {
+                    return Err(RedisError::Err(
+                        "RANK can't be zero: use 1 to start from the first match, 2 from the second ... or use negative to start from the end of the list".to_string(),
+                    ));
+                }
+                // Its negation has to fit as well
+                if params.rank == i64::MIN {
+                    return Err(RedisError::NotInteger);
+                }
+            }
+            "count" => {
+                params.count = Some(
+                    parse_integer::<i64>(value)?
+                        .try_into()
+                        .map_err(|_| RedisError::Err("COUNT can't be negative".to_string()))?,
+                )
+            }
+            "maxlen" => {
+                params.maxlen = parse_integer::<i64>(value)?
+                    .try_into()
+                    .map_err(|_| RedisError::Err("MAXLEN can't be negative".to_string()))?
+            }
+            _ => return Err(RedisError::Syntax),
+        }
+        i += 2;
+    }
+
+    Ok(params)
+}
+
+/// Parses the value of an EX/PX/EXAT/PXAT option, rejecting times Redis considers invalid.
+fn parse_expiry(option: &str, value: &Bytes, command: &str) -> Result<Expiry, RedisError> {
+    let value: i64 = parse_integer(value)?;
+    let is_seconds = option == "ex" || option == "exat";
+
+    // Anything that would overflow once converted to milliseconds is rejected up front
+    if value <= 0 || (is_seconds && value > i64::MAX / 1000) {
+        return Err(RedisError::InvalidExpireTime(command.to_string()));
+    }
+
+    Ok(match option {
+        "ex" => Expiry::Ex(value),
+        "px" => Expiry::Px(value),
+        "exat" => Expiry::ExAt(value),
+        _ => Expiry::PxAt(value),
+    })
+}
+
+fn build_hello_params(args: &[Bytes]) -> Result<HelloParams, RedisError> {
+    let mut params = HelloParams {
+        protover: None,
+        auth: None,
+        setname: None,
+    };
+
+    let Some(protover) = args.first() else {
+        return Ok(params);
+    };
+    params.protover = Some(parse_integer(protover).map_err(|_| {
+        RedisError::Err("Protocol version is not an integer or out of range".to_string())
+    })?);
+
+    let mut i = 1;
+    while i < args.len() {
+        let remaining = args.len() - i - 1;
+        match to_keyword(&args[i]).as_str() {
+            "auth" if remaining >= 2 => {
+                params.auth = Some((args[i + 1].clone(), args[i + 2].clone()));
+                i += 3;
+            }
+            "setname" if remaining >= 1 => {
+                params.setname = Some(args[i + 1].clone());
+                i += 2;
+            }
+            _ => {
+                return Err(RedisError::Err(format!(
+                    "Syntax error in HELLO option '{}'",
+                    to_string(&args[i])
+                )))
+            }
+        }
+    }
+
+    Ok(params)
+}
+
+/// Lossy conversion for arguments that are keywords or numbers rather than payload data
+pub(crate) fn to_string(arg: &Bytes) -> String {
+    String::from_utf8_lossy(arg).into_owned()
+}
+
+/// Keywords such as option names and CONFIG parameters are case-insensitive in Redis
+pub(crate) fn to_keyword(arg: &Bytes) -> String {
+    to_string(arg).to_lowercase()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{Expiry, SetCondition};
+
+    fn argv(args: &[&str]) -> Vec<Bytes> {
+        args.iter()
+            .map(|arg| Bytes::copy_from_slice(arg.as_bytes()))
+            .collect()
+    }
+
+    fn parse_set(args: &[&str]) -> SetParams {
+        match parse(&argv(args)) {
+            Ok((_, Set(params))) => params,
+            other => panic!("expected SET, got {:?}", other.map(|(_, command)| command)),
+        }
+    }
+
+    #[test]
+    fn command_names_match_in_any_case() {
+        for name in ["echo", "ECHO", "Echo", "eChO"] {
+            let (spec, command) = parse(&argv(&[name, "hi"])).unwrap();
+            assert_eq!(spec.name, "echo");
+            assert!(matches!(command, Echo(_)));
+        }
+        assert!(
+            matches!(parse(&argv(&["config", "GeT", "dir"])), Ok((spec, _)) if spec.name == "get")
+        );
+    }
+
+    #[test]
+    fn keywords_match_in_any_case() {
+        for (px, nx) in [("px", "nx"), ("PX", "NX"), ("pX", "Nx")] {
+            let params = parse_set(&["SET", "k", "v", px, "100", nx]);
+            assert!(matches!(params.expiry, Some(Expiry::Px(100))));
+            assert!(matches!(params.condition, Some(SetCondition::Nx)));
+        }
+
+        let params = parse_set(&["set", "k", "v", "Ex", "10", "xX", "GeT"]);
+        assert!(matches!(params.expiry, Some(Expiry::Ex(10))));
+        assert!(matches!(params.condition, Some(SetCondition::Xx)));
+        assert!(params.get);
+    }
+
+    #[test]
+    fn keys_and_values_keep_their_case() {
+        let params = parse_set(&["SET", "Foo", "Bar", "EX", "10"]);
+        assert_eq!(params.key, "Foo");
+        assert_eq!(params.value, "Bar");
+
+        match parse(&argv(&["ECHO", "Hello"])).unwrap().1 {
+            Echo(message) => assert_eq!(message, "Hello"),
+            other => panic!("expected ECHO, got {:?}", other),
+        }
+        match parse(&argv(&["get", "MixedCase"])).unwrap().1 {
+            Get(key) => assert_eq!(key, "MixedCase"),
+            other => panic!("expected GET, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn keys_spelled_like_keywords_stay_keys() {
+        let params = parse_set(&["SET", "NX", "px"]);
+        assert_eq!(params.key, "NX");
+        assert_eq!(params.value, "px");
+        assert!(params.condition.is_none());
+        assert!(params.expiry.is_none());
+    }
+
+    fn parse_error(args: &[&str]) -> String {
+        match parse(&argv(args)) {
+            Err(err) => err.to_string(),
+            Ok((_, command)) => panic!("expected an error, got {:?}", command),
+        }
+    }
+
+    #[test]
+    fn hrandfield_rejects_counts_without_a_magnitude() {
+        let min = i64::MIN.to_string();
+        assert_eq!(
+            parse_error(&["HRANDFIELD", "h", &min]),
+            "ERR value is out of range"
+        );
+        assert_eq!(
+            parse_error(&["HRANDFIELD", "h", &min, "WITHVALUES"]),
+            "ERR value is out of range"
+        );
+        let max = format!("-{}", i64::MAX);
+        assert!(matches!(
+            parse(&argv(&["HRANDFIELD", "h", &max])),
+            Ok((_, HRandField(_, Some(count), false))) if count == -i64::MAX
+        ));
+    }
+
+    #[test]
+    fn srandmember_rejects_counts_without_a_magnitude() {
+        let min = i64::MIN.to_string();
+        assert_eq!(
+            parse_error(&["SRANDMEMBER", "s", &min]),
+            "ERR value is out of range"
+        );
+        let max = format!("-{}", i64::MAX);
+        assert!(matches!(
+            parse(&argv(&["SRANDMEMBER", "s", &max])),
+            Ok((_, SRandMember(_, Some(count)))) if count == -i64::MAX
+        ));
+    }
+
+    #[test]
+    fn blocking_timeouts_round_up_to_the_millisecond() {
+        let timeout = |seconds: &str| match parse(&argv(&["BLPOP", "k", seconds])) {
+            Ok((_, BLPop(_, timeout))) => timeout,
+            other => panic!(
+                "expected BLPOP, got {:?}",
+                other.map(|(_, command)| command)
+            ),
+        };
+        assert_eq!(timeout("0"), None);
+        assert_eq!(timeout("0.0001"), Some(Duration::from_millis(1)));
+        assert_eq!(timeout("1.5"), Some(Duration::from_millis(1500)));
+        assert_eq!(timeout("1.1"), Some(Duration::from_millis(1100)));
+        assert_eq!(timeout("2.0001"), Some(Duration::from_millis(2001)));
+    }
+
+    #[test]
+    fn numkeys_commands_report_their_keys() {
+        let keys = |args: &[&str]| {
+            let argv = argv(args);
+            let (spec, _) = resolve(&argv).unwrap();
+            spec.key_positions(&argv)
+                .into_iter()
+                .map(|position| args[position].to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(keys(&["LMPOP", "2", "a", "b", "LEFT"]), ["a", "b"]);
+        assert_eq!(keys(&["BLMPOP", "0", "1", "a", "LEFT"]), ["a"]);
+        assert_eq!(
+            keys(&["SINTERCARD", "2", "a", "b", "LIMIT", "1"]),
+            ["a", "b"]
+        );
+        assert_eq!(keys(&["ZUNIONSTORE", "d", "2", "a", "b"]), ["d", "a", "b"]);
+        assert_eq!(keys(&["ZINTER", "2", "a", "b", "WITHSCORES"]), ["a", "b"]);
+        assert_eq!(keys(&["ZMPOP", "1", "a", "MIN"]), ["a"]);
+        assert_eq!(keys(&["BZMPOP", "0", "2", "a", "b", "MAX"]), ["a", "b"]);
+        assert!(keys(&["LMPOP", "3", "a", "b"]).is_empty());
+        assert!(keys(&["ZUNIONSTORE", "d", "x", "a"]).is_empty());
+        assert_eq!(keys(&["BLPOP", "a", "b", "0"]), ["a", "b"]);
+    }
+}
@@ -0,0 +1,127 @@
+use crate::models::{to_command, Command, RedisError, RespValue};
+use crate::processing::{process_command, write_and_flush};
+use crate::resp::{Frame, Protocol, RespReader};
+use crate::server::Server;
+use bytes::Bytes;
+use std::sync::Arc;
+use tokio::net::tcp::OwnedWriteHalf;
+use tokio::sync::Mutex;
+
+/// Per-connection state.
+pub struct Connection {
+    pub stream: Arc<Mutex<OwnedWriteHalf>>,
+
+    // Bytes of commands processed so far, reported back to the master on GETACK
+    pub offset: usize,
+
+    // The replication link to our master only gets replies to REPLCONF
+    pub from_master: bool,
+
+    pub id: u64,
+    pub name: Option<Bytes>,
+    pub protocol: Protocol,
+
+    // Index of the database commands run against
+    pub db: usize,
+}
+
+/// Serves requests read by `reader` until the peer disconnects.
+///
+/// Every complete request already buffered is executed before the replies are
+/// written, so a pipelining client gets a whole batch back in a single write.
+pub async fn handle_connection(
+    server: Arc<Server>,
+    mut reader: RespReader,
+    write_half: OwnedWriteHalf,
+    from_master: bool,
+) {
+    let mut connection = Connection {
+        stream: Arc::new(Mutex::new(write_half)),
+        offset: 0,
+        from_master,
+        id: server.next_client_id(),
+        name: None,
+        protocol: Protocol::Resp2,
+        db: 0,
+    };
+
+    let mut replies = Vec::new();
+    loop {
+        let mut next = reader.read_frame().await;
+        loop {
+            match next {
+                Ok(Some(frame)) => {
+                    // A client blocked by its command may give up on it by disconnecting
+                    tokio::select! {
+                        biased;
+                        _ = process_frame(frame, &server, &mut connection, &mut replies) => {}
+                        _ = reader.closed() => return,
+                    }
+                }
+                Ok(None) => {
+                    // EOF
+                    println!("No more data");
+                    flush(&connection, &mut replies).await;
+                    return;
+                }
+                Err(err) => {
+                    // The rest of the stream can't be framed after a protocol error
+                    let error = RespValue::from(RedisError::from(err));
+                    replies.extend(error.encode(connection.protocol));
+                    flush(&connection, &mut replies).await;
+                    return;
+                }
+            }
+
+            next = reader.next_buffered();
+            if let Ok(None) = next {
+                break;
+            }
+        }
+
+        flush(&connection, &mut replies).await;
+    }
+}
+
+async fn process_frame(
+    frame: Frame,
+    server: &Arc<Server>,
+    connection: &mut Connection,
+    replies: &mut Vec<u8>,
+) {
+    let len = frame.len;
+    match to_command(frame) {
+        Ok(Some(request)) => {
+            let reply_expected =
+                !connection.from_master || matches!(request.command, Command::ReplConf(_, _));
+
+            // Replies to the commands before one that may block go out first, as it may hold
+            // on to the batch for good
+            if request.spec.flags.contains(&"blocking") {
+                flush(connection, replies).await;
+            }
+
+            let mut reply = Vec::new();
+            process_command(request, server, connection, &mut reply).await;
+            if reply_expected {
+                replies.extend(reply);
+            }
+        }
+        Ok(None) => {}
+        Err(err) => {
+            if !connection.from_master {
+                replies.extend(RespValue::from(err).encode(connection.protocol));
+            }
+        }
+    }
+
+    connection.offset += len;
+}
+
+async fn flush(connection: &Connection, replies: &mut Vec<u8>) {
+    if !replies.is_empty() {
+        // A client that has gone away is noticed by the next read from it
+        let mut stream = connection.stream.lock().await;
+        let _ = write_and_flush(&mut *stream, std::mem::take(replies)).await;
+    }
+}
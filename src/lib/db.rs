@@ -0,0 +1,329 @@
+use crate::hash::Hash;
+use crate::quicklist::QuickList;
+use crate::set::Set;
+use crate::zset::SortedSet;
+use bytes::Bytes;
+use dashmap::mapref::entry::Entry as MapEntry;
+use dashmap::mapref::one::MappedRefMut;
+use dashmap::DashMap;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::BTreeSet;
+use std::hash::Hasher;
+use std::sync::{Arc, Mutex};
+use std::time::SystemTime;
+
+/// What a key holds.
+///
+/// Collections sit behind an `Arc` so that handing out copies of an entry stays cheap. They are
+/// cloned on write whenever a copy is still around, which also makes COPY itself cheap.
+#[derive(Debug, Clone)]
+pub enum Value {
+    String(Bytes),
+    List(Arc<QuickList>),
+    Hash(Arc<Hash>),
+    Set(Arc<Set>),
+    ZSet(Arc<SortedSet>),
+}
+
+impl Value {
+    /// The type name reported by TYPE and matched by SCAN's TYPE option.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+            Value::Hash(_) => "hash",
+            Value::Set(_) => "set",
+            Value::ZSet(_) => "zset",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Entry {
+    pub value: Value,
+    pub expire_at: Option<SystemTime>,
+}
+
+impl Entry {
+    pub fn new(value: Value, expire_at: Option<SystemTime>) -> Entry {
+        Entry { value, expire_at }
+    }
+
+    pub fn is_expired(&self, now: SystemTime) -> bool {
+        self.expire_at.is_some_and(|expire_at| expire_at <= now)
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        self.value.type_name()
+    }
+}
+
+/// A keyspace, along with indexes of its keys by expiry and by SCAN order.
+///
+/// Expired keys are kept until someone deletes them, so reads go through `get` which hides them.
+pub struct Db {
+    entries: DashMap<Bytes, Entry>,
+
+    // Keys with an expiry ordered by deadline, so the ones due are always at the front
+    expires: Mutex<BTreeSet<(SystemTime, Bytes)>>,
+
+    // Every key ordered by its scan hash, which SCAN cursors point into
+    scan_order: Mutex<BTreeSet<(u64, Bytes)>>,
+
+    // Hashes ordered by when one of their fields expires. Entries may be stale, as they are
+    // only checked once due
+    field_expires: Mutex<BTreeSet<(SystemTime, Bytes)>>,
+}
+
+impl Db {
+    pub fn new() -> Db {
+        Db {
+            entries: DashMap::new(),
+            expires: Mutex::new(BTreeSet::new()),
+            scan_order: Mutex::new(BTreeSet::new()),
+            field_expires: Mutex::new(BTreeSet::new()),
+        }
+    }
+
+    /// Returns the entry stored at `key`, unless it has expired.
+    pub fn get(&self, key: &Bytes) -> Option<Entry> {
+        self.entries
+            .get(key)
+            .filter(|entry| !entry.is_expired(SystemTime::now()))
+            .map(|entry| entry.clone())
+    }
+
+    /// Returns the value stored at `key` for modifying in place, unless it has expired.
+    ///
+    /// The key's shard stays locked until the reference is dropped, so no other method may be
+    /// called in the meantime.
+    pub fn get_mut(&self, key: &Bytes) -> Option<MappedRefMut<'_, Bytes, Entry, Value>> {
+        self.entries
+            .get_mut(key)
+            .filter(|entry| !entry.is_expired(SystemTime::now()))
+            .map(|entry| entry.map(|entry| &mut entry.value))
+    }
+
+    /// Like `get_mut`, but first stores the value `default` makes if there's no such key.
+    pub fn get_or_insert_with(
+        &self,
+        key: &Bytes,
+        default: impl FnOnce() -> Value,
+    ) -> MappedRefMut<'_, Bytes, Entry, Value> {
+        let entry = match self.entries.entry(key.clone()) {
+            MapEntry::Occupied(entry) => entry.into_ref(),
+            MapEntry::Vacant(entry) => {
+                let hash = scan_hash(key);
+                self.scan_order.lock().unwrap().insert((hash, key.clone()));
+                entry.insert(Entry::new(default(), None))
+            }
+        };
+        entry.map(|entry| &mut entry.value)
+    }
+
+    /// Returns whether `key` holds an entry that has expired but not been deleted yet.
+    pub fn has_expired(&self, key: &Bytes) -> bool {
+        self.entries
+            .get(key)
+            .is_some_and(|entry| entry.is_expired(SystemTime::now()))
+    }
+
+    pub fn insert(&self, key: Bytes, entry: Entry) {
+        let field_expiry = match &entry.value {
+            Value::Hash(hash) => hash.next_expiry(),
+            _ => None,
+        };
+
+        let mut expires = self.expires.lock().unwrap();
+        let expire_at = entry.expire_at;
+        match self.entries.insert(key.clone(), entry) {
+            Some(previous) => {
+                if let Some(previous) = previous.expire_at {
+                    expires.remove(&(previous, key.clone()));
+                }
+            }
+            None => {
+                let hash = scan_hash(&key);
+                self.scan_order.lock().unwrap().insert((hash, key.clone()));
+            }
+        }
+        // Only now, as the previous deadline may well be the same one
+        if let Some(expire_at) = expire_at {
+            expires.insert((expire_at, key.clone()));
+        }
+        drop(expires);
+
+        if let Some(expire_at) = field_expiry {
+            self.watch_field_expiry(&key, expire_at);
+        }
+    }
+
+    pub fn remove(&self, key: &Bytes) -> Option<Entry> {
+        let (key, entry) = self.entries.remove(key)?;
+        if let Some(expire_at) = entry.expire_at {
+            self.expires
+                .lock()
+                .unwrap()
+                .remove(&(expire_at, key.clone()));
+        }
+        self.scan_order
+            .lock()
+            .unwrap()
+            .remove(&(scan_hash(&key), key));
+        Some(entry)
+    }
+
+    /// Replaces the expiry of `key`, returning false if there's no such key.
+    pub fn set_expiry(&self, key: &Bytes, expire_at: Option<SystemTime>) -> bool {
+        let Some(mut entry) = self.entries.get_mut(key) else {
+            return false;
+        };
+
+        let mut expires = self.expires.lock().unwrap();
+        if let Some(previous) = entry.expire_at {
+            expires.remove(&(previous, key.clone()));
+        }
+        if let Some(expire_at) = expire_at {
+            expires.insert((expire_at, key.clone()));
+        }
+        entry.expire_at = expire_at;
+        true
+    }
+
+    /// Notes that a field of the hash at `key` expires at `expire_at`.
+    pub fn watch_field_expiry(&self, key: &Bytes, expire_at: SystemTime) {
+        self.field_expires
+            .lock()
+            .unwrap()
+            .insert((expire_at, key.clone()));
+    }
+
+    /// Takes up to `limit` hashes which had a field due by `now`, soonest first.
+    pub fn due_hashes(&self, now: SystemTime, limit: usize) -> Vec<Bytes> {
+        let mut field_expires = self.field_expires.lock().unwrap();
+        let mut keys = Vec::new();
+        while keys.len() < limit {
+            match field_expires.first() {
+                Some((expire_at, _)) if *expire_at <= now => {
+                    keys.push(field_expires.pop_first().unwrap().1);
+                }
+                _ => break,
+            }
+        }
+        keys
+    }
+
+    /// Removes the fields of the hash at `key` which expired by `now`, returning their names.
+    /// The key goes along with its last field.
+    pub fn remove_expired_fields(&self, key: &Bytes, now: SystemTime) -> Vec<Bytes> {
+        let (removed, emptied) = {
+            let Some(mut entry) = self.entries.get_mut(key) else {
+                return vec![];
+            };
+            let Value::Hash(hash) = &mut entry.value else {
+                return vec![];
+            };
+            let Some(next_expiry) = hash.next_expiry() else {
+                return vec![];
+            };
+            if next_expiry > now {
+                // Whichever entry led here may have been taken off already
+                self.watch_field_expiry(key, next_expiry);
+                return vec![];
+            }
+
+            let hash = Arc::make_mut(hash);
+            let removed = hash.remove_expired(now);
+            if let Some(next_expiry) = hash.next_expiry() {
+                self.watch_field_expiry(key, next_expiry);
+            }
+            (removed, hash.is_empty())
+        };
+
+        if emptied {
+            self.remove(key);
+        }
+        removed
+    }
+
+    /// The number of keys, counting expired ones not deleted yet.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn clear(&self) {
+        self.entries.clear();
+        self.expires.lock().unwrap().clear();
+        self.scan_order.lock().unwrap().clear();
+        self.field_expires.lock().unwrap().clear();
+    }
+
+    /// Every key that hasn't expired, in no particular order.
+    pub fn keys(&self) -> Vec<Bytes> {
+        let now = SystemTime::now();
+        self.entries
+            .iter()
+            .filter(|entry| !entry.is_expired(now))
+            .map(|entry| entry.key().clone())
+            .collect()
+    }
+
+    /// Returns about `count` keys starting at `cursor`, and the cursor the next call should pass.
+    ///
+    /// Keys are visited in the order of their scan hash and the cursor is the hash to resume
+    /// from, so keys added or removed in between calls don't shift the others around: a key
+    /// present for the whole scan is returned exactly once. Expired keys are included. The
+    /// returned cursor is 0 once there is nothing left.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        let scan_order = self.scan_order.lock().unwrap();
+        let mut keys = Vec::with_capacity(count);
+        let mut last_hash = None;
+        for (hash, key) in scan_order.range((cursor, Bytes::new())..) {
+            // Keys sharing a hash can't be told apart by a cursor, so they go out together
+            if keys.len() >= count && last_hash != Some(*hash) {
+                return (*hash, keys);
+            }
+            keys.push(key.clone());
+            last_hash = Some(*hash);
+        }
+        (0, keys)
+    }
+
+    /// A copy of every entry, expired ones included, for writing out a snapshot.
+    pub fn entries(&self) -> Vec<(Bytes, Entry)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.key().clone(), entry.value().clone()))
+            .collect()
+    }
+
+    /// Returns up to `limit` keys which expired by `now`, soonest first. They are left in place.
+    pub fn expired_keys(&self, now: SystemTime, limit: usize) -> Vec<Bytes> {
+        self.expires
+            .lock()
+            .unwrap()
+            .iter()
+            .take_while(|(expire_at, _)| *expire_at <= now)
+            .take(limit)
+            .map(|(_, key)| key.clone())
+            .collect()
+    }
+}
+
+/// Position of `key` in SCAN order. Never 0, as that cursor means starting over.
+pub fn scan_hash(key: &[u8]) -> u64 {
+    // Unlike `RandomState`, a default `DefaultHasher` hashes the same way for the whole process
+    let mut hasher = DefaultHasher::new();
+    hasher.write(key);
+    hasher.finish().max(1)
+}
+
+impl Default for Db {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,116 @@
+/// Matches `string` against a glob-style `pattern` the way Redis does for KEYS, SCAN and friends.
+///
+/// Supports `*`, `?`, character classes such as `[abc]`, `[^a]` and `[a-z]`, and `\` to escape
+/// any of those. An unterminated class runs to the end of the pattern.
+///
+/// Only the last `*` seen is ever backtracked to, letting it take one more character each time,
+/// so matching takes at most the product of both lengths however many stars there are.
+pub fn matches(pattern: &[u8], string: &[u8]) -> bool {
+    // Redis never gets to skip the stars of a pattern before taking a character, so even `*`
+    // doesn't match the empty string
+    if string.is_empty() {
+        return pattern.is_empty();
+    }
+
+    let (mut p, mut s) = (0, 0);
+    // Pattern position just past the last star, and how much of the string it has taken
+    let mut last_star: Option<(usize, usize)> = None;
+
+    while s < string.len() {
+        if pattern.get(p) == Some(&b'*') {
+            p += 1;
+            last_star = Some((p, s));
+            continue;
+        }
+        if let Some(next) = match_one(pattern, p, string[s]) {
+            p = next;
+            s += 1;
+            continue;
+        }
+
+        let Some((after_star, taken)) = last_star else {
+            return false;
+        };
+        last_star = Some((after_star, taken + 1));
+        p = after_star;
+        s = taken + 1;
+    }
+
+    pattern[p..].iter().all(|&c| c == b'*')
+}
+
+// Matches `c` against the single element of `pattern` at `p`, which isn't a star, returning
+// where the next element starts
+fn match_one(pattern: &[u8], mut p: usize, c: u8) -> Option<usize> {
+    match *pattern.get(p)? {
+        b'?' => Some(p + 1),
+        b'[' => {
+            p += 1;
+            let negated = pattern.get(p) == Some(&b'^');
+            if negated {
+                p += 1;
+            }
+
+            let mut matched = false;
+            while let Some(&class) = pattern.get(p) {
+                if class == b'\\' && p + 1 < pattern.len() {
+                    p += 1;
+                    matched |= pattern[p] == c;
+                } else if class == b']' {
+                    break;
+                } else if p + 2 < pattern.len() && pattern[p + 1] == b'-' {
+                    let (start, end) = if pattern[p] <= pattern[p + 2] {
+                        (pattern[p], pattern[p + 2])
+                    } else {
+                        (pattern[p + 2], pattern[p])
+                    };
+                    matched |= (start..=end).contains(&c);
+                    p += 2;
+                } else {
+                    matched |= class == c;
+                }
+                p += 1;
+            }
+
+            // Steps past the closing bracket, if there is one
+            (matched != negated).then_some((p + 1).min(pattern.len()))
+        }
+        b'\\' if p + 1 < pattern.len() => (pattern[p + 1] == c).then_some(p + 2),
+        literal => (literal == c).then_some(p + 1),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::matches;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn matches_wildcards_classes_and_escapes() {
+        assert!(!matches(b"*", b""));
+        assert!(matches(b"h?llo", b"hello"));
+        assert!(matches(b"h*llo", b"heeeello"));
+        assert!(matches(b"h*llo", b"hllo"));
+        assert!(!matches(b"h*llo", b"hell"));
+        assert!(matches(b"*a*b", b"xxaxxb"));
+        assert!(!matches(b"*a*b", b"xxaxxbx"));
+        assert!(matches(b"h[ae]llo", b"hallo"));
+        assert!(!matches(b"h[^e]llo", b"hello"));
+        assert!(matches(b"h[a-b]llo", b"hbllo"));
+        assert!(matches(b"h[b-a]llo", b"hallo"));
+        assert!(matches(b"h\\*llo", b"h*llo"));
+        assert!(!matches(b"h\\*llo", b"hello"));
+        assert!(matches(b"[abc", b"b"));
+        assert!(!matches(b"a", b"ab"));
+        assert!(!matches(b"ab", b"a"));
+    }
+
+    #[test]
+    fn many_stars_match_in_polynomial_time() {
+        let string = [b'a'; 82];
+        let started = Instant::now();
+        assert!(!matches(b"*a*a*a*a*a*a*a*a*a*b", &string));
+        assert!(matches(b"*a*a*a*a*a*a*a*a*a*", &string));
+        assert!(started.elapsed() < Duration::from_secs(1));
+    }
+}
@@ -0,0 +1,306 @@
+use crate::db::scan_hash;
+use bytes::Bytes;
+use std::collections::{BTreeSet, HashMap};
+use std::time::SystemTime;
+
+// Limits past which a hash leaves its compact form, the defaults of Redis'
+// hash-max-listpack-entries and hash-max-listpack-value
+const COMPACT_MAX_ENTRIES: usize = 128;
+const COMPACT_MAX_VALUE: usize = 64;
+
+/// A hash, stored much like Redis does.
+///
+/// Small hashes are a flat list of fields in insertion order, which is searched from start to
+/// end but takes little memory. Once a hash outgrows that it becomes a table, and stays one.
+///
+/// Fields may expire on their own. Expired fields are kept until someone removes them, so
+/// reads hide them the way `Db` hides expired keys.
+#[derive(Debug, Clone, Default)]
+pub struct Hash {
+    encoding: Encoding,
+
+    // Fields with an expiry ordered by deadline, so the ones due are always at the front
+    expires: BTreeSet<(SystemTime, Bytes)>,
+}
+
+#[derive(Debug, Clone)]
+enum Encoding {
+    Compact(Vec<(Bytes, Slot)>),
+    Table {
+        fields: HashMap<Bytes, Slot>,
+
+        // Every field ordered by its scan hash, which HSCAN cursors point into
+        scan_order: BTreeSet<(u64, Bytes)>,
+    },
+}
+
+impl Default for Encoding {
+    fn default() -> Encoding {
+        Encoding::Compact(Vec::new())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Slot {
+    value: Bytes,
+    expire_at: Option<SystemTime>,
+}
+
+impl Slot {
+    fn is_live(&self, now: SystemTime) -> bool {
+        self.expire_at.is_none_or(|expire_at| expire_at > now)
+    }
+}
+
+impl Hash {
+    pub fn new() -> Hash {
+        Hash::default()
+    }
+
+    /// The number of fields, counting expired ones not removed yet.
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::Compact(fields) => fields.len(),
+            Encoding::Table { fields, .. } => fields.len(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn is_compact(&self) -> bool {
+        matches!(self.encoding, Encoding::Compact(_))
+    }
+
+    /// Returns the value of `field`, unless it has expired.
+    pub fn get(&self, field: &[u8]) -> Option<&Bytes> {
+        self.slot(field)
+            .filter(|slot| slot.is_live(SystemTime::now()))
+            .map(|slot| &slot.value)
+    }
+
+    pub fn contains(&self, field: &[u8]) -> bool {
+        self.get(field).is_some()
+    }
+
+    /// Sets `field` to `value`, dropping any expiry it had. Returns whether the field is a new
+    /// one.
+    pub fn insert(&mut self, field: Bytes, value: Bytes) -> bool {
+        self.store(field, value, false)
+    }
+
+    /// Like `insert`, but an existing field keeps its expiry.
+    pub fn insert_keep_ttl(&mut self, field: Bytes, value: Bytes) -> bool {
+        self.store(field, value, true)
+    }
+
+    pub fn remove(&mut self, field: &[u8]) -> Option<Bytes> {
+        let (field, slot) = match &mut self.encoding {
+            Encoding::Compact(fields) => {
+                let position = fields.iter().position(|(name, _)| name == field)?;
+                fields.remove(position)
+            }
+            Encoding::Table { fields, scan_order } => {
+                let (field, slot) = fields.remove_entry(field)?;
+                scan_order.remove(&(scan_hash(&field), field.clone()));
+                (field, slot)
+            }
+        };
+        if let Some(expire_at) = slot.expire_at {
+            self.expires.remove(&(expire_at, field));
+        }
+        Some(slot.value)
+    }
+
+    /// The deadline of `field`: None if there's no such field, and Some(None) if it doesn't
+    /// expire.
+    pub fn expiry(&self, field: &[u8]) -> Option<Option<SystemTime>> {
+        self.slot(field)
+            .filter(|slot| slot.is_live(SystemTime::now()))
+            .map(|slot| slot.expire_at)
+    }
+
+    /// Replaces the expiry of `field`, returning false if there's no such field.
+    pub fn set_expiry(&mut self, field: &[u8], expire_at: Option<SystemTime>) -> bool {
+        let slot = match &mut self.encoding {
+            Encoding::Compact(fields) => fields
+                .iter_mut()
+                .find(|(name, _)| name == field)
+                .map(|(_, slot)| slot),
+            Encoding::Table { fields, .. } => fields.get_mut(field),
+        };
+        let Some(slot) = slot else {
+            return false;
+        };
+
+        let field = Bytes::copy_from_slice(field);
+        if let Some(previous) = slot.expire_at {
+            self.expires.remove(&(previous, field.clone()));
+        }
+        if let Some(expire_at) = expire_at {
+            self.expires.insert((expire_at, field));
+        }
+        slot.expire_at = expire_at;
+        true
+    }
+
+    /// The earliest deadline among the fields, if any of them expires.
+    pub fn next_expiry(&self) -> Option<SystemTime> {
+        self.expires.first().map(|(expire_at, _)| *expire_at)
+    }
+
+    /// Removes the fields which expired by `now`, returning their names.
+    pub fn remove_expired(&mut self, now: SystemTime) -> Vec<Bytes> {
+        let due: Vec<Bytes> = self
+            .expires
+            .iter()
+            .take_while(|(expire_at, _)| *expire_at <= now)
+            .map(|(_, field)| field.clone())
+            .collect();
+        for field in &due {
+            self.remove(field);
+        }
+        due
+    }
+
+    /// Every field with its value, leaving out expired ones.
+    pub fn iter(&self) -> impl Iterator<Item = (&Bytes, &Bytes)> {
+        let now = SystemTime::now();
+        self.iter_with_expiry()
+            .filter(move |(_, _, expire_at)| expire_at.is_none_or(|expire_at| expire_at > now))
+            .map(|(field, value, _)| (field, value))
+    }
+
+    /// Every field with its value and expiry, expired ones included.
+    pub fn iter_with_expiry(
+        &self,
+    ) -> Box<dyn Iterator<Item = (&Bytes, &Bytes, Option<SystemTime>)> + '_> {
+        match &self.encoding {
+            Encoding::Compact(fields) => Box::new(
+                fields
+                    .iter()
+                    .map(|(field, slot)| (field, &slot.value, slot.expire_at)),
+            ),
+            Encoding::Table { fields, .. } => Box::new(
+                fields
+                    .iter()
+                    .map(|(field, slot)| (field, &slot.value, slot.expire_at)),
+            ),
+        }
+    }
+
+    /// Returns about `count` fields with their values starting at `cursor`, and the cursor
+    /// the next call should pass.
+    ///
+    /// Works like `Db::scan`. A compact hash is small enough to be returned whole, as Redis
+    /// does too.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<(Bytes, Bytes)>) {
+        let Encoding::Table { fields, scan_order } = &self.encoding else {
+            return (0, self.pairs());
+        };
+
+        let now = SystemTime::now();
+        let mut pairs = Vec::with_capacity(count);
+        let mut last_hash = None;
+        for (hash, field) in scan_order.range((cursor, Bytes::new())..) {
+            // Fields sharing a hash can't be told apart by a cursor, so they go out together
+            if pairs.len() >= count && last_hash != Some(*hash) {
+                return (*hash, pairs);
+            }
+            last_hash = Some(*hash);
+
+            let slot = &fields[field];
+            if slot.is_live(now) {
+                pairs.push((field.clone(), slot.value.clone()));
+            }
+        }
+        (0, pairs)
+    }
+
+    /// A copy of every field and its value.
+    pub fn pairs(&self) -> Vec<(Bytes, Bytes)> {
+        self.iter()
+            .map(|(field, value)| (field.clone(), value.clone()))
+            .collect()
+    }
+
+    fn slot(&self, field: &[u8]) -> Option<&Slot> {
+        match &self.encoding {
+            Encoding::Compact(fields) => fields
+                .iter()
+                .find(|(name, _)| name == field)
+                .map(|(_, slot)| slot),
+            Encoding::Table { fields, .. } => fields.get(field),
+        }
+    }
+
+    fn store(&mut self, field: Bytes, value: Bytes, keep_ttl: bool) -> bool {
+        let too_long = field.len() > COMPACT_MAX_VALUE || value.len() > COMPACT_MAX_VALUE;
+        let now = SystemTime::now();
+        let existing = match &mut self.encoding {
+            Encoding::Compact(fields) => fields
+                .iter_mut()
+                .find(|(name, _)| *name == field)
+                .map(|(_, slot)| slot),
+            Encoding::Table { fields, .. } => fields.get_mut(&field),
+        };
+
+        // An expired field is as good as gone, expiry included
+        let added = match existing {
+            Some(slot) => {
+                let added = !slot.is_live(now);
+                slot.value = value;
+                if !keep_ttl || added {
+                    if let Some(previous) = slot.expire_at.take() {
+                        self.expires.remove(&(previous, field));
+                    }
+                }
+                added
+            }
+            None => {
+                let slot = Slot {
+                    value,
+                    expire_at: None,
+                };
+                match &mut self.encoding {
+                    Encoding::Compact(fields) => fields.push((field, slot)),
+                    Encoding::Table { fields, scan_order } => {
+                        scan_order.insert((scan_hash(&field), field.clone()));
+                        fields.insert(field, slot);
+                    }
+                }
+                true
+            }
+        };
+
+        if self.is_compact() && (too_long || self.len() > COMPACT_MAX_ENTRIES) {
+            self.convert_to_table();
+        }
+        added
+    }
+
+    fn convert_to_table(&mut self) {
+        let Encoding::Compact(compact) = std::mem::take(&mut self.encoding) else {
+            return;
+        };
+        let scan_order = compact
+            .iter()
+            .map(|(field, _)| (scan_hash(field), field.clone()))
+            .collect();
+        self.encoding = Encoding::Table {
+            fields: compact.into_iter().collect(),
+            scan_order,
+        };
+    }
+}
+
+impl FromIterator<(Bytes, Bytes)> for Hash {
+    fn from_iter<I: IntoIterator<Item = (Bytes, Bytes)>>(iter: I) -> Hash {
+        let mut hash = Hash::new();
+        for (field, value) in iter {
+            hash.insert(field, value);
+        }
+        hash
+    }
+}
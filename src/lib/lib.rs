@@ -1,4 +1,16 @@
+pub mod blocking;
+pub mod commands;
+pub mod connection;
+pub mod db;
+pub mod glob;
+pub mod hash;
+pub mod listpack;
 pub mod models;
 pub mod processing;
+pub mod quicklist;
 pub mod rdb;
 pub mod replication;
+pub mod resp;
+pub mod server;
+pub mod set;
+pub mod zset;
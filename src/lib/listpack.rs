@@ -0,0 +1,196 @@
+use anyhow::{bail, Context};
+use bytes::Bytes;
+
+// Marks the end of both listpacks and ziplists
+const END: u8 = 0xFF;
+
+/// Decodes a listpack, the compact encoding Redis 7 uses for small collections in RDB files.
+///
+/// Integers come back in their decimal form, the way they would have been given to Redis.
+pub fn decode(buf: &[u8]) -> anyhow::Result<Vec<Bytes>> {
+    // Skips the total size and element count heading the listpack
+    let mut pos = 6;
+    let mut elements = Vec::new();
+
+    loop {
+        let start = pos;
+        let encoding = *take(buf, &mut pos, 1)?.first().unwrap();
+        let element = match encoding {
+            END => return Ok(elements),
+            // 7 bit unsigned integer
+            0x00..=0x7F => integer(encoding as i64),
+            // String of up to 63 bytes
+            0x80..=0xBF => Bytes::copy_from_slice(take(buf, &mut pos, (encoding & 0x3F) as usize)?),
+            // 13 bit signed integer
+            0xC0..=0xDF => {
+                let low = take(buf, &mut pos, 1)?[0] as u16;
+                let raw = (((encoding & 0x1F) as u16) << 8) | low;
+                integer(((raw << 3) as i16 >> 3) as i64)
+            }
+            // String of up to 4095 bytes
+            0xE0..=0xEF => {
+                let low = take(buf, &mut pos, 1)?[0] as usize;
+                let length = (((encoding & 0x0F) as usize) << 8) | low;
+                Bytes::copy_from_slice(take(buf, &mut pos, length)?)
+            }
+            0xF0 => {
+                let length = u32::from_le_bytes(take(buf, &mut pos, 4)?.try_into()?) as usize;
+                Bytes::copy_from_slice(take(buf, &mut pos, length)?)
+            }
+            0xF1 => integer(i16::from_le_bytes(take(buf, &mut pos, 2)?.try_into()?) as i64),
+            0xF2 => {
+                let bytes = take(buf, &mut pos, 3)?;
+                integer((i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as i64)
+            }
+            0xF3 => integer(i32::from_le_bytes(take(buf, &mut pos, 4)?.try_into()?) as i64),
+            0xF4 => integer(i64::from_le_bytes(take(buf, &mut pos, 8)?.try_into()?)),
+            other => bail!("Unknown listpack encoding {:#04x}", other),
+        };
+
+        // Each element is trailed by its own length, for walking the listpack backwards
+        let backlen = backlen_size(pos - start);
+        take(buf, &mut pos, backlen)?;
+        elements.push(element);
+    }
+}
+
+/// Encodes `elements` as a listpack, storing those that look like integers as such.
+pub fn encode<'a>(elements: impl IntoIterator<Item = &'a Bytes>) -> Vec<u8> {
+    let mut buf = vec![0; 6];
+    let mut count = 0usize;
+
+    for element in elements {
+        let start = buf.len();
+        match as_integer(element) {
+            Some(value @ 0..=127) => buf.push(value as u8),
+            Some(value @ -4096..=4095) => {
+                let raw = (value as u16) & 0x1FFF;
+                buf.extend_from_slice(&[0xC0 | (raw >> 8) as u8, raw as u8]);
+            }
+            Some(value @ -32768..=32767) => {
+                buf.push(0xF1);
+                buf.extend_from_slice(&(value as i16).to_le_bytes());
+            }
+            Some(value @ -8388608..=8388607) => {
+                buf.push(0xF2);
+                buf.extend_from_slice(&(value as i32).to_le_bytes()[..3]);
+            }
+            Some(value) if i32::try_from(value).is_ok() => {
+                buf.push(0xF3);
+                buf.extend_from_slice(&(value as i32).to_le_bytes());
+            }
+            Some(value) => {
+                buf.push(0xF4);
+                buf.extend_from_slice(&value.to_le_bytes());
+            }
+            None => {
+                let length = element.len();
+                if length < 64 {
+                    buf.push(0x80 | length as u8);
+                } else if length < 4096 {
+                    buf.extend_from_slice(&[0xE0 | (length >> 8) as u8, length as u8]);
+                } else {
+                    buf.push(0xF0);
+                    buf.extend_from_slice(&(length as u32).to_le_bytes());
+                }
+                buf.extend_from_slice(element);
+            }
+        }
+
+        // The length is spread over 7 bits per byte, with the high bit set on all bytes but the
+        // first so it can be read from its end
+        let length = buf.len() - start;
+        let size = backlen_size(length);
+        for i in (0..size).rev() {
+            let mut byte = ((length >> (7 * i)) & 0x7F) as u8;
+            if i != size - 1 {
+                byte |= 0x80;
+            }
+            buf.push(byte);
+        }
+        count += 1;
+    }
+
+    buf.push(END);
+    let total = buf.len() as u32;
+    buf[..4].copy_from_slice(&total.to_le_bytes());
+    // Counts past what fits are left for readers to work out
+    buf[4..6].copy_from_slice(&(count.min(u16::MAX as usize) as u16).to_le_bytes());
+    buf
+}
+
+/// Decodes a ziplist, which older RDB files use where newer ones have listpacks.
+pub fn decode_ziplist(buf: &[u8]) -> anyhow::Result<Vec<Bytes>> {
+    // Skips the total size, offset of the last entry and element count
+    let mut pos = 10;
+    let mut elements = Vec::new();
+
+    loop {
+        let prevlen = *take(buf, &mut pos, 1)?.first().unwrap();
+        if prevlen == END {
+            return Ok(elements);
+        }
+        // Lengths of 254 and up are spelled out in the following 4 bytes
+        if prevlen == 0xFE {
+            take(buf, &mut pos, 4)?;
+        }
+
+        let encoding = take(buf, &mut pos, 1)?[0];
+        let element = match encoding >> 6 {
+            0b00 => Bytes::copy_from_slice(take(buf, &mut pos, (encoding & 0x3F) as usize)?),
+            0b01 => {
+                let low = take(buf, &mut pos, 1)?[0] as usize;
+                let length = (((encoding & 0x3F) as usize) << 8) | low;
+                Bytes::copy_from_slice(take(buf, &mut pos, length)?)
+            }
+            0b10 => {
+                let length = u32::from_be_bytes(take(buf, &mut pos, 4)?.try_into()?) as usize;
+                Bytes::copy_from_slice(take(buf, &mut pos, length)?)
+            }
+            _ => match encoding {
+                0xC0 => integer(i16::from_le_bytes(take(buf, &mut pos, 2)?.try_into()?) as i64),
+                0xD0 => integer(i32::from_le_bytes(take(buf, &mut pos, 4)?.try_into()?) as i64),
+                0xE0 => integer(i64::from_le_bytes(take(buf, &mut pos, 8)?.try_into()?)),
+                0xF0 => {
+                    let bytes = take(buf, &mut pos, 3)?;
+                    integer((i32::from_le_bytes([0, bytes[0], bytes[1], bytes[2]]) >> 8) as i64)
+                }
+                0xFE => integer(take(buf, &mut pos, 1)?[0] as i8 as i64),
+                // Small integers live in the encoding itself, offset by one
+                0xF1..=0xFD => integer((encoding & 0x0F) as i64 - 1),
+                other => bail!("Unknown ziplist encoding {:#04x}", other),
+            },
+        };
+        elements.push(element);
+    }
+}
+
+fn take<'a>(buf: &'a [u8], pos: &mut usize, count: usize) -> anyhow::Result<&'a [u8]> {
+    let bytes = buf
+        .get(*pos..*pos + count)
+        .context("Truncated listpack or ziplist")?;
+    *pos += count;
+    Ok(bytes)
+}
+
+fn integer(value: i64) -> Bytes {
+    Bytes::from(value.to_string())
+}
+
+// Only strings Redis itself would print for the number are stored as integers, so they come
+// back byte for byte
+fn as_integer(element: &[u8]) -> Option<i64> {
+    let value: i64 = std::str::from_utf8(element).ok()?.parse().ok()?;
+    (value.to_string().as_bytes() == element).then_some(value)
+}
+
+// Bytes taken by the length trailing an element of `length` bytes
+fn backlen_size(length: usize) -> usize {
+    match length {
+        0..=127 => 1,
+        128..=16382 => 2,
+        16383..=2097150 => 3,
+        2097151..=268435454 => 4,
+        _ => 5,
+    }
+}
@@ -1,58 +1,329 @@
-use crate::models::Command::*;
-use anyhow::Context;
+use crate::commands;
+use crate::commands::CommandSpec;
+use crate::resp::{parse_length, read_line, Frame, Protocol, ProtocolError};
+use bytes::Bytes;
 use clap::Parser;
-use std::str::FromStr;
-use std::sync::atomic::{AtomicUsize, Ordering};
-use tokio::io::{AsyncBufReadExt, BufStream};
-use tokio::net::TcpStream;
+use std::time::Duration;
+use thiserror::Error;
+
+/// When a key written by SET and friends should expire, as given by the client.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Expiry {
+    Ex(i64),
+    Px(i64),
+    ExAt(i64),
+    PxAt(i64),
+
+    // Retain whatever TTL the key already had
+    KeepTtl,
+
+    // Drop whatever TTL the key had, which only GETEX offers
+    Persist,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetCondition {
+    Nx,
+    Xx,
+}
 
 #[derive(Debug, Clone)]
 pub struct SetParams {
-    pub key: String,
-    pub value: String,
+    pub key: Bytes,
+    pub value: Bytes,
+
+    pub expiry: Option<Expiry>,
+    pub condition: Option<SetCondition>,
+
+    // Reply with the previous value instead of OK
+    pub get: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpireParams {
+    pub key: Bytes,
+    pub expiry: Expiry,
+
+    // Conditions on the key's current expiry, any number of which may be combined except
+    // that NX excludes the rest and GT excludes LT
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct CopyParams {
+    pub source: Bytes,
+    pub destination: Bytes,
+    pub db: Option<i64>,
+    pub replace: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct ScanParams {
+    pub cursor: u64,
+    pub pattern: Option<Bytes>,
+    pub count: usize,
+    pub type_name: Option<String>,
+
+    // HSCAN's NOVALUES, which leaves out the values
+    pub novalues: bool,
+}
+
+/// Which end of a list to push to or pop from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListEnd {
+    Left,
+    Right,
+}
+
+/// How SINTER, SUNION, SDIFF and friends combine their sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SetOp {
+    Inter,
+    Union,
+    Diff,
+}
+
+/// One end of a score range, which leaves out the score itself when exclusive.
+#[derive(Debug, Clone, Copy)]
+pub struct ScoreBound {
+    pub score: f64,
+    pub exclusive: bool,
+}
+
+/// One end of a lexicographical range, `-` and `+` standing for the smallest and largest
+/// possible member.
+#[derive(Debug, Clone)]
+pub enum LexBound {
+    Min,
+    Max,
+    Inclusive(Bytes),
+    Exclusive(Bytes),
+}
+
+/// The members ZRANGE and friends pick, from the lower end to the upper one.
+#[derive(Debug, Clone)]
+pub enum ZRangeBy {
+    Rank(i64, i64),
+    Score(ScoreBound, ScoreBound),
+    Lex(LexBound, LexBound),
+}
+
+#[derive(Debug, Clone)]
+pub struct ZAddParams {
+    pub key: Bytes,
+    pub nx: bool,
+    pub xx: bool,
+    pub gt: bool,
+    pub lt: bool,
+    pub ch: bool,
+    pub incr: bool,
+    pub pairs: Vec<(f64, Bytes)>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ZRangeParams {
+    pub key: Bytes,
+    pub by: ZRangeBy,
+    // Walks from the upper end down, ranks then counting from the highest score
+    pub rev: bool,
+    // The offset and count of LIMIT, a negative count meaning no limit
+    pub limit: Option<(i64, i64)>,
+    pub withscores: bool,
+}
+
+/// How ZUNION and ZINTER merge the scores a member has in several sets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Aggregate {
+    Sum,
+    Min,
+    Max,
+}
+
+#[derive(Debug, Clone)]
+pub struct ZCombineParams {
+    pub op: SetOp,
+    pub keys: Vec<Bytes>,
+    // One per key, each score of a key's set being multiplied by its weight
+    pub weights: Option<Vec<f64>>,
+    pub aggregate: Aggregate,
+    pub withscores: bool,
+}
+
+#[derive(Debug, Clone)]
+pub struct LInsertParams {
+    pub key: Bytes,
+    pub before: bool,
+    pub pivot: Bytes,
+    pub element: Bytes,
+}
+
+#[derive(Debug, Clone)]
+pub struct LPosParams {
+    pub key: Bytes,
+    pub element: Bytes,
+
+    // Which match to start from, counting from the tail when negative. Never 0
+    pub rank: i64,
 
-    pub px: Option<u32>,
+    // How many matches to return, all of them for 0. Replies with a single position if unset
+    pub count: Option<usize>,
+
+    // How many elements to look at, all of them for 0
+    pub maxlen: usize,
+}
+
+#[derive(Debug, Clone)]
+pub struct HelloParams {
+    pub protover: Option<i64>,
+    pub auth: Option<(Bytes, Bytes)>,
+    pub setname: Option<Bytes>,
 }
 
 #[derive(Debug, Clone)]
 pub enum Command {
-    Unknown(String),
-    Ping,
+    Ping(Option<Bytes>),
     Save,
     Info(String),
-    Echo(String),
-    Keys(String),
-    Get(String),
+    Echo(Bytes),
+    Keys(Bytes),
+    Select(i64),
+    Move(Bytes, i64),
+    SwapDb(i64, i64),
+    FlushDb,
+    FlushAll,
+    DbSize,
+    Scan(ScanParams),
+    Get(Bytes),
     Set(SetParams),
+    SetNx(Bytes, Bytes),
+    GetSet(Bytes, Bytes),
+    GetDel(Bytes),
+    GetEx(Bytes, Option<Expiry>),
+    Append(Bytes, Bytes),
+    StrLen(Bytes),
+    GetRange(Bytes, i64, i64),
+    SetRange(Bytes, i64, Bytes),
+    MGet(Vec<Bytes>),
+    MSet(Vec<(Bytes, Bytes)>),
+    MSetNx(Vec<(Bytes, Bytes)>),
+    IncrBy(Bytes, i64),
+    IncrByFloat(Bytes, f64),
+    LPush(Bytes, Vec<Bytes>),
+    RPush(Bytes, Vec<Bytes>),
+    LPushX(Bytes, Vec<Bytes>),
+    RPushX(Bytes, Vec<Bytes>),
+    LPop(Bytes, Option<usize>),
+    RPop(Bytes, Option<usize>),
+    LRange(Bytes, i64, i64),
+    LLen(Bytes),
+    LIndex(Bytes, i64),
+    LSet(Bytes, i64, Bytes),
+    LInsert(LInsertParams),
+    LRem(Bytes, i64, Bytes),
+    LTrim(Bytes, i64, i64),
+    LPos(LPosParams),
+    LMove(Bytes, Bytes, ListEnd, ListEnd),
+    LMPop(Vec<Bytes>, ListEnd, usize),
+    // Blocking commands wait forever without a timeout
+    BLPop(Vec<Bytes>, Option<Duration>),
+    BRPop(Vec<Bytes>, Option<Duration>),
+    BLMove(Bytes, Bytes, ListEnd, ListEnd, Option<Duration>),
+    BLMPop(Vec<Bytes>, ListEnd, usize, Option<Duration>),
+    HSet(Bytes, Vec<(Bytes, Bytes)>),
+    HMSet(Bytes, Vec<(Bytes, Bytes)>),
+    HSetNx(Bytes, Bytes, Bytes),
+    HGet(Bytes, Bytes),
+    HMGet(Bytes, Vec<Bytes>),
+    HDel(Bytes, Vec<Bytes>),
+    HLen(Bytes),
+    HStrLen(Bytes, Bytes),
+    HExists(Bytes, Bytes),
+    HKeys(Bytes),
+    HVals(Bytes),
+    HGetAll(Bytes),
+    HIncrBy(Bytes, Bytes, i64),
+    HIncrByFloat(Bytes, Bytes, f64),
+    HScan(Bytes, ScanParams),
+    // The count, and whether values come along with the fields
+    HRandField(Bytes, Option<i64>, bool),
+    HExpire(ExpireParams, Vec<Bytes>),
+    HTtl(Bytes, Vec<Bytes>),
+    HPTtl(Bytes, Vec<Bytes>),
+    HPersist(Bytes, Vec<Bytes>),
+    SAdd(Bytes, Vec<Bytes>),
+    SRem(Bytes, Vec<Bytes>),
+    SMembers(Bytes),
+    SIsMember(Bytes, Bytes),
+    SMIsMember(Bytes, Vec<Bytes>),
+    SCard(Bytes),
+    SPop(Bytes, Option<usize>),
+    SRandMember(Bytes, Option<i64>),
+    SMove(Bytes, Bytes, Bytes),
+    SCombine(SetOp, Vec<Bytes>),
+    // The destination comes first
+    SCombineStore(SetOp, Bytes, Vec<Bytes>),
+    // A limit of 0 counts the whole intersection
+    SInterCard(Vec<Bytes>, usize),
+    SScan(Bytes, ScanParams),
+    // ZINCRBY too, as a ZADD with INCR
+    ZAdd(ZAddParams),
+    ZRem(Bytes, Vec<Bytes>),
+    ZScore(Bytes, Bytes),
+    ZMScore(Bytes, Vec<Bytes>),
+    ZCard(Bytes),
+    // Whether the score comes along with the rank
+    ZRank(Bytes, Bytes, bool),
+    ZRevRank(Bytes, Bytes, bool),
+    ZRange(ZRangeParams),
+    // The destination comes first
+    ZRangeStore(Bytes, ZRangeParams),
+    ZCount(Bytes, ZRangeBy),
+    ZRemRange(Bytes, ZRangeBy),
+    ZPopMin(Bytes, Option<usize>),
+    ZPopMax(Bytes, Option<usize>),
+    // Whether to pop the highest scores rather than the lowest
+    ZMPop(Vec<Bytes>, bool, usize),
+    ZCombine(ZCombineParams),
+    // The destination comes first
+    ZCombineStore(Bytes, ZCombineParams),
+    // A limit of 0 counts the whole intersection
+    ZInterCard(Vec<Bytes>, usize),
+    BZPopMin(Vec<Bytes>, Option<Duration>),
+    BZPopMax(Vec<Bytes>, Option<Duration>),
+    BZMPop(Vec<Bytes>, bool, usize, Option<Duration>),
+    Del(Vec<Bytes>),
+    Unlink(Vec<Bytes>),
+    Exists(Vec<Bytes>),
+    Type(Bytes),
+    Rename(Bytes, Bytes),
+    RenameNx(Bytes, Bytes),
+    Copy(CopyParams),
+    Touch(Vec<Bytes>),
+    Expire(ExpireParams),
+    Ttl(Bytes),
+    PTtl(Bytes),
+    ExpireTime(Bytes),
+    PExpireTime(Bytes),
+    Persist(Bytes),
     Config(String),
+    ConfigSet(Vec<(String, Bytes)>),
     Wait(u32, u32),
     ReplConf(String, String),
     PSync(String, String),
+    Hello(HelloParams),
+    CommandList,
+    CommandCount,
+    CommandInfo(Vec<Bytes>),
+    CommandDocs(Vec<Bytes>),
+    CommandGetKeys(Vec<Bytes>),
 }
 
-#[derive(Debug)]
 pub struct Request {
     pub command: Command,
-}
-
-#[derive(Debug)]
-pub struct SimpleString {
-    pub value: String,
-}
-
-#[derive(Debug)]
-pub struct RespInteger {
-    pub value: i64,
-}
-
-#[derive(Debug)]
-pub struct BulkString {
-    pub payload: Option<String>,
-}
-
-#[derive(Debug)]
-pub struct Array {
-    pub payload: Vec<BulkString>,
+    pub spec: &'static CommandSpec,
 }
 
 #[derive(Parser, Debug)]
@@ -68,246 +339,370 @@ pub struct Args {
 
     #[arg(long)]
     pub replicaof: Option<String>,
+
+    #[arg(long, default_value_t = 10)]
+    pub hz: i64,
+
+    #[arg(long, default_value_t = 16)]
+    pub databases: usize,
 }
 
-#[derive(Debug)]
-pub struct BaseError {
-    pub message: String,
+/// Errors replied to clients, each carrying the prefix Redis uses for its kind.
+#[derive(Debug, Error)]
+pub enum RedisError {
+    #[error("ERR {0}")]
+    Err(String),
+
+    #[error("ERR syntax error")]
+    Syntax,
+
+    #[error("ERR wrong number of arguments for '{0}' command")]
+    WrongArity(String),
+
+    #[error("ERR unknown command '{0}', with args beginning with: {1}")]
+    UnknownCommand(String, String),
+
+    #[error("ERR value is not an integer or out of range")]
+    NotInteger,
+
+    #[error("ERR value is not a valid float")]
+    NotFloat,
+
+    #[error("ERR invalid expire time in '{0}' command")]
+    InvalidExpireTime(String),
+
+    #[error("ERR {0}")]
+    Protocol(#[from] ProtocolError),
+
+    #[error("WRONGTYPE Operation against a key holding the wrong kind of value")]
+    WrongType,
+
+    #[error("NOAUTH Authentication required.")]
+    NoAuth,
+
+    #[error("WRONGPASS invalid username-password pair or user is disabled.")]
+    WrongPass,
+
+    #[error("NOPROTO unsupported protocol version")]
+    NoProto,
+
+    #[error("READONLY You can't write against a read only replica.")]
+    ReadOnly,
+
+    #[error("MOVED {0} {1}")]
+    Moved(u16, String),
+
+    #[error("EXECABORT Transaction discarded because of previous errors.")]
+    ExecAbort,
+
+    #[error(
+        "BUSY Redis is busy running a script. You can only call SCRIPT KILL or SHUTDOWN NOSCRIPT."
+    )]
+    Busy,
+
+    #[error("NOSCRIPT No matching script. Please use EVAL.")]
+    NoScript,
+}
+
+impl From<RedisError> for RespValue {
+    fn from(error: RedisError) -> RespValue {
+        RespValue::Error(error.to_string())
+    }
 }
 
-impl Into<Vec<u8>> for BaseError {
-    fn into(self) -> Vec<u8> {
-        let redis_err = format!("-{}", self.message);
-        redis_err.into_bytes()
+// Commands we send ourselves, to our master or replicas, always go out as RESP2
+impl From<RespValue> for Vec<u8> {
+    fn from(value: RespValue) -> Vec<u8> {
+        value.encode(Protocol::Resp2)
     }
 }
 
-impl Into<Vec<u8>> for Command {
-    fn into(self) -> Vec<u8> {
-        let mut bulk_strings = vec![];
+/// Any RESP value, used for every reply the server sends and decoded from replies it receives.
+///
+/// RESP3-only types are downgraded to their RESP2 equivalents the same way Redis does,
+/// e.g. maps become flat arrays and booleans become integers.
+#[derive(Debug, Clone)]
+pub enum RespValue {
+    SimpleString(String),
+    Error(String),
+    Integer(i64),
+    BulkString(Bytes),
+    Array(Vec<RespValue>),
+    Null,
+    // Encoded as *-1 rather than $-1 under RESP2
+    NullArray,
+    Map(Vec<(RespValue, RespValue)>),
+    Set(Vec<RespValue>),
+    Double(f64),
+    Boolean(bool),
+    BigNumber(String),
+    VerbatimString(String, Bytes),
+    Push(Vec<RespValue>),
+}
+
+impl RespValue {
+    pub fn bulk<T: Into<Bytes>>(value: T) -> RespValue {
+        RespValue::BulkString(value.into())
+    }
+
+    /// A command as sent over the wire, i.e. an array of bulk strings.
+    pub fn command<I, T>(args: I) -> RespValue
+    where
+        I: IntoIterator<Item = T>,
+        T: AsRef<[u8]>,
+    {
+        RespValue::Array(
+            args.into_iter()
+                .map(|arg| RespValue::BulkString(Bytes::copy_from_slice(arg.as_ref())))
+                .collect(),
+        )
+    }
+
+    /// Decodes one value from the start of `buf` along with the number of bytes it took.
+    ///
+    /// Returns `Ok(None)` when `buf` does not yet hold the complete value.
+    pub fn decode(buf: &[u8]) -> Result<Option<(RespValue, usize)>, ProtocolError> {
+        let mut pos = 0;
+        Ok(decode_value(buf, &mut pos)?.map(|value| (value, pos)))
+    }
+
+    pub fn encode(&self, protocol: Protocol) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        self.encode_into(protocol, &mut bytes);
+        bytes
+    }
+
+    fn encode_into(&self, protocol: Protocol, bytes: &mut Vec<u8>) {
+        let resp3 = protocol == Protocol::Resp3;
         match self {
-            Unknown(_) => panic!("Cannot convert an UNKNOWN command"),
-            Ping => bulk_strings.push(BulkString {
-                payload: Some("PING".to_string()),
-            }),
-            Save => bulk_strings.push(BulkString {
-                payload: Some("SAVE".to_string()),
-            }),
-            Info(key) => {
-                bulk_strings.push(BulkString {
-                    payload: Some("INFO".to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(key.clone()),
-                })
+            RespValue::SimpleString(value) => {
+                bytes.extend_from_slice(format!("+{}\r\n", single_line(value)).as_bytes())
             }
-            Echo(value) => {
-                bulk_strings.push(BulkString {
-                    payload: Some("ECHO".to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(value.clone()),
-                })
+            RespValue::Error(message) => {
+                bytes.extend_from_slice(format!("-{}\r\n", single_line(message)).as_bytes())
             }
-            Keys(pattern) => {
-                bulk_strings.push(BulkString {
-                    payload: Some("KEYS".to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(pattern.clone()),
-                })
+            RespValue::Integer(value) => {
+                bytes.extend_from_slice(format!(":{}\r\n", value).as_bytes())
             }
-            Get(key) => {
-                bulk_strings.push(BulkString {
-                    payload: Some("GET".to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(key.clone()),
-                })
+            RespValue::BulkString(payload) => {
+                bytes.extend_from_slice(format!("${}\r\n", payload.len()).as_bytes());
+                bytes.extend_from_slice(payload);
+                bytes.extend_from_slice(b"\r\n");
             }
-            Set(params) => {
-                bulk_strings.push(BulkString {
-                    payload: Some("SET".to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(params.key.clone()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(params.value.clone()),
-                });
-                if let Some(px) = params.px {
-                    bulk_strings.push(BulkString {
-                        payload: Some(px.to_string()),
-                    });
+            RespValue::Array(values) => encode_aggregate('*', values, protocol, bytes),
+            RespValue::Null if resp3 => bytes.extend_from_slice(b"_\r\n"),
+            RespValue::Null => bytes.extend_from_slice(b"$-1\r\n"),
+            RespValue::NullArray if resp3 => bytes.extend_from_slice(b"_\r\n"),
+            RespValue::NullArray => bytes.extend_from_slice(b"*-1\r\n"),
+            RespValue::Map(entries) => {
+                if resp3 {
+                    bytes.extend_from_slice(format!("%{}\r\n", entries.len()).as_bytes());
+                } else {
+                    bytes.extend_from_slice(format!("*{}\r\n", entries.len() * 2).as_bytes());
+                }
+                for (key, value) in entries {
+                    key.encode_into(protocol, bytes);
+                    value.encode_into(protocol, bytes);
                 }
             }
-            Config(key) => {
-                bulk_strings.push(BulkString {
-                    payload: Some("CONFIG".to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(key.clone()),
-                })
+            RespValue::Set(values) if resp3 => encode_aggregate('~', values, protocol, bytes),
+            RespValue::Set(values) => encode_aggregate('*', values, protocol, bytes),
+            RespValue::Double(value) if resp3 => {
+                bytes.extend_from_slice(format!(",{}\r\n", format_double(*value)).as_bytes())
+            }
+            RespValue::Double(value) => {
+                RespValue::bulk(format_double(*value)).encode_into(protocol, bytes)
+            }
+            RespValue::Boolean(value) if resp3 => {
+                bytes.extend_from_slice(if *value { b"#t\r\n" } else { b"#f\r\n" })
             }
-            ReplConf(key, value) => {
-                bulk_strings.push(BulkString {
-                    payload: Some("REPLCONF".to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(key.clone()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(value.clone()),
-                });
+            RespValue::Boolean(value) => {
+                RespValue::Integer(*value as i64).encode_into(protocol, bytes)
             }
-            PSync(repl_id, offset) => {
-                bulk_strings.push(BulkString {
-                    payload: Some("PSYNC".to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(repl_id.clone()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(offset.clone()),
-                });
+            RespValue::BigNumber(value) if resp3 => {
+                bytes.extend_from_slice(format!("({}\r\n", value).as_bytes())
             }
-            Wait(replicas, timeout) => {
-                bulk_strings.push(BulkString {
-                    payload: Some("WAIT".to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(replicas.to_string()),
-                });
-                bulk_strings.push(BulkString {
-                    payload: Some(timeout.to_string()),
-                });
+            RespValue::BigNumber(value) => {
+                RespValue::bulk(value.clone()).encode_into(protocol, bytes)
             }
+            RespValue::VerbatimString(format, payload) if resp3 => {
+                // Three letter format, a colon, then the text itself
+                bytes
+                    .extend_from_slice(format!("={}\r\n{}:", payload.len() + 4, format).as_bytes());
+                bytes.extend_from_slice(payload);
+                bytes.extend_from_slice(b"\r\n");
+            }
+            RespValue::VerbatimString(_, payload) => {
+                RespValue::BulkString(payload.clone()).encode_into(protocol, bytes)
+            }
+            RespValue::Push(values) if resp3 => encode_aggregate('>', values, protocol, bytes),
+            RespValue::Push(values) => encode_aggregate('*', values, protocol, bytes),
         }
-        let array = Array {
-            payload: bulk_strings,
-        };
-        array.into()
     }
 }
 
-impl Into<Vec<u8>> for SimpleString {
-    fn into(self) -> Vec<u8> {
-        format!("+{}\r\n", self.value).as_bytes().to_vec()
-    }
-}
+fn decode_value(buf: &[u8], pos: &mut usize) -> Result<Option<RespValue>, ProtocolError> {
+    let Some(line) = read_line(buf, pos) else {
+        return Ok(None);
+    };
+    let Some((&prefix, rest)) = line.split_first() else {
+        return Err(ProtocolError::UnexpectedType(' '));
+    };
+    let text = || {
+        std::str::from_utf8(rest)
+            .map(str::to_string)
+            .map_err(|_| ProtocolError::InvalidValue)
+    };
+
+    let value = match prefix {
+        b'+' => RespValue::SimpleString(text()?),
+        b'-' => RespValue::Error(text()?),
+        b':' => RespValue::Integer(text()?.parse().map_err(|_| ProtocolError::InvalidValue)?),
+        b'(' => RespValue::BigNumber(text()?),
+        b',' => RespValue::Double(text()?.parse().map_err(|_| ProtocolError::InvalidValue)?),
+        b'#' => match rest {
+            b"t" => RespValue::Boolean(true),
+            b"f" => RespValue::Boolean(false),
+            _ => return Err(ProtocolError::InvalidValue),
+        },
+        b'_' => RespValue::Null,
+        b'$' | b'=' => {
+            let length = parse_length(rest).ok_or(ProtocolError::InvalidBulkLength)?;
+            if length < 0 {
+                return Ok(Some(RespValue::Null));
+            }
 
-impl Into<Vec<u8>> for RespInteger {
-    fn into(self) -> Vec<u8> {
-        format!(":{}\r\n", self.value).as_bytes().to_vec()
-    }
-}
+            let length = length as usize;
+            if buf.len() < *pos + length + 2 {
+                return Ok(None);
+            }
+            let payload = Bytes::copy_from_slice(&buf[*pos..*pos + length]);
+            *pos += length + 2;
+
+            if prefix == b'$' {
+                RespValue::BulkString(payload)
+            } else if length >= 4 && payload[3] == b':' {
+                let format = String::from_utf8_lossy(&payload[..3]).into_owned();
+                RespValue::VerbatimString(format, payload.slice(4..))
+            } else {
+                return Err(ProtocolError::InvalidValue);
+            }
+        }
+        b'*' | b'~' | b'>' | b'%' => {
+            let count = parse_length(rest).ok_or(ProtocolError::InvalidMultibulkLength)?;
+            if count < 0 {
+                return Ok(Some(RespValue::NullArray));
+            }
 
-impl Into<Vec<u8>> for Array {
-    fn into(self) -> Vec<u8> {
-        let mut bytes = format!("*{}\r\n", self.payload.len()).as_bytes().to_vec();
+            // Maps hold two values per entry
+            let elements = if prefix == b'%' {
+                count.saturating_mul(2)
+            } else {
+                count
+            };
+            // The count is the peer's claim, so room is made for at most a modest number of
+            // values up front and the rest as they actually arrive
+            let mut values = Vec::with_capacity(elements.min(1024) as usize);
+            for _ in 0..elements {
+                match decode_value(buf, pos)? {
+                    Some(value) => values.push(value),
+                    None => return Ok(None),
+                }
+            }
 
-        for bulk_string in self.payload {
-            let bulk_string_bytes: Vec<u8> = bulk_string.into();
-            bytes.extend(bulk_string_bytes);
+            match prefix {
+                b'*' => RespValue::Array(values),
+                b'~' => RespValue::Set(values),
+                b'>' => RespValue::Push(values),
+                _ => {
+                    let mut values = values.into_iter();
+                    let mut entries = Vec::with_capacity(values.len() / 2);
+                    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                        entries.push((key, value));
+                    }
+                    RespValue::Map(entries)
+                }
+            }
         }
+        other => return Err(ProtocolError::UnexpectedType(other as char)),
+    };
 
-        bytes
-    }
+    Ok(Some(value))
 }
 
-impl Into<Vec<u8>> for BulkString {
-    fn into(self) -> Vec<u8> {
-        if let Some(payload) = self.payload {
-            let length = payload.len();
-            format!("{}{}\r\n{}\r\n", "$", length, payload).into_bytes()
-        } else {
-            "$-1\r\n".to_owned().into_bytes()
-        }
-    }
+// Status and error replies end at the first line break, and may echo what a client sent, so
+// line breaks within them become spaces as in Redis
+fn single_line(text: &str) -> String {
+    text.replace(['\r', '\n'], " ")
 }
 
-pub async fn to_command(
-    mut buf_stream: &mut BufStream<TcpStream>,
-    offset: &AtomicUsize,
-) -> anyhow::Result<Option<Request>> {
-    let mut read_so_far = 0;
-    if let Some((part, bytes_read)) = read_cmd_part(&mut buf_stream).await {
-        read_so_far += bytes_read;
-        let num_of_elems = u8::from_str(&part[1usize..2usize])
-            .with_context(|| "Expecting length of elements, such as *2")?;
-
-        let (_, bytes_read) = read_cmd_part(&mut buf_stream).await.unwrap();
-        read_so_far += bytes_read;
-
-        let (command, bytes_read) = read_cmd_part(&mut buf_stream)
-            .await
-            .with_context(|| "Expecting a base command, such as GET")?;
-        read_so_far += bytes_read;
-
-        let mut args = Vec::with_capacity((num_of_elems - 1) as usize);
-        for _ in 0..num_of_elems - 1 {
-            let (_, bytes_read) = read_cmd_part(&mut buf_stream).await.unwrap();
-            read_so_far += bytes_read;
-
-            let (arg, bytes_read) = read_cmd_part(&mut buf_stream).await.unwrap();
-            read_so_far += bytes_read;
-
-            args.push(arg.to_lowercase());
-        }
-
-        let command = match command.to_lowercase().as_str() {
-            "ping" => Ping,
-            "wait" => Wait(args[0].parse()?, args[1].parse()?),
-            "info" => Info(args[0].clone()),
-            "echo" => Echo(args[0].clone()),
-            "keys" => Keys(args[0].clone()),
-            "get" => Get(args[0].clone()),
-            "set" => Set(build_set_params(args)),
-            "config" => Config(args[1].clone()),
-            "replconf" => ReplConf(args[0].clone(), args[1].clone()),
-            "psync" => PSync(args[0].clone(), args[1].clone()),
-            unknown => Unknown(unknown.to_string()),
-        };
-
-        match command {
-            // REPLCONF is added after processing because reasons *shrug*
-            ReplConf(_, _) => {}
-            _ => {
-                offset.fetch_add(read_so_far, Ordering::Relaxed);
-            }
-        }
-
-        return Ok(Some(Request { command }));
+fn encode_aggregate(prefix: char, values: &[RespValue], protocol: Protocol, bytes: &mut Vec<u8>) {
+    bytes.extend_from_slice(format!("{}{}\r\n", prefix, values.len()).as_bytes());
+    for value in values {
+        value.encode_into(protocol, bytes);
     }
-    Ok(None)
 }
 
-fn build_set_params(args: Vec<String>) -> SetParams {
-    let mut px = None;
-    for i in 2..args.len() {
-        if args[i] == "px" {
-            px = Some(u32::from_str(args[i + 1].as_str()).unwrap());
-        }
+/// Formats a double the way Redis replies with them, including its spelling of infinities.
+///
+/// Integers short of 2^52 are printed as such. Other values get the fewest digits that still
+/// read back as the same double, switching to scientific notation past Redis' thresholds.
+pub fn format_double(value: f64) -> String {
+    if value.is_nan() {
+        return "nan".to_string();
+    }
+    if value.is_infinite() {
+        return if value > 0.0 { "inf" } else { "-inf" }.to_string();
+    }
+    if value == 0.0 {
+        return if value.is_sign_negative() { "-0" } else { "0" }.to_string();
+    }
+    if value.fract() == 0.0 && value.abs() < (1u64 << 52) as f64 {
+        return (value as i64).to_string();
     }
 
-    SetParams {
-        key: args[0].to_owned(),
-        value: args[1].to_owned(),
-        px,
+    // Rust's scientific notation gives the shortest digits, along with where the point goes
+    let scientific = format!("{:e}", value.abs());
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let digits = mantissa.replace('.', "");
+    let exponent: i32 = exponent.parse().unwrap();
+    // The power of ten of the last digit
+    let last = exponent - (digits.len() as i32 - 1);
+
+    let sign = if value < 0.0 { "-" } else { "" };
+    if last >= 0 && exponent < digits.len() as i32 + 7 {
+        format!("{}{}{}", sign, digits, "0".repeat(last as usize))
+    } else if last < 0 && (last > -7 || exponent.abs() < 4) {
+        let point = digits.len() as i32 + last;
+        if point > 0 {
+            let (whole, fraction) = digits.split_at(point as usize);
+            format!("{}{}.{}", sign, whole, fraction)
+        } else {
+            format!("{}0.{}{}", sign, "0".repeat(-point as usize), digits)
+        }
+    } else {
+        let (first, rest) = digits.split_at(1);
+        let point = if rest.is_empty() { "" } else { "." };
+        let exponent_sign = if exponent < 0 { "-" } else { "+" };
+        format!(
+            "{}{}{}{}e{}{}",
+            sign,
+            first,
+            point,
+            rest,
+            exponent_sign,
+            exponent.abs()
+        )
     }
 }
 
-async fn read_cmd_part(buf_stream: &mut BufStream<TcpStream>) -> Option<(String, usize)> {
-    let mut command = "".to_owned();
-    let bytes_read = buf_stream
-        .read_line(&mut command)
-        .await
-        .expect("Failed to read from stream");
-
-    if bytes_read > 0 {
-        // Remove \r\n
-        Some((command[0..command.len() - 2].to_owned(), bytes_read))
-    } else {
-        None
+/// Builds a command out of a decoded request. Null or empty multibulks yield `None`.
+pub fn to_command(frame: Frame) -> Result<Option<Request>, RedisError> {
+    if frame.args.is_empty() {
+        return Ok(None);
     }
+
+    // Keys and values are kept verbatim, only keywords are matched case-insensitively
+    let (spec, command) = commands::parse(&frame.args)?;
+    Ok(Some(Request { command, spec }))
 }
@@ -1,281 +1,1195 @@
+use crate::blocking::BlockedOp;
+use crate::commands;
+use crate::commands::parse_integer;
+use crate::commands::{CommandSpec, COMMAND_TABLE};
+use crate::connection::Connection;
+use crate::db::{Entry, Value};
+use crate::glob;
 use crate::models::*;
-use crate::replication::MasterReplicationInfo;
-use anyhow::Context;
+use crate::rdb;
+use crate::replication::Replica;
+use crate::resp::Protocol;
+use crate::server::Server;
 use base64::{engine::general_purpose, Engine as _};
-use dashmap::DashMap;
-use std::ops::Add;
-use std::sync::atomic::{AtomicUsize, Ordering};
+use bytes::Bytes;
 use std::sync::Arc;
-use std::time::{Duration, SystemTime};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
-use tokio::net::TcpStream;
-use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncWrite, AsyncWriteExt};
 
+mod hashes;
+mod lists;
+mod sets;
+mod zsets;
+
+// Version reported to clients, matching the Redis feature set we emulate
+pub const REDIS_VERSION: &str = "7.4.0";
+
+// Largest string a client may build up, the default proto-max-bulk-len
+const MAX_STRING_LENGTH: usize = 512 * 1024 * 1024;
+
+/// Executes `request`, appending its reply to `replies`.
 pub async fn process_command(
-    command: Command,
-    args: &Arc<Args>,
-    rep_ref: &Arc<MasterReplicationInfo>,
-    map: &Arc<DashMap<String, (String, Option<SystemTime>)>>,
-    buf_stream: Arc<Mutex<TcpStream>>,
-    replicas: &Arc<Mutex<Vec<Arc<Mutex<TcpStream>>>>>,
-    tx: &Arc<Sender<Command>>,
-    rx: &Arc<Mutex<Receiver<Command>>>,
-    command_offset: &AtomicUsize,
+    request: Request,
+    server: &Arc<Server>,
+    connection: &mut Connection,
+    replies: &mut Vec<u8>,
 ) {
+    let command = request.command;
     println!(
         "Processing {:?} as replica: {}",
         command,
-        args.replicaof.is_some()
+        server.args.replicaof.is_some()
     );
-    let mut guard = buf_stream.lock().await;
+
+    // Not a regular reply, the snapshot follows it directly on the stream
+    if let Command::PSync(_, _) = command {
+        return full_resync(server, connection, replies).await;
+    }
+
+    // Only writes streamed from our master may change a replica's dataset
+    if request.spec.is_write() && !server.is_master() && !connection.from_master {
+        return reply(replies, connection, RedisError::ReadOnly.into());
+    }
+
+    let value = execute(command, server, connection)
+        .await
+        .unwrap_or_else(RespValue::from);
+    serve_blocked(server);
+    reply(replies, connection, value);
+}
+
+/// Serves clients blocked on keys the last command made ready, longest waiting first.
+///
+/// Runs before any other command, so nobody can take what was pushed from under them.
+fn serve_blocked(server: &Server) {
+    // Serving a client can make more keys ready, as BLMOVE pushes onto its destination
+    while let Some((index, key)) = server.blocked.next_ready() {
+        loop {
+            // Clients after another type than the key holds are skipped, and keep waiting
+            let is_zset = match live_entry(server, index, &key).map(|entry| entry.value) {
+                Some(Value::List(_)) => false,
+                Some(Value::ZSet(_)) => true,
+                _ => break,
+            };
+            let fits = |op: &BlockedOp| op.is_zset() == is_zset;
+            let Some(waiter) = server.blocked.first_waiter(index, &key, fits) else {
+                break;
+            };
+            let reply = if is_zset {
+                zsets::serve(server, &waiter, &key)
+            } else {
+                lists::serve(server, &waiter, &key)
+            };
+            let Some(reply) = reply else {
+                break;
+            };
+            server.blocked.unregister(&waiter);
+            waiter.serve(reply);
+        }
+    }
+}
+
+/// Blocks the client on `keys` until a later write lets `op` through, replying with a null
+/// array if `timeout` passes first.
+///
+/// The client is unblocked when the future is dropped, as happens when it disconnects.
+async fn block(
+    server: &Server,
+    index: usize,
+    keys: Vec<Bytes>,
+    op: BlockedOp,
+    timeout: Option<Duration>,
+) -> Result<RespValue, RedisError> {
+    let (_registration, reply) = server.blocked.register(index, keys, op);
+    let reply = match timeout {
+        Some(timeout) => tokio::time::timeout(timeout, reply).await.ok(),
+        None => Some(reply.await),
+    };
+    Ok(reply.and_then(Result::ok).unwrap_or(RespValue::NullArray))
+}
+
+async fn execute(
+    command: Command,
+    server: &Arc<Server>,
+    connection: &mut Connection,
+) -> Result<RespValue, RedisError> {
+    let args = &server.args;
+    let index = connection.db;
+    let db = server.db(index);
+
     match command {
-        Command::Wait(num_replicas, timeout) => {
-            write_and_flush(
-                &mut guard,
-                RespInteger {
-                    value: replicas.lock().await.len() as i64,
-                },
-            )
-            .await;
-        }
-        Command::Config(field) => match field.as_str() {
-            "dir" => {
-                let array = Array {
-                    payload: vec![
-                        BulkString {
-                            payload: Some("dir".to_owned()),
-                        },
-                        BulkString {
-                            payload: Some(args.dir.clone().unwrap()),
-                        },
-                    ],
-                };
-
-                write_and_flush(&mut guard, array).await;
-            }
-            "dbfilename" => {
-                let array = Array {
-                    payload: vec![
-                        BulkString {
-                            payload: Some("dbfilename".to_owned()),
-                        },
-                        BulkString {
-                            payload: Some(args.dbfilename.clone().unwrap()),
-                        },
-                    ],
-                };
-
-                write_and_flush(&mut guard, array).await;
+        Command::Wait(_num_replicas, _timeout) => {
+            let replica_count = server.replicas.lock().await.len() as i64;
+            Ok(RespValue::Integer(replica_count))
+        }
+        Command::Config(field) => {
+            let value = match field.as_str() {
+                "dir" => args.dir.clone(),
+                "dbfilename" => args.dbfilename.clone(),
+                "hz" => Some(server.hz().to_string()),
+                _ => None,
+            };
+
+            // Unknown parameters simply don't show up in the reply
+            let entries = value
+                .map(|value| (RespValue::bulk(field), RespValue::bulk(value)))
+                .into_iter()
+                .collect();
+            Ok(RespValue::Map(entries))
+        }
+        Command::ConfigSet(params) => {
+            // Every parameter is validated before any of them is applied
+            let mut hz = None;
+            for (name, value) in &params {
+                match name.as_str() {
+                    "hz" => {
+                        hz = Some(parse_integer(value).map_err(|_| {
+                            RedisError::Err(format!(
+                                "CONFIG SET failed (possibly related to argument '{}') - argument couldn't be parsed into an integer",
+                                name
+                            ))
+                        })?)
+                    }
+                    _ => {
+                        return Err(RedisError::Err(format!(
+                            "Unknown option or number of arguments for CONFIG SET - '{}'",
+                            name
+                        )))
+                    }
+                }
             }
-            unknown => {
-                write_and_flush(
-                    &mut guard,
-                    BaseError {
-                        message: format!("Config key '{}' unknown", unknown),
-                    },
-                )
-                .await;
+
+            if let Some(hz) = hz {
+                server.set_hz(hz);
             }
-        },
+            Ok(RespValue::SimpleString("OK".to_string()))
+        }
         Command::Info(_) => {
-            let master_or_slave = if is_master(&args) { "master" } else { "slave" };
-
-            let replication = BulkString {
-                payload: Some(
-                    format!(
-                        "role:{}\rmaster_replid:{}\rmaster_repl_offset:{}",
-                        master_or_slave, rep_ref.replid, rep_ref.repl_offset
-                    )
-                    .to_string(),
-                ),
+            let master_or_slave = if server.is_master() {
+                "master"
+            } else {
+                "slave"
+            };
+
+            Ok(RespValue::bulk(format!(
+                "role:{}\rmaster_replid:{}\rmaster_repl_offset:{}",
+                master_or_slave, server.rep_info.replid, server.rep_info.repl_offset
+            )))
+        }
+        Command::Ping(message) => Ok(match message {
+            Some(message) => RespValue::BulkString(message),
+            None => RespValue::SimpleString("PONG".to_string()),
+        }),
+        Command::Echo(message) => Ok(RespValue::BulkString(message)),
+        Command::Get(key) => Ok(match live_string(server, index, &key)? {
+            Some((value, _)) => RespValue::BulkString(value),
+            None => RespValue::Null,
+        }),
+        Command::Set(params) => {
+            let get = params.get;
+            let (written, previous) = set(server, index, params)?;
+            Ok(match (get, previous) {
+                (true, Some(previous)) => RespValue::BulkString(previous),
+                (true, None) => RespValue::Null,
+                (false, _) if written => RespValue::SimpleString("OK".to_string()),
+                (false, _) => RespValue::Null,
+            })
+        }
+        Command::SetNx(key, value) => {
+            let params = SetParams {
+                key,
+                value,
+                expiry: None,
+                condition: Some(SetCondition::Nx),
+                get: false,
+            };
+            let (written, _) = set(server, index, params)?;
+            Ok(RespValue::Integer(written as i64))
+        }
+        Command::GetSet(key, value) => {
+            let params = SetParams {
+                key,
+                value,
+                expiry: None,
+                condition: None,
+                get: true,
+            };
+            let (_, previous) = set(server, index, params)?;
+            Ok(previous.map_or(RespValue::Null, RespValue::BulkString))
+        }
+        Command::GetDel(key) => {
+            let Some((value, _)) = live_string(server, index, &key)? else {
+                return Ok(RespValue::Null);
             };
-            write_and_flush(&mut guard, replication).await;
+            db.remove(&key);
+            server.replicate(index, vec![Bytes::from_static(b"DEL"), key]);
+            Ok(RespValue::BulkString(value))
         }
-        Command::Ping => {
-            if is_master(args) {
-                write_and_flush(&mut guard, "+PONG\r\n").await;
+        Command::GetEx(key, expiry) => {
+            let expire_at = match expiry {
+                None | Some(Expiry::Persist) => None,
+                Some(expiry) => Some(expiry_deadline(expiry, "getex")?),
+            };
+            let Some((value, current)) = live_string(server, index, &key)? else {
+                return Ok(RespValue::Null);
+            };
+
+            if let Some(expire_at) = expire_at {
+                expire_key(server, index, key, expire_at);
+            } else if expiry == Some(Expiry::Persist) && current.is_some() {
+                db.set_expiry(&key, None);
+                server.replicate(index, vec![Bytes::from_static(b"PERSIST"), key]);
             }
+            Ok(RespValue::BulkString(value))
         }
-        Command::Echo(ref message) => {
-            write_and_flush(
-                &mut guard,
-                BulkString {
-                    payload: Some(message.to_string()),
-                },
-            )
-            .await;
-        }
-        Command::Get(ref key) => {
-            let result = map.get(key);
-            if let Some(result) = result {
-                let value = match result.to_owned().1 {
-                    Some(expire_at) => {
-                        let now = SystemTime::now();
-                        if now >= expire_at {
-                            None
-                        } else {
-                            Some(result.to_owned().0)
-                        }
-                    }
-                    None => Some(result.to_owned().0),
-                };
+        Command::Append(key, value) => {
+            let (mut current, expire_at) = match live_string(server, index, &key)? {
+                Some((current, expire_at)) => (current.to_vec(), expire_at),
+                None => (Vec::new(), None),
+            };
+            check_string_length(current.len() + value.len())?;
 
-                write_and_flush(&mut guard, BulkString { payload: value }).await;
-            } else {
-                write_and_flush(&mut guard, BulkString { payload: None }).await;
+            current.extend_from_slice(&value);
+            let length = current.len();
+            let current = Value::String(Bytes::from(current));
+            db.insert(key.clone(), Entry::new(current, expire_at));
+            server.replicate(index, vec![Bytes::from_static(b"APPEND"), key, value]);
+            Ok(RespValue::Integer(length as i64))
+        }
+        Command::StrLen(key) => Ok(RespValue::Integer(
+            live_string(server, index, &key)?.map_or(0, |(value, _)| value.len() as i64),
+        )),
+        Command::GetRange(key, start, end) => {
+            let value = live_string(server, index, &key)?
+                .map(|(value, _)| value)
+                .unwrap_or_default();
+            let length = value.len() as i64;
+
+            // Negative offsets count from the end, and whatever is out of bounds is clamped
+            if start < 0 && end < 0 && start > end {
+                return Ok(RespValue::bulk(""));
+            }
+            let start = if start < 0 { length + start } else { start }.max(0);
+            let end = if end < 0 { length + end } else { end }
+                .max(0)
+                .min(length - 1);
+            if length == 0 || start > end {
+                return Ok(RespValue::bulk(""));
             }
+            Ok(RespValue::BulkString(
+                value.slice(start as usize..end as usize + 1),
+            ))
         }
-        Command::Set(ref params) => {
-            let value = params.value.to_string();
-            let expire_at = match params.px {
-                Some(_) => {
-                    Some(SystemTime::now().add(Duration::from_millis(params.px.unwrap() as u64)))
-                }
-                None => None,
+        Command::SetRange(key, offset, value) => {
+            if offset < 0 {
+                return Err(RedisError::Err("offset is out of range".to_string()));
+            }
+            let existing = live_string(server, index, &key)?;
+            // Writing nothing leaves the string as it is, however far the offset
+            if value.is_empty() {
+                let length = existing.map_or(0, |(current, _)| current.len());
+                return Ok(RespValue::Integer(length as i64));
+            }
+            check_string_length(offset as usize + value.len())?;
+
+            let (mut current, expire_at) = match existing {
+                Some((current, expire_at)) => (current.to_vec(), expire_at),
+                None => (Vec::new(), None),
             };
 
-            map.insert(params.key.to_string(), (value, expire_at));
-            tx.send(command.clone())
-                .await
-                .expect("Failed to send Command to TX");
-
-            if is_master(args) {
-                write_and_flush(
-                    &mut guard,
-                    BulkString {
-                        payload: Some("OK".to_string()),
-                    },
-                )
-                .await;
+            // Any gap between the end of the string and the offset is zero-padded
+            let (offset, end) = (offset as usize, offset as usize + value.len());
+            if current.len() < end {
+                current.resize(end, 0);
             }
+            current[offset..end].copy_from_slice(&value);
+
+            let length = current.len();
+            let current = Value::String(Bytes::from(current));
+            db.insert(key.clone(), Entry::new(current, expire_at));
+            server.replicate(
+                index,
+                vec![
+                    Bytes::from_static(b"SETRANGE"),
+                    key,
+                    Bytes::from(offset.to_string()),
+                    value,
+                ],
+            );
+            Ok(RespValue::Integer(length as i64))
+        }
+        // Keys holding anything but a string read as missing
+        Command::MGet(keys) => Ok(RespValue::Array(
+            keys.iter()
+                .map(|key| match live_entry(server, index, key) {
+                    Some(Entry {
+                        value: Value::String(value),
+                        ..
+                    }) => RespValue::BulkString(value),
+                    _ => RespValue::Null,
+                })
+                .collect(),
+        )),
+        Command::MSet(pairs) => {
+            mset(server, index, "MSET", pairs);
+            Ok(RespValue::SimpleString("OK".to_string()))
         }
-        Command::Keys(_) => {
-            // Assuming a wildcard ('*') for now
-            let bulk_strings = map
+        // Nothing is written unless none of the keys exist
+        Command::MSetNx(pairs) => {
+            if pairs
                 .iter()
-                .map(|e| BulkString {
-                    payload: Some(e.key().to_string()),
+                .any(|(key, _)| live_entry(server, index, key).is_some())
+            {
+                return Ok(RespValue::Integer(0));
+            }
+            mset(server, index, "MSETNX", pairs);
+            Ok(RespValue::Integer(1))
+        }
+        Command::IncrBy(key, increment) => {
+            let existing = live_string(server, index, &key)?;
+            let current = match &existing {
+                Some((current, _)) => parse_counter(current).ok_or(RedisError::NotInteger)?,
+                None => 0,
+            };
+            let value = current.checked_add(increment).ok_or_else(|| {
+                RedisError::Err("increment or decrement would overflow".to_string())
+            })?;
+
+            let expire_at = existing.and_then(|(_, expire_at)| expire_at);
+            let counter = Value::String(Bytes::from(value.to_string()));
+            db.insert(key.clone(), Entry::new(counter, expire_at));
+            server.replicate(
+                index,
+                vec![
+                    Bytes::from_static(b"INCRBY"),
+                    key,
+                    Bytes::from(increment.to_string()),
+                ],
+            );
+            Ok(RespValue::Integer(value))
+        }
+        Command::IncrByFloat(key, increment) => {
+            let existing = live_string(server, index, &key)?;
+            let current = match &existing {
+                Some((current, _)) => commands::parse_float(current)?,
+                None => 0.0,
+            };
+            let value = current + increment;
+            if !value.is_finite() {
+                return Err(RedisError::Err(
+                    "increment would produce NaN or Infinity".to_string(),
+                ));
+            }
+
+            let value = Bytes::from(format_float(value));
+            let expire_at = existing.and_then(|(_, expire_at)| expire_at);
+            db.insert(
+                key.clone(),
+                Entry::new(Value::String(value.clone()), expire_at),
+            );
+
+            // Replicas get the result rather than redoing the float math themselves
+            server.replicate(
+                index,
+                vec![
+                    Bytes::from_static(b"SET"),
+                    key,
+                    value.clone(),
+                    Bytes::from_static(b"KEEPTTL"),
+                ],
+            );
+            Ok(RespValue::BulkString(value))
+        }
+        Command::LPush(key, elements) => {
+            lists::push(server, index, key, elements, ListEnd::Left, false)
+        }
+        Command::RPush(key, elements) => {
+            lists::push(server, index, key, elements, ListEnd::Right, false)
+        }
+        Command::LPushX(key, elements) => {
+            lists::push(server, index, key, elements, ListEnd::Left, true)
+        }
+        Command::RPushX(key, elements) => {
+            lists::push(server, index, key, elements, ListEnd::Right, true)
+        }
+        Command::LPop(key, count) => lists::pop(server, index, key, ListEnd::Left, count),
+        Command::RPop(key, count) => lists::pop(server, index, key, ListEnd::Right, count),
+        Command::LRange(key, start, stop) => lists::range(server, index, key, start, stop),
+        Command::LLen(key) => lists::len(server, index, key),
+        Command::LIndex(key, position) => lists::index(server, index, key, position),
+        Command::LSet(key, position, element) => lists::set(server, index, key, position, element),
+        Command::LInsert(params) => lists::insert(server, index, params),
+        Command::LRem(key, count, element) => lists::rem(server, index, key, count, element),
+        Command::LTrim(key, start, stop) => lists::trim(server, index, key, start, stop),
+        Command::LPos(params) => lists::pos(server, index, params),
+        Command::LMove(source, destination, from, to) => {
+            lists::lmove(server, index, source, destination, from, to)
+        }
+        Command::LMPop(keys, end, count) => lists::mpop(server, index, keys, end, count),
+        Command::HSet(key, pairs) => {
+            let added = hashes::set(server, index, key, pairs)?;
+            Ok(RespValue::Integer(added as i64))
+        }
+        Command::HMSet(key, pairs) => {
+            hashes::set(server, index, key, pairs)?;
+            Ok(RespValue::SimpleString("OK".to_string()))
+        }
+        Command::HSetNx(key, field, value) => hashes::setnx(server, index, key, field, value),
+        Command::HGet(key, field) => hashes::get(server, index, key, field),
+        Command::HMGet(key, fields) => hashes::mget(server, index, key, fields),
+        Command::HDel(key, fields) => hashes::del(server, index, key, fields),
+        Command::HLen(key) => hashes::len(server, index, key),
+        Command::HStrLen(key, field) => hashes::strlen(server, index, key, field),
+        Command::HExists(key, field) => hashes::exists(server, index, key, field),
+        Command::HKeys(key) => hashes::all(server, index, key, true, false),
+        Command::HVals(key) => hashes::all(server, index, key, false, true),
+        Command::HGetAll(key) => hashes::all(server, index, key, true, true),
+        Command::HIncrBy(key, field, increment) => {
+            hashes::incr_by(server, index, key, field, increment)
+        }
+        Command::HIncrByFloat(key, field, increment) => {
+            hashes::incr_by_float(server, index, key, field, increment)
+        }
+        Command::HScan(key, params) => hashes::scan(server, index, key, params),
+        Command::HRandField(key, count, withvalues) => {
+            let protocol = connection.protocol;
+            hashes::rand_field(server, index, key, count, withvalues, protocol)
+        }
+        Command::HExpire(params, fields) => hashes::expire(server, index, params, fields),
+        Command::HTtl(key, fields) => hashes::ttl(server, index, key, fields, false),
+        Command::HPTtl(key, fields) => hashes::ttl(server, index, key, fields, true),
+        Command::HPersist(key, fields) => hashes::persist(server, index, key, fields),
+        Command::SAdd(key, members) => sets::add(server, index, key, members),
+        Command::SRem(key, members) => sets::rem(server, index, key, members),
+        Command::SMembers(key) => sets::members(server, index, key),
+        Command::SIsMember(key, member) => sets::is_member(server, index, key, member),
+        Command::SMIsMember(key, members) => sets::mis_member(server, index, key, members),
+        Command::SCard(key) => sets::card(server, index, key),
+        Command::SPop(key, count) => sets::pop(server, index, key, count),
+        Command::SRandMember(key, count) => sets::rand_member(server, index, key, count),
+        Command::SMove(source, destination, member) => {
+            sets::smove(server, index, source, destination, member)
+        }
+        Command::SCombine(op, keys) => sets::combine(server, index, op, keys),
+        Command::SCombineStore(op, destination, keys) => {
+            sets::combine_store(server, index, op, destination, keys)
+        }
+        Command::SInterCard(keys, limit) => sets::inter_card(server, index, keys, limit),
+        Command::SScan(key, params) => sets::scan(server, index, key, params),
+        Command::ZAdd(params) => zsets::add(server, index, params),
+        Command::ZRem(key, members) => zsets::rem(server, index, key, members),
+        Command::ZScore(key, member) => zsets::score(server, index, key, member),
+        Command::ZMScore(key, members) => zsets::mscore(server, index, key, members),
+        Command::ZCard(key) => zsets::card(server, index, key),
+        Command::ZRank(key, member, withscore) => {
+            zsets::rank(server, index, key, member, withscore, false)
+        }
+        Command::ZRevRank(key, member, withscore) => {
+            zsets::rank(server, index, key, member, withscore, true)
+        }
+        Command::ZRange(params) => zsets::range(server, index, params, connection.protocol),
+        Command::ZRangeStore(destination, params) => {
+            zsets::range_store(server, index, destination, params)
+        }
+        Command::ZCount(key, by) => zsets::count(server, index, key, by),
+        Command::ZRemRange(key, by) => zsets::rem_range(server, index, key, by),
+        Command::ZPopMin(key, count) => {
+            zsets::pop(server, index, key, false, count, connection.protocol)
+        }
+        Command::ZPopMax(key, count) => {
+            zsets::pop(server, index, key, true, count, connection.protocol)
+        }
+        Command::ZMPop(keys, max, count) => zsets::mpop(server, index, keys, max, count),
+        Command::ZCombine(params) => zsets::combine(server, index, params, connection.protocol),
+        Command::ZCombineStore(destination, params) => {
+            zsets::combine_store(server, index, destination, params)
+        }
+        Command::ZInterCard(keys, limit) => zsets::inter_card(server, index, keys, limit),
+        Command::BZPopMin(keys, timeout) => zsets::bpop(server, index, keys, false, timeout).await,
+        Command::BZPopMax(keys, timeout) => zsets::bpop(server, index, keys, true, timeout).await,
+        Command::BZMPop(keys, max, count, timeout) => {
+            zsets::bmpop(server, index, keys, max, count, timeout).await
+        }
+        Command::BLPop(keys, timeout) => {
+            lists::bpop(server, index, keys, ListEnd::Left, timeout).await
+        }
+        Command::BRPop(keys, timeout) => {
+            lists::bpop(server, index, keys, ListEnd::Right, timeout).await
+        }
+        Command::BLMove(source, destination, from, to, timeout) => {
+            lists::blmove(server, index, source, destination, from, to, timeout).await
+        }
+        Command::BLMPop(keys, end, count, timeout) => {
+            lists::bmpop(server, index, keys, end, count, timeout).await
+        }
+        Command::Del(keys) => Ok(RespValue::Integer(delete_keys(server, index, "DEL", keys))),
+        // Values are freed along with the entry either way, so there's nothing to do lazily
+        Command::Unlink(keys) => Ok(RespValue::Integer(delete_keys(
+            server, index, "UNLINK", keys,
+        ))),
+        // Keys given more than once are counted each time
+        Command::Exists(keys) | Command::Touch(keys) => Ok(RespValue::Integer(
+            keys.iter()
+                .filter(|key| live_entry(server, index, key).is_some())
+                .count() as i64,
+        )),
+        Command::Type(key) => Ok(RespValue::SimpleString(
+            live_entry(server, index, &key)
+                .map_or("none", |entry| entry.type_name())
+                .to_string(),
+        )),
+        Command::Rename(source, destination) => rename(server, index, source, destination, false),
+        Command::RenameNx(source, destination) => rename(server, index, source, destination, true),
+        Command::Copy(params) => {
+            let target = match params.db {
+                Some(target) => server.db_index(target)?,
+                None => index,
+            };
+            if params.source == params.destination && target == index {
+                return Err(RedisError::Err(
+                    "source and destination objects are the same".to_string(),
+                ));
+            }
+
+            let Some(entry) = live_entry(server, index, &params.source) else {
+                return Ok(RespValue::Integer(0));
+            };
+            if !params.replace && live_entry(server, target, &params.destination).is_some() {
+                return Ok(RespValue::Integer(0));
+            }
+
+            // The copy expires along with the original
+            server.db(target).insert(params.destination.clone(), entry);
+            server.blocked.signal(target, &params.destination);
+
+            let mut argv = vec![
+                Bytes::from_static(b"COPY"),
+                params.source,
+                params.destination,
+            ];
+            if params.db.is_some() {
+                argv.push(Bytes::from_static(b"DB"));
+                argv.push(Bytes::from(target.to_string()));
+            }
+            if params.replace {
+                argv.push(Bytes::from_static(b"REPLACE"));
+            }
+            server.replicate(index, argv);
+
+            Ok(RespValue::Integer(1))
+        }
+        Command::Expire(params) => {
+            let command = match params.expiry {
+                Expiry::Ex(_) => "expire",
+                Expiry::Px(_) => "pexpire",
+                Expiry::ExAt(_) => "expireat",
+                _ => "pexpireat",
+            };
+            let expire_at = expiry_deadline(params.expiry, command)?;
+            let Some(entry) = live_entry(server, index, &params.key) else {
+                return Ok(RespValue::Integer(0));
+            };
+
+            // A key without an expiry counts as never expiring for GT and LT
+            let allowed = match entry.expire_at {
+                Some(current) => {
+                    !params.nx
+                        && (!params.gt || expire_at > current)
+                        && (!params.lt || expire_at < current)
+                }
+                None => !params.xx && !params.gt,
+            };
+            if !allowed {
+                return Ok(RespValue::Integer(0));
+            }
+
+            expire_key(server, index, params.key, expire_at);
+            Ok(RespValue::Integer(1))
+        }
+        Command::Ttl(key) => Ok(RespValue::Integer(match expiry_of(server, index, &key) {
+            Ok(expire_at) => (millis_until(expire_at) + 500) / 1000,
+            Err(code) => code,
+        })),
+        Command::PTtl(key) => Ok(RespValue::Integer(match expiry_of(server, index, &key) {
+            Ok(expire_at) => millis_until(expire_at),
+            Err(code) => code,
+        })),
+        Command::ExpireTime(key) => Ok(RespValue::Integer(match expiry_of(server, index, &key) {
+            Ok(expire_at) => (unix_millis(expire_at) + 500) / 1000,
+            Err(code) => code,
+        })),
+        Command::PExpireTime(key) => Ok(RespValue::Integer(match expiry_of(server, index, &key) {
+            Ok(expire_at) => unix_millis(expire_at),
+            Err(code) => code,
+        })),
+        Command::Persist(key) => {
+            let persisted =
+                live_entry(server, index, &key).is_some_and(|entry| entry.expire_at.is_some());
+            if persisted {
+                db.set_expiry(&key, None);
+                server.replicate(index, vec![Bytes::from_static(b"PERSIST"), key]);
+            }
+            Ok(RespValue::Integer(persisted as i64))
+        }
+        Command::Keys(pattern) => {
+            let keys = db
+                .keys()
+                .into_iter()
+                .filter(|key| glob::matches(&pattern, key))
+                .map(RespValue::BulkString)
+                .collect();
+
+            Ok(RespValue::Array(keys))
+        }
+        Command::Scan(params) => {
+            let (cursor, keys) = db.scan(params.cursor, params.count);
+
+            // Filtering happens after the keys were picked, so a page may well come back empty
+            let keys = keys
+                .into_iter()
+                .filter(|key| {
+                    params
+                        .pattern
+                        .as_ref()
+                        .is_none_or(|pattern| glob::matches(pattern, key))
+                })
+                .filter_map(|key| {
+                    let entry = live_entry(server, index, &key)?;
+                    let wanted_type = params
+                        .type_name
+                        .as_ref()
+                        .is_none_or(|type_name| type_name == entry.type_name());
+                    wanted_type.then_some(RespValue::BulkString(key))
                 })
                 .collect();
 
-            write_and_flush(
-                &mut guard,
-                Array {
-                    payload: bulk_strings,
-                },
-            )
-            .await;
+            Ok(RespValue::Array(vec![
+                RespValue::bulk(cursor.to_string()),
+                RespValue::Array(keys),
+            ]))
+        }
+        Command::Select(target) => {
+            connection.db = server.db_index(target)?;
+            Ok(RespValue::SimpleString("OK".to_string()))
+        }
+        Command::Move(key, target) => {
+            let target = server.db_index(target)?;
+            if target == index {
+                return Err(RedisError::Err(
+                    "source and destination objects are the same".to_string(),
+                ));
+            }
+
+            let Some(entry) = live_entry(server, index, &key) else {
+                return Ok(RespValue::Integer(0));
+            };
+            if live_entry(server, target, &key).is_some() {
+                return Ok(RespValue::Integer(0));
+            }
+
+            db.remove(&key);
+            server.db(target).insert(key.clone(), entry);
+            server.blocked.signal(target, &key);
+            server.replicate(
+                index,
+                vec![
+                    Bytes::from_static(b"MOVE"),
+                    key,
+                    Bytes::from(target.to_string()),
+                ],
+            );
+            Ok(RespValue::Integer(1))
+        }
+        Command::SwapDb(first, second) => {
+            let (first, second) = (server.db_index(first)?, server.db_index(second)?);
+            server.swap_dbs(first, second);
+            server.blocked.signal_all(first);
+            server.blocked.signal_all(second);
+            server.replicate(
+                index,
+                vec![
+                    Bytes::from_static(b"SWAPDB"),
+                    Bytes::from(first.to_string()),
+                    Bytes::from(second.to_string()),
+                ],
+            );
+            Ok(RespValue::SimpleString("OK".to_string()))
+        }
+        Command::FlushDb => {
+            db.clear();
+            server.replicate(index, vec![Bytes::from_static(b"FLUSHDB")]);
+            Ok(RespValue::SimpleString("OK".to_string()))
+        }
+        Command::FlushAll => {
+            for target in 0..server.db_count() {
+                server.db(target).clear();
+            }
+            server.replicate(index, vec![Bytes::from_static(b"FLUSHALL")]);
+            Ok(RespValue::SimpleString("OK".to_string()))
         }
-        Command::Unknown(_) => {
-            eprintln!("");
+        Command::DbSize => Ok(RespValue::Integer(db.len() as i64)),
+        Command::Save => {
+            let dir = args.dir.as_deref().unwrap_or(".");
+            let filename = args.dbfilename.as_deref().unwrap_or("dump.rdb");
+            rdb::save(dir, filename, server)
+                .await
+                .map_err(|err| RedisError::Err(format!("Failed saving the DB: {}", err)))?;
+            Ok(RespValue::SimpleString("OK".to_string()))
         }
-        Command::Save => todo!(),
         Command::ReplConf(_, _) => {
-            if let Some(_) = &args.replicaof {
-                write_and_flush(
-                    &mut guard,
-                    Command::ReplConf(
-                        "ACK".to_string(),
-                        command_offset.load(Ordering::Relaxed).to_string(),
-                    ),
-                )
-                .await;
-                command_offset.fetch_add(37, Ordering::Relaxed);
+            if args.replicaof.is_some() {
+                let offset = connection.offset.to_string();
+                Ok(RespValue::command(["REPLCONF", "ACK", offset.as_str()]))
             } else {
-                send_ack(&mut guard).await;
+                Ok(RespValue::SimpleString("OK".to_string()))
             }
         }
-        Command::PSync(_, _) => {
-            assert!(
-                &args.replicaof.is_none(),
-                "Configured as replica. I should never receive this command."
-            );
+        Command::Hello(params) => {
+            let protocol = match params.protover {
+                None => connection.protocol,
+                Some(2) => Protocol::Resp2,
+                Some(3) => Protocol::Resp3,
+                Some(_) => return Err(RedisError::NoProto),
+            };
 
-            write_and_flush(
-                &mut guard,
-                SimpleString {
-                    value: format!("FULLRESYNC {} 0", rep_ref.replid),
-                },
-            )
-            .await;
-
-            let empty_rdb = "UkVESVMwMDEx+glyZWRpcy12ZXIFNy4yLjD6CnJlZGlzLWJpdHPAQPoFY3RpbWXCbQi8ZfoIdXNlZC1tZW3CsMQQAPoIYW9mLWJhc2XAAP/wbjv+wP9aog==".as_bytes();
-            let empty_rdb_bytes = general_purpose::STANDARD.decode(empty_rdb).unwrap();
-
-            println!("len of empty: {}", empty_rdb_bytes.len());
-
-            let bytes = empty_rdb_bytes.as_slice();
-            write_and_flush(&mut guard, format!("${}\r\n", bytes.len())).await;
-            write_and_flush(&mut guard, bytes).await;
-
-            println!("Adding replica");
-            replicas.lock().await.push(buf_stream.clone());
-
-            let replicas = replicas.clone();
-            let rx = rx.clone();
-            tokio::spawn(async move {
-                println!("Replication started on master");
-                loop {
-                    let mut rx = rx.lock().await;
-                    for command in rx.recv().await {
-                        println!("Received command for replication: {:?}", command);
-                        let mut guard = replicas.lock().await;
-                        for replica_stream in guard.iter_mut() {
-                            let mut stream = replica_stream.lock().await;
-                            let bytes_written = write_and_flush(&mut stream, command.clone()).await;
-                            println!("Wrote {} bytes for replication", bytes_written);
-                        }
-                    }
+            // There are no ACL users, so only the default user exists and it takes any password
+            if let Some((username, _)) = &params.auth {
+                if username.as_ref() != b"default" {
+                    return Err(RedisError::WrongPass);
                 }
-            });
+            }
+
+            if let Some(name) = &params.setname {
+                if name.iter().any(|c| *c <= b' ' || *c > b'~') {
+                    return Err(RedisError::Err(
+                        "Client names cannot contain spaces, newlines or special characters."
+                            .to_string(),
+                    ));
+                }
+                connection.name = (!name.is_empty()).then(|| name.clone());
+            }
+
+            connection.protocol = protocol;
+            let role = if server.is_master() {
+                "master"
+            } else {
+                "replica"
+            };
+            Ok(RespValue::Map(vec![
+                (RespValue::bulk("server"), RespValue::bulk("redis")),
+                (RespValue::bulk("version"), RespValue::bulk(REDIS_VERSION)),
+                (
+                    RespValue::bulk("proto"),
+                    RespValue::Integer(match protocol {
+                        Protocol::Resp2 => 2,
+                        Protocol::Resp3 => 3,
+                    }),
+                ),
+                (
+                    RespValue::bulk("id"),
+                    RespValue::Integer(connection.id as i64),
+                ),
+                (RespValue::bulk("mode"), RespValue::bulk("standalone")),
+                (RespValue::bulk("role"), RespValue::bulk(role)),
+                (RespValue::bulk("modules"), RespValue::Array(vec![])),
+            ]))
+        }
+        Command::CommandList => Ok(RespValue::Array(
+            COMMAND_TABLE.iter().map(|spec| spec.info(None)).collect(),
+        )),
+        Command::CommandCount => Ok(RespValue::Integer(COMMAND_TABLE.len() as i64)),
+        Command::CommandInfo(names) => {
+            if names.is_empty() {
+                return Ok(RespValue::Array(
+                    COMMAND_TABLE.iter().map(|spec| spec.info(None)).collect(),
+                ));
+            }
+
+            Ok(RespValue::Array(
+                names
+                    .iter()
+                    .map(|name| match commands::lookup(name) {
+                        Some(spec) => spec.info(None),
+                        None => RespValue::NullArray,
+                    })
+                    .collect(),
+            ))
         }
+        Command::CommandDocs(names) => {
+            let specs: Vec<&CommandSpec> = if names.is_empty() {
+                COMMAND_TABLE.iter().collect()
+            } else {
+                // Unknown commands are left out rather than replied as nulls
+                names
+                    .iter()
+                    .filter_map(|name| commands::lookup(name))
+                    .collect()
+            };
+
+            Ok(RespValue::Map(
+                specs
+                    .into_iter()
+                    .map(|spec| (RespValue::bulk(spec.name), spec.docs()))
+                    .collect(),
+            ))
+        }
+        Command::CommandGetKeys(argv) => {
+            let (spec, _) = commands::resolve(&argv)
+                .map_err(|_| RedisError::Err("Invalid command specified".to_string()))?;
+            if !spec.has_keys() {
+                return Err(RedisError::Err(
+                    "The command has no key arguments".to_string(),
+                ));
+            }
+            if !spec.accepts(argv.len()) {
+                return Err(RedisError::Err(
+                    "Invalid number of arguments specified for command".to_string(),
+                ));
+            }
+
+            let positions = spec.key_positions(&argv);
+            if positions.is_empty() {
+                return Err(RedisError::Err(
+                    "Invalid arguments specified for command".to_string(),
+                ));
+            }
+            Ok(RespValue::Array(
+                positions
+                    .into_iter()
+                    .map(|position| RespValue::BulkString(argv[position].clone()))
+                    .collect(),
+            ))
+        }
+        Command::PSync(_, _) => unreachable!("PSYNC is answered by full_resync"),
     }
 }
 
-fn is_master(args: &Arc<Args>) -> bool {
-    args.replicaof.is_none()
+async fn full_resync(server: &Arc<Server>, connection: &mut Connection, replies: &mut Vec<u8>) {
+    assert!(
+        server.args.replicaof.is_none(),
+        "Configured as replica. I should never receive this command."
+    );
+
+    let fullresync = format!("FULLRESYNC {} 0", server.rep_info.replid);
+    reply(replies, connection, RespValue::SimpleString(fullresync));
+
+    let empty_rdb = "UkVESVMwMDEx+glyZWRpcy12ZXIFNy4yLjD6CnJlZGlzLWJpdHPAQPoFY3RpbWXCbQi8ZfoIdXNlZC1tZW3CsMQQAPoIYW9mLWJhc2XAAP/wbjv+wP9aog==".as_bytes();
+    let empty_rdb_bytes = general_purpose::STANDARD.decode(empty_rdb).unwrap();
+
+    println!("len of empty: {}", empty_rdb_bytes.len());
+
+    // Sent like a bulk string, minus the trailing \r\n
+    replies.extend(format!("${}\r\n", empty_rdb_bytes.len()).into_bytes());
+    replies.extend(empty_rdb_bytes);
+
+    // The replica must have the snapshot before anything is propagated to it
+    let mut stream = connection.stream.lock().await;
+    if let Err(err) = write_and_flush(&mut *stream, std::mem::take(replies)).await {
+        println!("Failed to send the snapshot to the replica: {}", err);
+        return;
+    }
+
+    println!("Adding replica");
+    server.replicas.lock().await.push(Replica {
+        stream: connection.stream.clone(),
+        selected_db: None,
+    });
 }
 
-pub async fn write_and_flush<T>(tcp_stream: &mut TcpStream, into_bytes: T) -> usize
-where
-    T: Into<Vec<u8>>,
-{
-    let bytes: Vec<u8> = into_bytes.into();
+/// Writes a string value on behalf of SET and friends. Returns whether the SET's condition let
+/// it through, along with the value the key held before.
+fn set(
+    server: &Server,
+    index: usize,
+    params: SetParams,
+) -> Result<(bool, Option<Bytes>), RedisError> {
+    let existing = live_entry(server, index, &params.key);
+    let expire_at = match params.expiry {
+        None => None,
+        Some(Expiry::KeepTtl) => existing.as_ref().and_then(|entry| entry.expire_at),
+        Some(expiry) => Some(expiry_deadline(expiry, "set")?),
+    };
 
-    let bytes = tcp_stream
-        .write(bytes.as_slice())
-        .await
-        .expect("Failed to send bytes");
+    // Values of any type get overwritten, but GET can only hand back a string
+    let previous = match existing.as_ref().map(|entry| &entry.value) {
+        Some(Value::String(previous)) => Some(previous.clone()),
+        Some(_) if params.get => return Err(RedisError::WrongType),
+        _ => None,
+    };
+    let skipped = match params.condition {
+        Some(SetCondition::Nx) => existing.is_some(),
+        Some(SetCondition::Xx) => existing.is_none(),
+        None => false,
+    };
+    if skipped {
+        return Ok((false, previous));
+    }
+
+    server.db(index).insert(
+        params.key.clone(),
+        Entry::new(Value::String(params.value.clone()), expire_at),
+    );
 
-    tcp_stream.flush().await.unwrap();
+    // Replicas get a plain SET with an absolute expiry, so they agree on when it expires
+    let mut argv = vec![Bytes::from_static(b"SET"), params.key, params.value];
+    if let Some(expire_at) = expire_at {
+        argv.push(Bytes::from_static(b"PXAT"));
+        argv.push(Bytes::from(unix_millis(expire_at).to_string()));
+    }
+    server.replicate(index, argv);
 
-    bytes
+    Ok((true, previous))
 }
 
-pub async fn send_ack(buf_stream: &mut TcpStream) {
-    buf_stream
-        .write("+OK\r\n".as_bytes())
-        .await
-        .expect("Failed to send bytes");
+/// Writes every pair on behalf of MSET or MSETNX, dropping any TTL the keys had.
+fn mset(server: &Server, index: usize, command: &'static str, pairs: Vec<(Bytes, Bytes)>) {
+    let db = server.db(index);
+    let mut argv = vec![Bytes::from_static(command.as_bytes())];
+    for (key, value) in pairs {
+        db.insert(key.clone(), Entry::new(Value::String(value.clone()), None));
+        argv.push(key);
+        argv.push(value);
+    }
+    server.replicate(index, argv);
+}
 
-    buf_stream.flush().await.unwrap();
+/// Parses a counter stored as a string, which must be exactly how Redis would print the number:
+/// no sign other than a minus, no leading zeros and no whitespace.
+fn parse_counter(value: &[u8]) -> Option<i64> {
+    let counter: i64 = std::str::from_utf8(value).ok()?.parse().ok()?;
+    (counter.to_string().as_bytes() == value).then_some(counter)
 }
 
-pub async fn receive_ack(tcp_stream: &mut TcpStream) -> anyhow::Result<()> {
-    let mut ack = String::new();
-    let mut bytes = [0; 5];
-    tcp_stream
-        .read_exact(&mut bytes)
-        .await
-        .with_context(|| "Failed to receive bytes")?;
+/// Formats a float the way INCRBYFLOAT stores it, in plain decimal notation without trailing
+/// zeros.
+///
+/// Redis computes with long doubles and prints them with 17 decimals, so anything smaller is
+/// lost. Doubles only hold about 15 significant digits, so the value is first rounded to those,
+/// sparing users of e.g. 0.30000000000000004 where Redis says 0.3. The digits a long double
+/// holds past those 15 are lost too, so results that need them differ from Redis there.
+fn format_float(value: f64) -> String {
+    let scientific = format!("{:.14e}", value);
+    let (_, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+
+    // Fewer digits are left above the 17th decimal for small values
+    let significant = (exponent + 18).clamp(0, 15) as usize;
+    if significant == 0 {
+        return match value.abs() >= 5e-18 {
+            true if value < 0.0 => "-0.00000000000000001".to_string(),
+            true => "0.00000000000000001".to_string(),
+            false => "0".to_string(),
+        };
+    }
+    let scientific = format!("{:.*e}", significant - 1, value);
+    let (mantissa, exponent) = scientific.split_once('e').unwrap();
+    let exponent: i32 = exponent.parse().unwrap();
+    let digits: String = mantissa.chars().filter(char::is_ascii_digit).collect();
+    let digits = digits.trim_end_matches('0');
+    // Also takes care of negative zero
+    if digits.is_empty() {
+        return "0".to_string();
+    }
+
+    let mut formatted = String::new();
+    if value < 0.0 {
+        formatted.push('-');
+    }
+    // Digits before the decimal point
+    let whole = exponent + 1;
+    if whole <= 0 {
+        formatted.push_str("0.");
+        formatted.push_str(&"0".repeat(whole.unsigned_abs() as usize));
+        formatted.push_str(digits);
+    } else if whole as usize >= digits.len() {
+        formatted.push_str(digits);
+        formatted.push_str(&"0".repeat(whole as usize - digits.len()));
+    } else {
+        let (integer, fraction) = digits.split_at(whole as usize);
+        formatted.push_str(integer);
+        formatted.push('.');
+        formatted.push_str(fraction);
+    }
+    formatted
+}
+
+fn check_string_length(length: usize) -> Result<(), RedisError> {
+    if length > MAX_STRING_LENGTH {
+        return Err(RedisError::Err(
+            "string exceeds maximum allowed size (proto-max-bulk-len)".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Deletes `keys` on behalf of DEL or UNLINK, returning how many of them existed.
+fn delete_keys(server: &Server, index: usize, command: &'static str, keys: Vec<Bytes>) -> i64 {
+    let mut deleted = 0;
+    for key in &keys {
+        if live_entry(server, index, key).is_some() {
+            server.db(index).remove(key);
+            deleted += 1;
+        }
+    }
+
+    if deleted > 0 {
+        let argv = [Bytes::from_static(command.as_bytes())]
+            .into_iter()
+            .chain(keys);
+        server.replicate(index, argv.collect());
+    }
+    deleted
+}
+
+/// Moves `source` over to `destination` along with its TTL. With `nx` an existing destination
+/// is left alone, and the reply is 1 or 0 rather than OK.
+fn rename(
+    server: &Server,
+    index: usize,
+    source: Bytes,
+    destination: Bytes,
+    nx: bool,
+) -> Result<RespValue, RedisError> {
+    let Some(entry) = live_entry(server, index, &source) else {
+        return Err(RedisError::Err("no such key".to_string()));
+    };
 
-    if !ack.eq("+OK\r") {
-        Ok(())
+    if source == destination {
+        return Ok(if nx {
+            RespValue::Integer(0)
+        } else {
+            RespValue::SimpleString("OK".to_string())
+        });
+    }
+    if nx && live_entry(server, index, &destination).is_some() {
+        return Ok(RespValue::Integer(0));
+    }
+
+    let db = server.db(index);
+    db.remove(&source);
+    db.insert(destination.clone(), entry);
+    server.blocked.signal(index, &destination);
+
+    let command = if nx { "RENAMENX" } else { "RENAME" };
+    server.replicate(
+        index,
+        vec![Bytes::from_static(command.as_bytes()), source, destination],
+    );
+
+    Ok(if nx {
+        RespValue::Integer(1)
     } else {
-        Err(anyhow::Error::msg(format!(
-            "Received unexpected ACK: {}",
-            ack
-        )))
+        RespValue::SimpleString("OK".to_string())
+    })
+}
+
+/// Returns the entry stored at `key`, deleting it first if it has expired.
+fn live_entry(server: &Server, index: usize, key: &Bytes) -> Option<Entry> {
+    server.expire_if_needed(index, key);
+    server.db(index).get(key)
+}
+
+/// Makes an existing `key` expire at `expire_at`, or deletes it if that has already passed.
+fn expire_key(server: &Server, index: usize, key: Bytes, expire_at: SystemTime) {
+    let db = server.db(index);
+
+    // Replicas wait for our DEL rather than deleting on their own
+    if server.is_master() && expire_at <= SystemTime::now() {
+        db.remove(&key);
+        server.replicate(index, vec![Bytes::from_static(b"DEL"), key]);
+        return;
+    }
+
+    db.set_expiry(&key, Some(expire_at));
+    server.replicate(
+        index,
+        vec![
+            Bytes::from_static(b"PEXPIREAT"),
+            key,
+            Bytes::from(unix_millis(expire_at).to_string()),
+        ],
+    );
+}
+
+/// Returns the string stored at `key` along with when it expires, failing with WRONGTYPE if the
+/// key holds another kind of value.
+fn live_string(
+    server: &Server,
+    index: usize,
+    key: &Bytes,
+) -> Result<Option<(Bytes, Option<SystemTime>)>, RedisError> {
+    match live_entry(server, index, key) {
+        Some(Entry {
+            value: Value::String(value),
+            expire_at,
+        }) => Ok(Some((value, expire_at))),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+/// Resolves an inclusive range where negative positions count from the end, such as the one
+/// LRANGE takes, to positions within `len` elements. None if the range holds no elements.
+fn resolve_range(start: i64, stop: i64, len: usize) -> Option<(usize, usize)> {
+    let len = len as i64;
+    let start = if start < 0 { len + start } else { start }.max(0);
+    let stop = if stop < 0 { len + stop } else { stop }.min(len - 1);
+    (start <= stop).then_some((start as usize, stop as usize))
+}
+
+/// Returns when `key` expires, or the code the TTL family replies with when it doesn't:
+/// -2 if the key doesn't exist and -1 if it never expires.
+fn expiry_of(server: &Server, index: usize, key: &Bytes) -> Result<SystemTime, i64> {
+    match live_entry(server, index, key) {
+        Some(entry) => entry.expire_at.ok_or(-1),
+        None => Err(-2),
+    }
+}
+
+fn millis_until(time: SystemTime) -> i64 {
+    (unix_millis(time) - unix_millis(SystemTime::now())).max(0)
+}
+
+/// Resolves `expiry` to the moment the key expires.
+fn expiry_deadline(expiry: Expiry, command: &str) -> Result<SystemTime, RedisError> {
+    let (value, unit, relative) = match expiry {
+        Expiry::Ex(seconds) => (seconds, 1000, true),
+        Expiry::Px(millis) => (millis, 1, true),
+        Expiry::ExAt(seconds) => (seconds, 1000, false),
+        Expiry::PxAt(millis) => (millis, 1, false),
+        Expiry::KeepTtl | Expiry::Persist => unreachable!("{:?} has no deadline", expiry),
+    };
+
+    let millis = value.checked_mul(unit).and_then(|millis| match relative {
+        true => millis.checked_add(unix_millis(SystemTime::now())),
+        false => Some(millis),
+    });
+    match millis {
+        // Times before the epoch are simply already past
+        Some(millis) => Ok(UNIX_EPOCH + Duration::from_millis(millis.max(0) as u64)),
+        None => Err(RedisError::InvalidExpireTime(command.to_string())),
     }
 }
+
+fn unix_millis(time: SystemTime) -> i64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+fn reply(replies: &mut Vec<u8>, connection: &Connection, value: RespValue) {
+    replies.extend(value.encode(connection.protocol));
+}
+
+/// Writes `into_bytes` out in full, returning how many bytes that was. Fails if the peer has
+/// gone away.
+pub async fn write_and_flush<W, T>(tcp_stream: &mut W, into_bytes: T) -> std::io::Result<usize>
+where
+    W: AsyncWrite + Unpin,
+    T: Into<Vec<u8>>,
+{
+    let bytes: Vec<u8> = into_bytes.into();
+    tcp_stream.write_all(bytes.as_slice()).await?;
+    tcp_stream.flush().await?;
+    Ok(bytes.len())
+}
@@ -0,0 +1,532 @@
+use super::{expiry_deadline, format_float, live_entry, millis_until, parse_counter, unix_millis};
+use crate::commands;
+use crate::db::Value;
+use crate::glob;
+use crate::hash::Hash;
+use crate::models::{ExpireParams, Expiry, RedisError, RespValue, ScanParams};
+use crate::resp::Protocol;
+use crate::server::Server;
+use bytes::Bytes;
+use rand::seq::index::sample;
+use rand::Rng;
+use std::sync::Arc;
+use std::time::SystemTime;
+
+// Latest deadline a hash field may have, as Redis keeps them in 48 bits of milliseconds
+const MAX_FIELD_EXPIRE_MILLIS: i64 = (1 << 48) - 1;
+
+/// HSET and HMSET, returning how many of the fields are new.
+pub(super) fn set(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    pairs: Vec<(Bytes, Bytes)>,
+) -> Result<usize, RedisError> {
+    server.expire_if_needed(index, &key);
+    let added = {
+        let db = server.db(index);
+        let mut value = db.get_or_insert_with(&key, || Value::Hash(Arc::default()));
+        let hash = hash_mut(&mut value)?;
+        pairs
+            .iter()
+            .filter(|(field, value)| hash.insert(field.clone(), value.clone()))
+            .count()
+    };
+
+    let argv = [Bytes::from_static(b"HSET"), key]
+        .into_iter()
+        .chain(pairs.into_iter().flat_map(|(field, value)| [field, value]));
+    server.replicate(index, argv.collect());
+    Ok(added)
+}
+
+pub(super) fn setnx(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    field: Bytes,
+    value: Bytes,
+) -> Result<RespValue, RedisError> {
+    if live_hash(server, index, &key)?.is_some_and(|hash| hash.contains(&field)) {
+        return Ok(RespValue::Integer(0));
+    }
+    set(server, index, key, vec![(field, value)])?;
+    Ok(RespValue::Integer(1))
+}
+
+pub(super) fn get(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    field: Bytes,
+) -> Result<RespValue, RedisError> {
+    let value = live_hash(server, index, &key)?.and_then(|hash| hash.get(&field).cloned());
+    Ok(value.map_or(RespValue::Null, RespValue::BulkString))
+}
+
+pub(super) fn mget(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    fields: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    let hash = live_hash(server, index, &key)?.unwrap_or_default();
+    Ok(RespValue::Array(
+        fields
+            .iter()
+            .map(|field| {
+                hash.get(field).map_or(RespValue::Null, |value| {
+                    RespValue::BulkString(value.clone())
+                })
+            })
+            .collect(),
+    ))
+}
+
+/// HDEL, which deletes the key along with its last field.
+pub(super) fn del(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    fields: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let (removed, emptied) = {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Ok(RespValue::Integer(0));
+        };
+        let hash = hash_mut(&mut value)?;
+        let removed = fields
+            .iter()
+            .filter(|field| hash.remove(field).is_some())
+            .count();
+        (removed, hash.is_empty())
+    };
+
+    if emptied {
+        db.remove(&key);
+    }
+    if removed > 0 {
+        let argv = [Bytes::from_static(b"HDEL"), key].into_iter().chain(fields);
+        server.replicate(index, argv.collect());
+    }
+    Ok(RespValue::Integer(removed as i64))
+}
+
+pub(super) fn len(server: &Server, index: usize, key: Bytes) -> Result<RespValue, RedisError> {
+    let length = live_hash(server, index, &key)?.map_or(0, |hash| hash.len());
+    Ok(RespValue::Integer(length as i64))
+}
+
+pub(super) fn strlen(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    field: Bytes,
+) -> Result<RespValue, RedisError> {
+    let length = live_hash(server, index, &key)?
+        .and_then(|hash| hash.get(&field).map(|value| value.len()))
+        .unwrap_or(0);
+    Ok(RespValue::Integer(length as i64))
+}
+
+pub(super) fn exists(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    field: Bytes,
+) -> Result<RespValue, RedisError> {
+    let exists = live_hash(server, index, &key)?.is_some_and(|hash| hash.contains(&field));
+    Ok(RespValue::Integer(exists as i64))
+}
+
+/// HKEYS, HVALS and HGETALL, replying with the fields, the values or both.
+pub(super) fn all(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    fields: bool,
+    values: bool,
+) -> Result<RespValue, RedisError> {
+    let pairs = live_hash(server, index, &key)?.map_or(vec![], |hash| hash.pairs());
+    if fields && values {
+        return Ok(RespValue::Map(
+            pairs
+                .into_iter()
+                .map(|(field, value)| (RespValue::BulkString(field), RespValue::BulkString(value)))
+                .collect(),
+        ));
+    }
+
+    Ok(RespValue::Array(
+        pairs
+            .into_iter()
+            .map(|(field, value)| RespValue::BulkString(if fields { field } else { value }))
+            .collect(),
+    ))
+}
+
+pub(super) fn incr_by(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    field: Bytes,
+    increment: i64,
+) -> Result<RespValue, RedisError> {
+    let current = match live_hash(server, index, &key)?.and_then(|hash| hash.get(&field).cloned()) {
+        Some(current) => parse_counter(&current)
+            .ok_or_else(|| RedisError::Err("hash value is not an integer".to_string()))?,
+        None => 0,
+    };
+    let value = current
+        .checked_add(increment)
+        .ok_or_else(|| RedisError::Err("increment or decrement would overflow".to_string()))?;
+
+    store(server, index, &key, field.clone(), value.to_string())?;
+    server.replicate(
+        index,
+        vec![
+            Bytes::from_static(b"HINCRBY"),
+            key,
+            field,
+            Bytes::from(increment.to_string()),
+        ],
+    );
+    Ok(RespValue::Integer(value))
+}
+
+pub(super) fn incr_by_float(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    field: Bytes,
+    increment: f64,
+) -> Result<RespValue, RedisError> {
+    let current = match live_hash(server, index, &key)?.and_then(|hash| hash.get(&field).cloned()) {
+        Some(current) => commands::parse_float(&current)
+            .map_err(|_| RedisError::Err("hash value is not a float".to_string()))?,
+        None => 0.0,
+    };
+    let value = current + increment;
+    if !value.is_finite() {
+        return Err(RedisError::Err(
+            "increment would produce NaN or Infinity".to_string(),
+        ));
+    }
+
+    let value = Bytes::from(format_float(value));
+    let expire_at = store(server, index, &key, field.clone(), value.clone())?;
+
+    // Replicas get the result rather than redoing the float math themselves, and as HSET
+    // drops the field's expiry it is sent along again
+    server.replicate(
+        index,
+        vec![
+            Bytes::from_static(b"HSET"),
+            key.clone(),
+            field.clone(),
+            value.clone(),
+        ],
+    );
+    if let Some(expire_at) = expire_at {
+        replicate_field_expiry(server, index, key, expire_at, vec![field]);
+    }
+    Ok(RespValue::BulkString(value))
+}
+
+pub(super) fn scan(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    params: ScanParams,
+) -> Result<RespValue, RedisError> {
+    let (cursor, pairs) = match live_hash(server, index, &key)? {
+        Some(hash) => hash.scan(params.cursor, params.count),
+        None => (0, vec![]),
+    };
+
+    let mut reply = Vec::new();
+    for (field, value) in pairs {
+        let wanted = params
+            .pattern
+            .as_ref()
+            .is_none_or(|pattern| glob::matches(pattern, &field));
+        if !wanted {
+            continue;
+        }
+        reply.push(RespValue::BulkString(field));
+        if !params.novalues {
+            reply.push(RespValue::BulkString(value));
+        }
+    }
+
+    Ok(RespValue::Array(vec![
+        RespValue::bulk(cursor.to_string()),
+        RespValue::Array(reply),
+    ]))
+}
+
+/// HRANDFIELD. A positive count picks that many distinct fields, a negative one picks fields
+/// independently so they may repeat, and no count replies with a single field.
+pub(super) fn rand_field(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    count: Option<i64>,
+    withvalues: bool,
+    protocol: Protocol,
+) -> Result<RespValue, RedisError> {
+    let hash = live_hash(server, index, &key)?;
+    let pairs: Vec<(&Bytes, &Bytes)> = hash.as_deref().map_or(vec![], |hash| hash.iter().collect());
+
+    let mut rng = rand::thread_rng();
+    let Some(count) = count else {
+        if pairs.is_empty() {
+            return Ok(RespValue::Null);
+        }
+        let (field, _) = pairs[rng.gen_range(0..pairs.len())];
+        return Ok(RespValue::BulkString(field.clone()));
+    };
+
+    let picked: Vec<(&Bytes, &Bytes)> = if pairs.is_empty() {
+        vec![]
+    } else if count < 0 {
+        // Grown a pick at a time, as reserving room for the count up front could fail outright
+        let mut picked = Vec::new();
+        for _ in 0..count.unsigned_abs() {
+            picked.push(pairs[rng.gen_range(0..pairs.len())]);
+        }
+        picked
+    } else if count as usize >= pairs.len() {
+        pairs
+    } else {
+        sample(&mut rng, pairs.len(), count as usize)
+            .into_iter()
+            .map(|position| pairs[position])
+            .collect()
+    };
+
+    let field = |(field, _): (&Bytes, &Bytes)| RespValue::BulkString(field.clone());
+    Ok(RespValue::Array(match (withvalues, protocol) {
+        (false, _) => picked.into_iter().map(field).collect(),
+        // RESP3 clients get each field paired up with its value
+        (true, Protocol::Resp3) => picked
+            .into_iter()
+            .map(|(field, value)| {
+                RespValue::Array(vec![
+                    RespValue::BulkString(field.clone()),
+                    RespValue::BulkString(value.clone()),
+                ])
+            })
+            .collect(),
+        (true, Protocol::Resp2) => picked
+            .into_iter()
+            .flat_map(|(field, value)| {
+                [
+                    RespValue::BulkString(field.clone()),
+                    RespValue::BulkString(value.clone()),
+                ]
+            })
+            .collect(),
+    }))
+}
+
+/// HEXPIRE and friends, replying for each field with -2 if there's no such field, 0 if the
+/// condition wasn't met, 1 if the expiry was set and 2 if the field was deleted right away
+/// since the deadline is already past.
+pub(super) fn expire(
+    server: &Server,
+    index: usize,
+    params: ExpireParams,
+    fields: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    let command = match params.expiry {
+        Expiry::Ex(_) => "hexpire",
+        Expiry::Px(_) => "hpexpire",
+        Expiry::ExAt(_) => "hexpireat",
+        _ => "hpexpireat",
+    };
+    let expire_at = expiry_deadline(params.expiry, command)?;
+    if unix_millis(expire_at) > MAX_FIELD_EXPIRE_MILLIS {
+        return Err(RedisError::InvalidExpireTime(command.to_string()));
+    }
+
+    // Replicas wait for our HDEL rather than deleting on their own
+    let past = server.is_master() && expire_at <= SystemTime::now();
+    let key = params.key;
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let (codes, updated, deleted, emptied) = {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Ok(integers(fields.iter().map(|_| -2)));
+        };
+        let hash = hash_mut(&mut value)?;
+
+        let (mut codes, mut updated, mut deleted) = (vec![], vec![], vec![]);
+        for field in fields {
+            let Some(current) = hash.expiry(&field) else {
+                codes.push(-2);
+                continue;
+            };
+            // A field without an expiry counts as never expiring for GT and LT
+            let allowed = match current {
+                Some(current) => {
+                    !params.nx
+                        && (!params.gt || expire_at > current)
+                        && (!params.lt || expire_at < current)
+                }
+                None => !params.xx && !params.gt,
+            };
+
+            if !allowed {
+                codes.push(0);
+            } else if past {
+                hash.remove(&field);
+                codes.push(2);
+                deleted.push(field);
+            } else {
+                hash.set_expiry(&field, Some(expire_at));
+                codes.push(1);
+                updated.push(field);
+            }
+        }
+        (codes, updated, deleted, hash.is_empty())
+    };
+
+    if !updated.is_empty() {
+        db.watch_field_expiry(&key, expire_at);
+        replicate_field_expiry(server, index, key.clone(), expire_at, updated);
+    }
+    if !deleted.is_empty() {
+        let argv = [Bytes::from_static(b"HDEL"), key.clone()]
+            .into_iter()
+            .chain(deleted);
+        server.replicate(index, argv.collect());
+    }
+    if emptied {
+        db.remove(&key);
+    }
+    Ok(integers(codes))
+}
+
+/// HTTL and HPTTL, replying for each field with -2 if there's no such field, -1 if it doesn't
+/// expire, and otherwise its TTL.
+pub(super) fn ttl(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    fields: Vec<Bytes>,
+    millis: bool,
+) -> Result<RespValue, RedisError> {
+    let hash = live_hash(server, index, &key)?;
+    Ok(integers(fields.iter().map(
+        |field| match hash.as_ref().and_then(|hash| hash.expiry(field)) {
+            None => -2,
+            Some(None) => -1,
+            Some(Some(expire_at)) if millis => millis_until(expire_at),
+            Some(Some(expire_at)) => (millis_until(expire_at) + 500) / 1000,
+        },
+    )))
+}
+
+/// HPERSIST, replying for each field with -2 if there's no such field, -1 if it didn't expire
+/// anyway and 1 if its expiry was removed.
+pub(super) fn persist(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    fields: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let (codes, persisted) = {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Ok(integers(fields.iter().map(|_| -2)));
+        };
+        let hash = hash_mut(&mut value)?;
+
+        let (mut codes, mut persisted) = (vec![], vec![]);
+        for field in fields {
+            match hash.expiry(&field) {
+                None => codes.push(-2),
+                Some(None) => codes.push(-1),
+                Some(Some(_)) => {
+                    hash.set_expiry(&field, None);
+                    codes.push(1);
+                    persisted.push(field);
+                }
+            }
+        }
+        (codes, persisted)
+    };
+
+    if !persisted.is_empty() {
+        let argv = [
+            Bytes::from_static(b"HPERSIST"),
+            key,
+            Bytes::from_static(b"FIELDS"),
+            Bytes::from(persisted.len().to_string()),
+        ];
+        server.replicate(index, argv.into_iter().chain(persisted).collect());
+    }
+    Ok(integers(codes))
+}
+
+// Sets a single field, creating the hash if needed but keeping the field's expiry, without
+// replicating anything. Returns the expiry
+fn store(
+    server: &Server,
+    index: usize,
+    key: &Bytes,
+    field: Bytes,
+    value: impl Into<Bytes>,
+) -> Result<Option<SystemTime>, RedisError> {
+    server.expire_if_needed(index, key);
+    let db = server.db(index);
+    let mut entry = db.get_or_insert_with(key, || Value::Hash(Arc::default()));
+    let hash = hash_mut(&mut entry)?;
+    hash.insert_keep_ttl(field.clone(), value.into());
+    Ok(hash.expiry(&field).flatten())
+}
+
+fn replicate_field_expiry(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    expire_at: SystemTime,
+    fields: Vec<Bytes>,
+) {
+    let argv = [
+        Bytes::from_static(b"HPEXPIREAT"),
+        key,
+        Bytes::from(unix_millis(expire_at).to_string()),
+        Bytes::from_static(b"FIELDS"),
+        Bytes::from(fields.len().to_string()),
+    ];
+    server.replicate(index, argv.into_iter().chain(fields).collect());
+}
+
+fn integers(values: impl IntoIterator<Item = i64>) -> RespValue {
+    RespValue::Array(values.into_iter().map(RespValue::Integer).collect())
+}
+
+/// Returns the hash stored at `key`, failing with WRONGTYPE if the key holds another kind of
+/// value.
+///
+/// The hash is shared with the keyspace, so it must be dropped before modifying the key or
+/// the whole hash gets copied.
+fn live_hash(server: &Server, index: usize, key: &Bytes) -> Result<Option<Arc<Hash>>, RedisError> {
+    match live_entry(server, index, key).map(|entry| entry.value) {
+        Some(Value::Hash(hash)) => Ok(Some(hash)),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn hash_mut(value: &mut Value) -> Result<&mut Hash, RedisError> {
+    match value {
+        Value::Hash(hash) => Ok(Arc::make_mut(hash)),
+        _ => Err(RedisError::WrongType),
+    }
+}
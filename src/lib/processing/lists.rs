@@ -0,0 +1,697 @@
+use super::{block, live_entry, resolve_range};
+use crate::blocking::{BlockedOp, Waiter};
+use crate::db::Value;
+use crate::models::{LInsertParams, LPosParams, ListEnd, RedisError, RespValue};
+use crate::quicklist::QuickList;
+use crate::server::Server;
+use bytes::Bytes;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// Pushes `elements` one at a time onto `end` of the list at `key`, creating it unless
+/// `existing_only` is set. Replies with the new length.
+pub(super) fn push(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    elements: Vec<Bytes>,
+    end: ListEnd,
+    existing_only: bool,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    if existing_only && db.get(&key).is_none() {
+        return Ok(RespValue::Integer(0));
+    }
+
+    let length = {
+        let mut value = db.get_or_insert_with(&key, || Value::List(Arc::default()));
+        let list = list_mut(&mut value)?;
+        for element in &elements {
+            match end {
+                ListEnd::Left => list.push_front(element.clone()),
+                ListEnd::Right => list.push_back(element.clone()),
+            }
+        }
+        list.len()
+    };
+    server.blocked.signal(index, &key);
+
+    let command = match end {
+        ListEnd::Left => "LPUSH",
+        ListEnd::Right => "RPUSH",
+    };
+    let argv = [Bytes::from_static(command.as_bytes()), key]
+        .into_iter()
+        .chain(elements);
+    server.replicate(index, argv.collect());
+
+    Ok(RespValue::Integer(length as i64))
+}
+
+/// LPOP and RPOP, which reply with a single element unless given a count.
+pub(super) fn pop(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    end: ListEnd,
+    count: Option<usize>,
+) -> Result<RespValue, RedisError> {
+    // Popping nothing still tells an empty array from a missing key
+    if count == Some(0) {
+        return Ok(match live_list(server, index, &key)? {
+            Some(_) => RespValue::Array(vec![]),
+            None => RespValue::NullArray,
+        });
+    }
+
+    let Some(popped) = pop_elements(server, index, &key, end, count.unwrap_or(1))? else {
+        return Ok(match count {
+            Some(_) => RespValue::NullArray,
+            None => RespValue::Null,
+        });
+    };
+    replicate_pop(server, index, key, end, popped.len());
+
+    Ok(match count {
+        Some(_) => RespValue::Array(popped.into_iter().map(RespValue::BulkString).collect()),
+        None => RespValue::BulkString(popped.into_iter().next().unwrap()),
+    })
+}
+
+pub(super) fn range(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    start: i64,
+    stop: i64,
+) -> Result<RespValue, RedisError> {
+    let Some(list) = live_list(server, index, &key)? else {
+        return Ok(RespValue::Array(vec![]));
+    };
+    let Some((start, stop)) = resolve_range(start, stop, list.len()) else {
+        return Ok(RespValue::Array(vec![]));
+    };
+
+    Ok(RespValue::Array(
+        list.iter_from(start)
+            .take(stop - start + 1)
+            .map(|element| RespValue::BulkString(element.clone()))
+            .collect(),
+    ))
+}
+
+pub(super) fn len(server: &Server, index: usize, key: Bytes) -> Result<RespValue, RedisError> {
+    let length = live_list(server, index, &key)?.map_or(0, |list| list.len());
+    Ok(RespValue::Integer(length as i64))
+}
+
+pub(super) fn index(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    position: i64,
+) -> Result<RespValue, RedisError> {
+    let element = live_list(server, index, &key)?.and_then(|list| {
+        let position = resolve_index(position, list.len())?;
+        list.get(position).cloned()
+    });
+    Ok(element.map_or(RespValue::Null, RespValue::BulkString))
+}
+
+pub(super) fn set(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    position: i64,
+    element: Bytes,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Err(RedisError::Err("no such key".to_string()));
+        };
+        let list = list_mut(&mut value)?;
+        let Some(resolved) = resolve_index(position, list.len()) else {
+            return Err(RedisError::Err("index out of range".to_string()));
+        };
+        list.set(resolved, element.clone());
+    }
+
+    server.replicate(
+        index,
+        vec![
+            Bytes::from_static(b"LSET"),
+            key,
+            Bytes::from(position.to_string()),
+            element,
+        ],
+    );
+    Ok(RespValue::SimpleString("OK".to_string()))
+}
+
+/// LINSERT, which replies with the new length, 0 if there's no list and -1 if there's no pivot.
+pub(super) fn insert(
+    server: &Server,
+    index: usize,
+    params: LInsertParams,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &params.key);
+    let db = server.db(index);
+    let length = {
+        let Some(mut value) = db.get_mut(&params.key) else {
+            return Ok(RespValue::Integer(0));
+        };
+        let list = list_mut(&mut value)?;
+        let Some(pivot) = list.iter().position(|element| *element == params.pivot) else {
+            return Ok(RespValue::Integer(-1));
+        };
+
+        let position = if params.before { pivot } else { pivot + 1 };
+        list.insert(position, params.element.clone());
+        list.len()
+    };
+
+    let position = if params.before { "BEFORE" } else { "AFTER" };
+    server.replicate(
+        index,
+        vec![
+            Bytes::from_static(b"LINSERT"),
+            params.key,
+            Bytes::from_static(position.as_bytes()),
+            params.pivot,
+            params.element,
+        ],
+    );
+    Ok(RespValue::Integer(length as i64))
+}
+
+/// LREM, which removes the first `count` occurrences of `element`, the last ones if `count` is
+/// negative and all of them if it's 0.
+pub(super) fn rem(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    count: i64,
+    element: Bytes,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let (removed, emptied) = {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Ok(RespValue::Integer(0));
+        };
+        let list = list_mut(&mut value)?;
+
+        let limit = match count {
+            0 => usize::MAX,
+            count => count.unsigned_abs() as usize,
+        };
+        let length = list.len();
+        let mut positions: Vec<usize> = if count < 0 {
+            list.iter()
+                .rev()
+                .enumerate()
+                .filter(|(_, candidate)| **candidate == element)
+                .map(|(position, _)| length - 1 - position)
+                .take(limit)
+                .collect()
+        } else {
+            list.iter()
+                .enumerate()
+                .filter(|(_, candidate)| **candidate == element)
+                .map(|(position, _)| position)
+                .take(limit)
+                .collect()
+        };
+
+        // Back to front, so removals don't shift the positions still to go
+        positions.sort_unstable_by(|a, b| b.cmp(a));
+        for position in &positions {
+            list.remove(*position);
+        }
+        (positions.len(), list.is_empty())
+    };
+
+    if emptied {
+        db.remove(&key);
+    }
+    if removed > 0 {
+        server.replicate(
+            index,
+            vec![
+                Bytes::from_static(b"LREM"),
+                key,
+                Bytes::from(count.to_string()),
+                element,
+            ],
+        );
+    }
+    Ok(RespValue::Integer(removed as i64))
+}
+
+pub(super) fn trim(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    start: i64,
+    stop: i64,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let emptied = {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Ok(RespValue::SimpleString("OK".to_string()));
+        };
+        let list = list_mut(&mut value)?;
+
+        let length = list.len();
+        match resolve_range(start, stop, length) {
+            Some((start, stop)) => {
+                list.drop_back(length - 1 - stop);
+                list.drop_front(start);
+            }
+            None => list.drop_front(length),
+        }
+        list.is_empty()
+    };
+
+    if emptied {
+        db.remove(&key);
+    }
+    server.replicate(
+        index,
+        vec![
+            Bytes::from_static(b"LTRIM"),
+            key,
+            Bytes::from(start.to_string()),
+            Bytes::from(stop.to_string()),
+        ],
+    );
+    Ok(RespValue::SimpleString("OK".to_string()))
+}
+
+pub(super) fn pos(
+    server: &Server,
+    index: usize,
+    params: LPosParams,
+) -> Result<RespValue, RedisError> {
+    let positions: Vec<usize> = match live_list(server, index, &params.key)? {
+        None => vec![],
+        Some(list) => {
+            let length = list.len();
+            let scanned = match params.maxlen {
+                0 => length,
+                maxlen => maxlen.min(length),
+            };
+            let skipped = (params.rank.unsigned_abs() - 1) as usize;
+            let limit = match params.count {
+                None => 1,
+                Some(0) => usize::MAX,
+                Some(count) => count,
+            };
+
+            let is_match = |(_, element): &(usize, &Bytes)| **element == params.element;
+            if params.rank > 0 {
+                list.iter()
+                    .take(scanned)
+                    .enumerate()
+                    .filter(is_match)
+                    .skip(skipped)
+                    .take(limit)
+                    .map(|(position, _)| position)
+                    .collect()
+            } else {
+                list.iter()
+                    .rev()
+                    .take(scanned)
+                    .enumerate()
+                    .filter(is_match)
+                    .skip(skipped)
+                    .take(limit)
+                    .map(|(position, _)| length - 1 - position)
+                    .collect()
+            }
+        }
+    };
+
+    Ok(match params.count {
+        Some(_) => RespValue::Array(
+            positions
+                .into_iter()
+                .map(|position| RespValue::Integer(position as i64))
+                .collect(),
+        ),
+        None => positions.first().map_or(RespValue::Null, |position| {
+            RespValue::Integer(*position as i64)
+        }),
+    })
+}
+
+pub(super) fn lmove(
+    server: &Server,
+    index: usize,
+    source: Bytes,
+    destination: Bytes,
+    from: ListEnd,
+    to: ListEnd,
+) -> Result<RespValue, RedisError> {
+    let element = move_element(server, index, source, destination, from, to)?;
+    Ok(element.map_or(RespValue::Null, RespValue::BulkString))
+}
+
+/// LMPOP, which pops from the first of `keys` holding a list.
+pub(super) fn mpop(
+    server: &Server,
+    index: usize,
+    keys: Vec<Bytes>,
+    end: ListEnd,
+    count: usize,
+) -> Result<RespValue, RedisError> {
+    let reply = pop_first(server, index, &keys, end, Some(count))?;
+    Ok(reply.unwrap_or(RespValue::NullArray))
+}
+
+/// BLPOP and BRPOP, which reply with the key popped from along with the element.
+pub(super) async fn bpop(
+    server: &Server,
+    index: usize,
+    keys: Vec<Bytes>,
+    end: ListEnd,
+    timeout: Option<Duration>,
+) -> Result<RespValue, RedisError> {
+    if let Some(reply) = pop_first(server, index, &keys, end, None)? {
+        return Ok(reply);
+    }
+    block(
+        server,
+        index,
+        keys,
+        BlockedOp::Pop { end, count: None },
+        timeout,
+    )
+    .await
+}
+
+pub(super) async fn blmove(
+    server: &Server,
+    index: usize,
+    source: Bytes,
+    destination: Bytes,
+    from: ListEnd,
+    to: ListEnd,
+    timeout: Option<Duration>,
+) -> Result<RespValue, RedisError> {
+    if let Some(element) =
+        move_element(server, index, source.clone(), destination.clone(), from, to)?
+    {
+        return Ok(RespValue::BulkString(element));
+    }
+
+    let op = BlockedOp::Move {
+        destination,
+        from,
+        to,
+    };
+    block(server, index, vec![source], op, timeout).await
+}
+
+pub(super) async fn bmpop(
+    server: &Server,
+    index: usize,
+    keys: Vec<Bytes>,
+    end: ListEnd,
+    count: usize,
+    timeout: Option<Duration>,
+) -> Result<RespValue, RedisError> {
+    if let Some(reply) = pop_first(server, index, &keys, end, Some(count))? {
+        return Ok(reply);
+    }
+    let op = BlockedOp::Pop {
+        end,
+        count: Some(count),
+    };
+    block(server, index, keys, op, timeout).await
+}
+
+/// Carries out `waiter`'s operation on the list at `key` on its behalf, returning its reply.
+/// None if there's no list there for it, in which case it keeps waiting.
+pub(super) fn serve(server: &Server, waiter: &Waiter, key: &Bytes) -> Option<RespValue> {
+    let index = waiter.index;
+    // Another type may have taken the key over since, which is no list to pop from
+    live_list(server, index, key).ok()??;
+
+    match &waiter.op {
+        BlockedOp::Pop { end, count } => {
+            let popped = pop_elements(server, index, key, *end, count.unwrap_or(1)).ok()??;
+            replicate_pop(server, index, key.clone(), *end, popped.len());
+            Some(pop_reply(key.clone(), popped, *count))
+        }
+        BlockedOp::Move {
+            destination,
+            from,
+            to,
+        } => {
+            let moved = move_element(server, index, key.clone(), destination.clone(), *from, *to);
+            // A destination of another type fails the client rather than leaving it waiting
+            match moved {
+                Ok(element) => element.map(RespValue::BulkString),
+                Err(err) => Some(err.into()),
+            }
+        }
+        BlockedOp::ZPop { .. } => unreachable!("sorted set pops are served by zsets::serve"),
+    }
+}
+
+/// Pops an element off `from` of `source` and pushes it onto `to` of `destination`, returning
+/// it. None if there's no source list.
+fn move_element(
+    server: &Server,
+    index: usize,
+    source: Bytes,
+    destination: Bytes,
+    from: ListEnd,
+    to: ListEnd,
+) -> Result<Option<Bytes>, RedisError> {
+    // Both types are checked first, so a wrong destination doesn't lose the element
+    if live_list(server, index, &source)?.is_none() {
+        return Ok(None);
+    }
+    live_list(server, index, &destination)?;
+
+    let db = server.db(index);
+    let element = if source == destination {
+        // A rotation, done in place as popping the last element would delete the key and its TTL
+        let mut value = db.get_mut(&source).expect("list checked above");
+        let list = list_mut(&mut value)?;
+        let element = match from {
+            ListEnd::Left => list.pop_front(),
+            ListEnd::Right => list.pop_back(),
+        }
+        .expect("lists are never empty");
+        match to {
+            ListEnd::Left => list.push_front(element.clone()),
+            ListEnd::Right => list.push_back(element.clone()),
+        }
+        element
+    } else {
+        let Some(popped) = pop_elements(server, index, &source, from, 1)? else {
+            return Ok(None);
+        };
+        let element = popped.into_iter().next().unwrap();
+        let mut value = db.get_or_insert_with(&destination, || Value::List(Arc::default()));
+        let list = list_mut(&mut value)?;
+        match to {
+            ListEnd::Left => list.push_front(element.clone()),
+            ListEnd::Right => list.push_back(element.clone()),
+        }
+        element
+    };
+    server.blocked.signal(index, &destination);
+
+    server.replicate(
+        index,
+        vec![
+            Bytes::from_static(b"LMOVE"),
+            source,
+            destination,
+            Bytes::from_static(end_name(from).as_bytes()),
+            Bytes::from_static(end_name(to).as_bytes()),
+        ],
+    );
+    Ok(Some(element))
+}
+
+/// Pops off the first of `keys` holding a list: up to `count` elements when given one, replied
+/// as an array, or else a single element.
+fn pop_first(
+    server: &Server,
+    index: usize,
+    keys: &[Bytes],
+    end: ListEnd,
+    count: Option<usize>,
+) -> Result<Option<RespValue>, RedisError> {
+    for key in keys {
+        if let Some(popped) = pop_elements(server, index, key, end, count.unwrap_or(1))? {
+            replicate_pop(server, index, key.clone(), end, popped.len());
+            return Ok(Some(pop_reply(key.clone(), popped, count)));
+        }
+    }
+    Ok(None)
+}
+
+fn pop_reply(key: Bytes, popped: Vec<Bytes>, count: Option<usize>) -> RespValue {
+    let popped = match count {
+        Some(_) => RespValue::Array(popped.into_iter().map(RespValue::BulkString).collect()),
+        None => RespValue::BulkString(popped.into_iter().next().unwrap()),
+    };
+    RespValue::Array(vec![RespValue::BulkString(key), popped])
+}
+
+/// Pops up to `count` elements off `end` of the list at `key`, deleting the key once it's
+/// empty. None if there's no such key.
+fn pop_elements(
+    server: &Server,
+    index: usize,
+    key: &Bytes,
+    end: ListEnd,
+    count: usize,
+) -> Result<Option<Vec<Bytes>>, RedisError> {
+    server.expire_if_needed(index, key);
+    let db = server.db(index);
+    let (popped, emptied) = {
+        let Some(mut value) = db.get_mut(key) else {
+            return Ok(None);
+        };
+        let list = list_mut(&mut value)?;
+        let popped: Vec<Bytes> = (0..count)
+            .map_while(|_| match end {
+                ListEnd::Left => list.pop_front(),
+                ListEnd::Right => list.pop_back(),
+            })
+            .collect();
+        (popped, list.is_empty())
+    };
+
+    if emptied {
+        db.remove(key);
+    }
+    Ok(Some(popped))
+}
+
+// Replicas pop exactly as many elements as we did, whichever command did the popping
+fn replicate_pop(server: &Server, index: usize, key: Bytes, end: ListEnd, count: usize) {
+    let command = match end {
+        ListEnd::Left => "LPOP",
+        ListEnd::Right => "RPOP",
+    };
+    server.replicate(
+        index,
+        vec![
+            Bytes::from_static(command.as_bytes()),
+            key,
+            Bytes::from(count.to_string()),
+        ],
+    );
+}
+
+/// Returns the list stored at `key`, failing with WRONGTYPE if the key holds another kind of
+/// value.
+///
+/// The list is shared with the keyspace, so it must be dropped before modifying the key or
+/// the whole list gets copied.
+fn live_list(
+    server: &Server,
+    index: usize,
+    key: &Bytes,
+) -> Result<Option<Arc<QuickList>>, RedisError> {
+    match live_entry(server, index, key).map(|entry| entry.value) {
+        Some(Value::List(list)) => Ok(Some(list)),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn list_mut(value: &mut Value) -> Result<&mut QuickList, RedisError> {
+    match value {
+        Value::List(list) => Ok(Arc::make_mut(list)),
+        _ => Err(RedisError::WrongType),
+    }
+}
+
+// Negative indexes count from the tail, -1 being the last element
+fn resolve_index(position: i64, len: usize) -> Option<usize> {
+    let position = if position < 0 {
+        len as i64 + position
+    } else {
+        position
+    };
+    (0..len as i64)
+        .contains(&position)
+        .then_some(position as usize)
+}
+
+fn end_name(end: ListEnd) -> &'static str {
+    match end {
+        ListEnd::Left => "LEFT",
+        ListEnd::Right => "RIGHT",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::Args;
+    use crate::processing::expire_key;
+    use clap::Parser;
+    use std::time::{Duration, SystemTime};
+
+    #[tokio::test]
+    async fn moving_a_list_onto_itself_keeps_its_ttl() {
+        let server = Server::start(Args::parse_from(["redis"]));
+        let key = Bytes::from_static(b"r");
+        let elements = vec![Bytes::from_static(b"x")];
+        push(&server, 0, key.clone(), elements, ListEnd::Right, false).unwrap();
+        let expire_at = SystemTime::now() + Duration::from_secs(100);
+        expire_key(&server, 0, key.clone(), expire_at);
+
+        let moved = lmove(
+            &server,
+            0,
+            key.clone(),
+            key.clone(),
+            ListEnd::Left,
+            ListEnd::Right,
+        );
+        assert!(matches!(moved, Ok(RespValue::BulkString(element)) if element == "x"));
+
+        let entry = server.db(0).get(&key).unwrap();
+        assert_eq!(entry.expire_at, Some(expire_at));
+        let list = live_list(&server, 0, &key).unwrap().unwrap();
+        assert_eq!(list.iter().collect::<Vec<_>>(), [&Bytes::from_static(b"x")]);
+    }
+
+    #[tokio::test]
+    async fn moving_within_a_list_rotates_it() {
+        let server = Server::start(Args::parse_from(["redis"]));
+        let key = Bytes::from_static(b"r");
+        let elements = [b"a", b"b", b"c"]
+            .map(|element| Bytes::from_static(element))
+            .to_vec();
+        push(&server, 0, key.clone(), elements, ListEnd::Right, false).unwrap();
+
+        lmove(
+            &server,
+            0,
+            key.clone(),
+            key.clone(),
+            ListEnd::Right,
+            ListEnd::Left,
+        )
+        .unwrap();
+        let list = live_list(&server, 0, &key).unwrap().unwrap();
+        let order: Vec<&Bytes> = list.iter().collect();
+        assert_eq!(order, ["c", "a", "b"]);
+    }
+}
@@ -0,0 +1,386 @@
+use super::live_entry;
+use crate::db::{Entry, Value};
+use crate::glob;
+use crate::models::{RedisError, RespValue, ScanParams, SetOp};
+use crate::server::Server;
+use crate::set::Set;
+use bytes::Bytes;
+use rand::seq::index::sample;
+use rand::Rng;
+use std::sync::Arc;
+
+pub(super) fn add(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    members: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let added = {
+        let db = server.db(index);
+        let mut value = db.get_or_insert_with(&key, || Value::Set(Arc::default()));
+        let set = set_mut(&mut value)?;
+        members
+            .iter()
+            .filter(|member| set.insert((*member).clone()))
+            .count()
+    };
+
+    if added > 0 {
+        let argv = [Bytes::from_static(b"SADD"), key]
+            .into_iter()
+            .chain(members);
+        server.replicate(index, argv.collect());
+    }
+    Ok(RespValue::Integer(added as i64))
+}
+
+/// SREM, which deletes the key along with its last member.
+pub(super) fn rem(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    members: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let (removed, emptied) = {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Ok(RespValue::Integer(0));
+        };
+        let set = set_mut(&mut value)?;
+        let removed = members.iter().filter(|member| set.remove(member)).count();
+        (removed, set.is_empty())
+    };
+
+    if emptied {
+        db.remove(&key);
+    }
+    if removed > 0 {
+        let argv = [Bytes::from_static(b"SREM"), key]
+            .into_iter()
+            .chain(members);
+        server.replicate(index, argv.collect());
+    }
+    Ok(RespValue::Integer(removed as i64))
+}
+
+pub(super) fn members(server: &Server, index: usize, key: Bytes) -> Result<RespValue, RedisError> {
+    let members = match live_set(server, index, &key)? {
+        Some(set) => set.iter().map(RespValue::BulkString).collect(),
+        None => vec![],
+    };
+    Ok(RespValue::Set(members))
+}
+
+pub(super) fn is_member(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    member: Bytes,
+) -> Result<RespValue, RedisError> {
+    let is_member = live_set(server, index, &key)?.is_some_and(|set| set.contains(&member));
+    Ok(RespValue::Integer(is_member as i64))
+}
+
+pub(super) fn mis_member(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    members: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    let set = live_set(server, index, &key)?.unwrap_or_default();
+    Ok(RespValue::Array(
+        members
+            .iter()
+            .map(|member| RespValue::Integer(set.contains(member) as i64))
+            .collect(),
+    ))
+}
+
+pub(super) fn card(server: &Server, index: usize, key: Bytes) -> Result<RespValue, RedisError> {
+    let length = live_set(server, index, &key)?.map_or(0, |set| set.len());
+    Ok(RespValue::Integer(length as i64))
+}
+
+/// SPOP, replying with a single member without a count and a set of them with one. Deletes
+/// the key along with its last member.
+pub(super) fn pop(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    count: Option<usize>,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let (popped, emptied) = {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Ok(match count {
+                Some(_) => RespValue::Set(vec![]),
+                None => RespValue::Null,
+            });
+        };
+        let set = set_mut(&mut value)?;
+
+        // Picked before any goes, as removing members moves others around
+        let wanted = count.unwrap_or(1).min(set.len());
+        let popped: Vec<Bytes> = sample(&mut rand::thread_rng(), set.len(), wanted)
+            .into_iter()
+            .map(|position| set.get(position).unwrap())
+            .collect();
+        for member in &popped {
+            set.remove(member);
+        }
+        (popped, set.is_empty())
+    };
+
+    if emptied {
+        db.remove(&key);
+    }
+    // Replicas are told which members went, as they would pick others
+    if !popped.is_empty() {
+        let argv = [Bytes::from_static(b"SREM"), key]
+            .into_iter()
+            .chain(popped.iter().cloned());
+        server.replicate(index, argv.collect());
+    }
+
+    Ok(match count {
+        Some(_) => RespValue::Set(popped.into_iter().map(RespValue::BulkString).collect()),
+        None => popped
+            .into_iter()
+            .next()
+            .map_or(RespValue::Null, RespValue::BulkString),
+    })
+}
+
+/// SRANDMEMBER. A positive count picks that many distinct members, a negative one picks
+/// members independently so they may repeat, and no count replies with a single member.
+pub(super) fn rand_member(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    count: Option<i64>,
+) -> Result<RespValue, RedisError> {
+    let set = live_set(server, index, &key)?.unwrap_or_default();
+    let pick = |position: usize| set.get(position).unwrap();
+
+    let mut rng = rand::thread_rng();
+    let Some(count) = count else {
+        if set.is_empty() {
+            return Ok(RespValue::Null);
+        }
+        return Ok(RespValue::BulkString(pick(rng.gen_range(0..set.len()))));
+    };
+
+    let picked: Vec<Bytes> = if set.is_empty() {
+        vec![]
+    } else if count < 0 {
+        // Grown a pick at a time, as reserving room for the count up front could fail outright
+        let mut picked = Vec::new();
+        for _ in 0..count.unsigned_abs() {
+            picked.push(pick(rng.gen_range(0..set.len())));
+        }
+        picked
+    } else if count as usize >= set.len() {
+        set.members()
+    } else {
+        sample(&mut rng, set.len(), count as usize)
+            .into_iter()
+            .map(pick)
+            .collect()
+    };
+    Ok(RespValue::Array(
+        picked.into_iter().map(RespValue::BulkString).collect(),
+    ))
+}
+
+/// SMOVE, replying 1 if `member` was in `source` and 0 otherwise.
+pub(super) fn smove(
+    server: &Server,
+    index: usize,
+    source: Bytes,
+    destination: Bytes,
+    member: Bytes,
+) -> Result<RespValue, RedisError> {
+    let Some(set) = live_set(server, index, &source)? else {
+        return Ok(RespValue::Integer(0));
+    };
+    // The destination must be a set too, even if nothing ends up moving
+    let target = live_set(server, index, &destination)?;
+    if !set.contains(&member) {
+        return Ok(RespValue::Integer(0));
+    }
+    if source == destination {
+        return Ok(RespValue::Integer(1));
+    }
+    drop((set, target));
+
+    let db = server.db(index);
+    let emptied = {
+        let mut value = db.get_mut(&source).unwrap();
+        let set = set_mut(&mut value)?;
+        set.remove(&member);
+        set.is_empty()
+    };
+    if emptied {
+        db.remove(&source);
+    }
+    {
+        let mut value = db.get_or_insert_with(&destination, || Value::Set(Arc::default()));
+        set_mut(&mut value)?.insert(member.clone());
+    }
+
+    server.replicate(
+        index,
+        vec![Bytes::from_static(b"SMOVE"), source, destination, member],
+    );
+    Ok(RespValue::Integer(1))
+}
+
+/// SINTER, SUNION and SDIFF.
+pub(super) fn combine(
+    server: &Server,
+    index: usize,
+    op: SetOp,
+    keys: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    let members = combined(server, index, op, &keys)?.members();
+    Ok(RespValue::Set(
+        members.into_iter().map(RespValue::BulkString).collect(),
+    ))
+}
+
+/// SINTERSTORE, SUNIONSTORE and SDIFFSTORE, which overwrite `destination` whatever it held,
+/// or delete it if the result is empty. Replies with the size of the result.
+pub(super) fn combine_store(
+    server: &Server,
+    index: usize,
+    op: SetOp,
+    destination: Bytes,
+    keys: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    let set = combined(server, index, op, &keys)?;
+    let length = set.len();
+
+    let db = server.db(index);
+    if set.is_empty() {
+        db.remove(&destination);
+    } else {
+        db.insert(
+            destination.clone(),
+            Entry::new(Value::Set(Arc::new(set)), None),
+        );
+    }
+
+    let command: &'static [u8] = match op {
+        SetOp::Inter => b"SINTERSTORE",
+        SetOp::Union => b"SUNIONSTORE",
+        SetOp::Diff => b"SDIFFSTORE",
+    };
+    let argv = [Bytes::from_static(command), destination]
+        .into_iter()
+        .chain(keys);
+    server.replicate(index, argv.collect());
+    Ok(RespValue::Integer(length as i64))
+}
+
+/// SINTERCARD, which stops counting once `limit` is reached unless it's 0.
+pub(super) fn inter_card(
+    server: &Server,
+    index: usize,
+    keys: Vec<Bytes>,
+    limit: usize,
+) -> Result<RespValue, RedisError> {
+    let mut sets = live_sets(server, index, &keys)?;
+    // Only members of the smallest set need checking against the others
+    sets.sort_by_key(|set| set.len());
+    let Some((smallest, others)) = sets.split_first() else {
+        return Ok(RespValue::Integer(0));
+    };
+
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    let count = smallest
+        .iter()
+        .filter(|member| others.iter().all(|set| set.contains(member)))
+        .take(limit)
+        .count();
+    Ok(RespValue::Integer(count as i64))
+}
+
+pub(super) fn scan(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    params: ScanParams,
+) -> Result<RespValue, RedisError> {
+    let (cursor, members) = match live_set(server, index, &key)? {
+        Some(set) => set.scan(params.cursor, params.count),
+        None => (0, vec![]),
+    };
+
+    let reply = members
+        .into_iter()
+        .filter(|member| {
+            params
+                .pattern
+                .as_ref()
+                .is_none_or(|pattern| glob::matches(pattern, member))
+        })
+        .map(RespValue::BulkString)
+        .collect();
+
+    Ok(RespValue::Array(vec![
+        RespValue::bulk(cursor.to_string()),
+        RespValue::Array(reply),
+    ]))
+}
+
+// Combines the sets at `keys`, missing keys counting as empty sets
+fn combined(server: &Server, index: usize, op: SetOp, keys: &[Bytes]) -> Result<Set, RedisError> {
+    let sets = live_sets(server, index, keys)?;
+    let (first, others) = sets.split_first().unwrap();
+
+    Ok(match op {
+        SetOp::Inter => {
+            // Only members of the smallest set need checking against the others
+            let smallest = sets.iter().min_by_key(|set| set.len()).unwrap();
+            smallest
+                .iter()
+                .filter(|member| sets.iter().all(|set| set.contains(member)))
+                .collect()
+        }
+        SetOp::Union => sets.iter().flat_map(|set| set.iter()).collect(),
+        SetOp::Diff => first
+            .iter()
+            .filter(|member| !others.iter().any(|set| set.contains(member)))
+            .collect(),
+    })
+}
+
+// Every set at `keys`, with an empty one for each missing key. Fails if any key isn't a set
+fn live_sets(server: &Server, index: usize, keys: &[Bytes]) -> Result<Vec<Arc<Set>>, RedisError> {
+    keys.iter()
+        .map(|key| Ok(live_set(server, index, key)?.unwrap_or_default()))
+        .collect()
+}
+
+/// Returns the set stored at `key`, failing with WRONGTYPE if the key holds another kind of
+/// value.
+///
+/// The set is shared with the keyspace, so it must be dropped before modifying the key or the
+/// whole set gets copied.
+fn live_set(server: &Server, index: usize, key: &Bytes) -> Result<Option<Arc<Set>>, RedisError> {
+    match live_entry(server, index, key).map(|entry| entry.value) {
+        Some(Value::Set(set)) => Ok(Some(set)),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn set_mut(value: &mut Value) -> Result<&mut Set, RedisError> {
+    match value {
+        Value::Set(set) => Ok(Arc::make_mut(set)),
+        _ => Err(RedisError::WrongType),
+    }
+}
@@ -0,0 +1,772 @@
+use super::{block, live_entry, resolve_range};
+use crate::blocking::{BlockedOp, Waiter};
+use crate::db::{Entry, Value};
+use crate::models::{
+    format_double, Aggregate, LexBound, RedisError, RespValue, SetOp, ZAddParams, ZCombineParams,
+    ZRangeBy, ZRangeParams,
+};
+use crate::resp::Protocol;
+use crate::server::Server;
+use crate::set::Set;
+use crate::zset::SortedSet;
+use bytes::Bytes;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+/// ZADD and ZINCRBY. Replies with how many members were added, or changed too with CH. With
+/// INCR it's the new score instead, or a null if a condition kept the member from changing.
+pub(super) fn add(
+    server: &Server,
+    index: usize,
+    params: ZAddParams,
+) -> Result<RespValue, RedisError> {
+    let key = params.key;
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let (added, updated, emptied) = {
+        let mut value = db.get_or_insert_with(&key, || Value::ZSet(Arc::default()));
+        let zset = zset_mut(&mut value)?;
+
+        let (mut added, mut updated) = (vec![], vec![]);
+        for (score, member) in params.pairs {
+            let score = match zset.score(&member) {
+                None if params.xx => continue,
+                None => score,
+                Some(_) if params.nx => continue,
+                Some(current) => {
+                    let score = if params.incr { current + score } else { score };
+                    if score.is_nan() {
+                        return Err(RedisError::Err(
+                            "resulting score is not a number (NaN)".to_string(),
+                        ));
+                    }
+                    if (params.gt && score <= current) || (params.lt && score >= current) {
+                        continue;
+                    }
+                    if score == current {
+                        updated.push((member, score, false));
+                        continue;
+                    }
+                    score
+                }
+            };
+            match zset.insert(member.clone(), score) {
+                None => added.push((member, score)),
+                Some(_) => updated.push((member, score, true)),
+            }
+        }
+        (added, updated, zset.is_empty())
+    };
+
+    // XX on a missing key leaves nothing behind
+    if emptied {
+        db.remove(&key);
+    }
+    if !added.is_empty() {
+        server.blocked.signal(index, &key);
+    }
+
+    // Replicas get plain scores, so INCR isn't applied twice
+    let changed: Vec<(Bytes, f64)> = added
+        .iter()
+        .cloned()
+        .chain(
+            updated
+                .iter()
+                .filter(|(_, _, changed)| *changed)
+                .map(|(member, score, _)| (member.clone(), *score)),
+        )
+        .collect();
+    if !changed.is_empty() {
+        let argv = [Bytes::from_static(b"ZADD"), key].into_iter().chain(
+            changed
+                .iter()
+                .flat_map(|(member, score)| [Bytes::from(format_double(*score)), member.clone()]),
+        );
+        server.replicate(index, argv.collect());
+    }
+
+    if params.incr {
+        let score = added
+            .first()
+            .map(|(_, score)| *score)
+            .or(updated.first().map(|(_, score, _)| *score));
+        return Ok(score.map_or(RespValue::Null, RespValue::Double));
+    }
+    let count = if params.ch {
+        changed.len()
+    } else {
+        added.len()
+    };
+    Ok(RespValue::Integer(count as i64))
+}
+
+/// ZREM, which deletes the key along with its last member.
+pub(super) fn rem(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    members: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let (removed, emptied) = {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Ok(RespValue::Integer(0));
+        };
+        let zset = zset_mut(&mut value)?;
+        let removed = members
+            .iter()
+            .filter(|member| zset.remove(member).is_some())
+            .count();
+        (removed, zset.is_empty())
+    };
+
+    if emptied {
+        db.remove(&key);
+    }
+    if removed > 0 {
+        let argv = [Bytes::from_static(b"ZREM"), key]
+            .into_iter()
+            .chain(members);
+        server.replicate(index, argv.collect());
+    }
+    Ok(RespValue::Integer(removed as i64))
+}
+
+pub(super) fn score(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    member: Bytes,
+) -> Result<RespValue, RedisError> {
+    let score = live_zset(server, index, &key)?.and_then(|zset| zset.score(&member));
+    Ok(score.map_or(RespValue::Null, RespValue::Double))
+}
+
+pub(super) fn mscore(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    members: Vec<Bytes>,
+) -> Result<RespValue, RedisError> {
+    let zset = live_zset(server, index, &key)?.unwrap_or_default();
+    Ok(RespValue::Array(
+        members
+            .iter()
+            .map(|member| {
+                zset.score(member)
+                    .map_or(RespValue::Null, RespValue::Double)
+            })
+            .collect(),
+    ))
+}
+
+pub(super) fn card(server: &Server, index: usize, key: Bytes) -> Result<RespValue, RedisError> {
+    let length = live_zset(server, index, &key)?.map_or(0, |zset| zset.len());
+    Ok(RespValue::Integer(length as i64))
+}
+
+/// ZRANK and ZREVRANK, the latter counting from the highest score.
+pub(super) fn rank(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    member: Bytes,
+    withscore: bool,
+    rev: bool,
+) -> Result<RespValue, RedisError> {
+    let zset = live_zset(server, index, &key)?;
+    let Some((rank, score)) = zset
+        .as_ref()
+        .and_then(|zset| Some((zset.rank(&member)?, zset.score(&member)?)))
+    else {
+        return Ok(if withscore {
+            RespValue::NullArray
+        } else {
+            RespValue::Null
+        });
+    };
+
+    let rank = if rev {
+        zset.unwrap().len() - 1 - rank
+    } else {
+        rank
+    };
+    if withscore {
+        return Ok(RespValue::Array(vec![
+            RespValue::Integer(rank as i64),
+            RespValue::Double(score),
+        ]));
+    }
+    Ok(RespValue::Integer(rank as i64))
+}
+
+pub(super) fn range(
+    server: &Server,
+    index: usize,
+    params: ZRangeParams,
+    protocol: Protocol,
+) -> Result<RespValue, RedisError> {
+    let picked = match live_zset(server, index, &params.key)? {
+        Some(zset) => select(&zset, &params),
+        None => vec![],
+    };
+
+    if params.withscores {
+        return Ok(RespValue::Array(with_scores(picked, protocol)));
+    }
+    Ok(RespValue::Array(
+        picked
+            .into_iter()
+            .map(|(member, _)| RespValue::BulkString(member))
+            .collect(),
+    ))
+}
+
+/// ZRANGESTORE, which overwrites `destination` whatever it held, or deletes it if nothing was
+/// picked. Replies with the number of members stored.
+pub(super) fn range_store(
+    server: &Server,
+    index: usize,
+    destination: Bytes,
+    params: ZRangeParams,
+) -> Result<RespValue, RedisError> {
+    let picked = match live_zset(server, index, &params.key)? {
+        Some(zset) => select(&zset, &params),
+        None => vec![],
+    };
+    let length = store(server, index, destination, picked.into_iter().collect());
+    Ok(RespValue::Integer(length as i64))
+}
+
+/// ZUNION, ZINTER and ZDIFF, replying with the members from the lowest score up.
+pub(super) fn combine(
+    server: &Server,
+    index: usize,
+    params: ZCombineParams,
+    protocol: Protocol,
+) -> Result<RespValue, RedisError> {
+    let zset = combined(server, index, &params)?;
+    let pairs: Vec<(Bytes, f64)> = zset
+        .iter()
+        .map(|(member, score)| (member.clone(), score))
+        .collect();
+
+    if params.withscores {
+        return Ok(RespValue::Array(with_scores(pairs, protocol)));
+    }
+    Ok(RespValue::Array(
+        pairs
+            .into_iter()
+            .map(|(member, _)| RespValue::BulkString(member))
+            .collect(),
+    ))
+}
+
+/// ZUNIONSTORE, ZINTERSTORE and ZDIFFSTORE, which overwrite `destination` whatever it held, or
+/// delete it if the result is empty. Replies with the size of the result.
+pub(super) fn combine_store(
+    server: &Server,
+    index: usize,
+    destination: Bytes,
+    params: ZCombineParams,
+) -> Result<RespValue, RedisError> {
+    let zset = combined(server, index, &params)?;
+    let length = store(server, index, destination, zset);
+    Ok(RespValue::Integer(length as i64))
+}
+
+/// ZINTERCARD, which stops counting once `limit` is reached unless it's 0.
+pub(super) fn inter_card(
+    server: &Server,
+    index: usize,
+    keys: Vec<Bytes>,
+    limit: usize,
+) -> Result<RespValue, RedisError> {
+    let mut inputs = live_inputs(server, index, &keys)?;
+    // Only members of the smallest set need checking against the others
+    inputs.sort_by_key(|input| input.len());
+    let (smallest, others) = inputs.split_first().unwrap();
+
+    let limit = if limit == 0 { usize::MAX } else { limit };
+    let count = smallest
+        .iter()
+        .filter(|(member, _)| others.iter().all(|input| input.score(member).is_some()))
+        .take(limit)
+        .count();
+    Ok(RespValue::Integer(count as i64))
+}
+
+/// ZCOUNT and ZLEXCOUNT.
+pub(super) fn count(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    by: ZRangeBy,
+) -> Result<RespValue, RedisError> {
+    let count = match live_zset(server, index, &key)? {
+        Some(zset) => {
+            let (start, end) = rank_range(&zset, &by, false);
+            end - start
+        }
+        None => 0,
+    };
+    Ok(RespValue::Integer(count as i64))
+}
+
+/// ZREMRANGEBYRANK, ZREMRANGEBYSCORE and ZREMRANGEBYLEX, which delete the key along with its
+/// last member.
+pub(super) fn rem_range(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    by: ZRangeBy,
+) -> Result<RespValue, RedisError> {
+    server.expire_if_needed(index, &key);
+    let db = server.db(index);
+    let (removed, emptied) = {
+        let Some(mut value) = db.get_mut(&key) else {
+            return Ok(RespValue::Integer(0));
+        };
+        let zset = zset_mut(&mut value)?;
+
+        let (start, end) = rank_range(zset, &by, false);
+        let removed: Vec<Bytes> = zset
+            .iter_from(start)
+            .take(end - start)
+            .map(|(member, _)| member.clone())
+            .collect();
+        for member in &removed {
+            zset.remove(member);
+        }
+        (removed, zset.is_empty())
+    };
+
+    if emptied {
+        db.remove(&key);
+    }
+    let count = removed.len();
+    if count > 0 {
+        let argv = [Bytes::from_static(b"ZREM"), key]
+            .into_iter()
+            .chain(removed);
+        server.replicate(index, argv.collect());
+    }
+    Ok(RespValue::Integer(count as i64))
+}
+
+/// ZPOPMIN and ZPOPMAX. A single member comes with its score as a flat pair, while a count
+/// gets a pair per member under RESP3.
+pub(super) fn pop(
+    server: &Server,
+    index: usize,
+    key: Bytes,
+    max: bool,
+    count: Option<usize>,
+    protocol: Protocol,
+) -> Result<RespValue, RedisError> {
+    let popped = pop_members(server, index, &key, max, count.unwrap_or(1))?;
+    let protocol = match count {
+        Some(_) => protocol,
+        None => Protocol::Resp2,
+    };
+    Ok(RespValue::Array(with_scores(popped, protocol)))
+}
+
+/// ZMPOP, which pops from the first of `keys` holding a sorted set.
+pub(super) fn mpop(
+    server: &Server,
+    index: usize,
+    keys: Vec<Bytes>,
+    max: bool,
+    count: usize,
+) -> Result<RespValue, RedisError> {
+    let reply = pop_first(server, index, &keys, max, Some(count))?;
+    Ok(reply.unwrap_or(RespValue::NullArray))
+}
+
+/// BZPOPMIN and BZPOPMAX, which reply with the key popped from along with the member and its
+/// score.
+pub(super) async fn bpop(
+    server: &Server,
+    index: usize,
+    keys: Vec<Bytes>,
+    max: bool,
+    timeout: Option<Duration>,
+) -> Result<RespValue, RedisError> {
+    if let Some(reply) = pop_first(server, index, &keys, max, None)? {
+        return Ok(reply);
+    }
+    block(
+        server,
+        index,
+        keys,
+        BlockedOp::ZPop { max, count: None },
+        timeout,
+    )
+    .await
+}
+
+pub(super) async fn bmpop(
+    server: &Server,
+    index: usize,
+    keys: Vec<Bytes>,
+    max: bool,
+    count: usize,
+    timeout: Option<Duration>,
+) -> Result<RespValue, RedisError> {
+    if let Some(reply) = pop_first(server, index, &keys, max, Some(count))? {
+        return Ok(reply);
+    }
+    let op = BlockedOp::ZPop {
+        max,
+        count: Some(count),
+    };
+    block(server, index, keys, op, timeout).await
+}
+
+/// Pops from the sorted set at `key` on behalf of `waiter`, returning its reply. None if
+/// there's no sorted set there for it, in which case it keeps waiting.
+pub(super) fn serve(server: &Server, waiter: &Waiter, key: &Bytes) -> Option<RespValue> {
+    let BlockedOp::ZPop { max, count } = waiter.op else {
+        unreachable!("only sorted set pops are served from sorted sets");
+    };
+    let index = waiter.index;
+    live_zset(server, index, key).ok()??;
+
+    let popped = pop_members(server, index, key, max, count.unwrap_or(1)).ok()?;
+    Some(pop_reply(key.clone(), popped, count))
+}
+
+// Pops off the first of `keys` holding a sorted set: up to `count` members when given one, or
+// else a single member
+fn pop_first(
+    server: &Server,
+    index: usize,
+    keys: &[Bytes],
+    max: bool,
+    count: Option<usize>,
+) -> Result<Option<RespValue>, RedisError> {
+    for key in keys {
+        let popped = pop_members(server, index, key, max, count.unwrap_or(1))?;
+        if !popped.is_empty() {
+            return Ok(Some(pop_reply(key.clone(), popped, count)));
+        }
+    }
+    Ok(None)
+}
+
+// The reply of the blocking pops and ZMPOP: the key, then the member and its score without a
+// count, or else an array of member and score pairs
+fn pop_reply(key: Bytes, popped: Vec<(Bytes, f64)>, count: Option<usize>) -> RespValue {
+    let mut reply = vec![RespValue::BulkString(key)];
+    match count {
+        Some(_) => reply.push(RespValue::Array(with_scores(popped, Protocol::Resp3))),
+        None => reply.extend(with_scores(popped, Protocol::Resp2)),
+    }
+    RespValue::Array(reply)
+}
+
+// Pops up to `count` members from the low or high end of the sorted set at `key`, deleting the
+// key along with its last member
+fn pop_members(
+    server: &Server,
+    index: usize,
+    key: &Bytes,
+    max: bool,
+    count: usize,
+) -> Result<Vec<(Bytes, f64)>, RedisError> {
+    server.expire_if_needed(index, key);
+    let db = server.db(index);
+    let (popped, emptied) = {
+        let Some(mut value) = db.get_mut(key) else {
+            return Ok(vec![]);
+        };
+        let zset = zset_mut(&mut value)?;
+
+        let popped: Vec<(Bytes, f64)> = if max {
+            let picked = zset.iter().rev().take(count);
+            picked
+                .map(|(member, score)| (member.clone(), score))
+                .collect()
+        } else {
+            let picked = zset.iter().take(count);
+            picked
+                .map(|(member, score)| (member.clone(), score))
+                .collect()
+        };
+        for (member, _) in &popped {
+            zset.remove(member);
+        }
+        (popped, zset.is_empty())
+    };
+
+    if emptied {
+        db.remove(key);
+    }
+    // Members with equal scores come out in the same order on replicas, so they pop the same
+    if !popped.is_empty() {
+        let command: &'static [u8] = if max { b"ZPOPMAX" } else { b"ZPOPMIN" };
+        server.replicate(
+            index,
+            vec![
+                Bytes::from_static(command),
+                key.clone(),
+                Bytes::from(popped.len().to_string()),
+            ],
+        );
+    }
+    Ok(popped)
+}
+
+// Stores `zset` at `destination` whatever it held, or deletes it if `zset` is empty. Returns its
+// size
+fn store(server: &Server, index: usize, destination: Bytes, zset: SortedSet) -> usize {
+    let length = zset.len();
+
+    // Replicas get the result itself rather than working it out again
+    let db = server.db(index);
+    db.remove(&destination);
+    server.replicate(index, vec![Bytes::from_static(b"DEL"), destination.clone()]);
+    if !zset.is_empty() {
+        let argv =
+            [Bytes::from_static(b"ZADD"), destination.clone()]
+                .into_iter()
+                .chain(zset.iter().flat_map(|(member, score)| {
+                    [Bytes::from(format_double(score)), member.clone()]
+                }));
+        server.replicate(index, argv.collect());
+        db.insert(
+            destination.clone(),
+            Entry::new(Value::ZSet(Arc::new(zset)), None),
+        );
+        server.blocked.signal(index, &destination);
+    }
+    length
+}
+
+// Combines the sets at `keys` the way ZUNION, ZINTER or ZDIFF do
+fn combined(
+    server: &Server,
+    index: usize,
+    params: &ZCombineParams,
+) -> Result<SortedSet, RedisError> {
+    let inputs = live_inputs(server, index, &params.keys)?;
+    let weights = match &params.weights {
+        Some(weights) => weights.clone(),
+        None => vec![1.0; inputs.len()],
+    };
+    // Infinity times zero counts as zero
+    let weighted = |score: f64, weight: f64| {
+        let score = score * weight;
+        if score.is_nan() {
+            0.0
+        } else {
+            score
+        }
+    };
+    let aggregate = |a: f64, b: f64| match params.aggregate {
+        Aggregate::Sum if (a + b).is_nan() => 0.0,
+        Aggregate::Sum => a + b,
+        Aggregate::Min => a.min(b),
+        Aggregate::Max => a.max(b),
+    };
+
+    let mut scores: HashMap<Bytes, f64> = HashMap::new();
+    match params.op {
+        SetOp::Union => {
+            for (input, weight) in inputs.iter().zip(&weights) {
+                for (member, score) in input.iter() {
+                    let score = weighted(score, *weight);
+                    scores
+                        .entry(member)
+                        .and_modify(|current| *current = aggregate(*current, score))
+                        .or_insert(score);
+                }
+            }
+        }
+        SetOp::Inter => {
+            // Only members of the smallest set need checking against the others
+            let mut order: Vec<usize> = (0..inputs.len()).collect();
+            order.sort_by_key(|position| inputs[*position].len());
+            let (smallest, others) = order.split_first().unwrap();
+
+            'members: for (member, score) in inputs[*smallest].iter() {
+                let mut total = weighted(score, weights[*smallest]);
+                for other in others {
+                    let Some(score) = inputs[*other].score(&member) else {
+                        continue 'members;
+                    };
+                    total = aggregate(total, weighted(score, weights[*other]));
+                }
+                scores.insert(member, total);
+            }
+        }
+        SetOp::Diff => {
+            let (first, others) = inputs.split_first().unwrap();
+            for (member, score) in first.iter() {
+                if others.iter().all(|input| input.score(&member).is_none()) {
+                    scores.insert(member, score);
+                }
+            }
+        }
+    }
+    Ok(scores.into_iter().collect())
+}
+
+/// A sorted set or a plain set given to ZUNION and friends, the members of a plain set all
+/// scoring 1. A missing key counts as an empty set.
+enum Input {
+    ZSet(Arc<SortedSet>),
+    Set(Arc<Set>),
+}
+
+impl Input {
+    fn len(&self) -> usize {
+        match self {
+            Input::ZSet(zset) => zset.len(),
+            Input::Set(set) => set.len(),
+        }
+    }
+
+    fn score(&self, member: &[u8]) -> Option<f64> {
+        match self {
+            Input::ZSet(zset) => zset.score(member),
+            Input::Set(set) => set.contains(member).then_some(1.0),
+        }
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = (Bytes, f64)> + '_> {
+        match self {
+            Input::ZSet(zset) => {
+                Box::new(zset.iter().map(|(member, score)| (member.clone(), score)))
+            }
+            Input::Set(set) => Box::new(set.iter().map(|member| (member, 1.0))),
+        }
+    }
+}
+
+fn live_inputs(server: &Server, index: usize, keys: &[Bytes]) -> Result<Vec<Input>, RedisError> {
+    keys.iter()
+        .map(
+            |key| match live_entry(server, index, key).map(|entry| entry.value) {
+                Some(Value::ZSet(zset)) => Ok(Input::ZSet(zset)),
+                Some(Value::Set(set)) => Ok(Input::Set(set)),
+                Some(_) => Err(RedisError::WrongType),
+                None => Ok(Input::ZSet(Arc::default())),
+            },
+        )
+        .collect()
+}
+
+// The members ZRANGE picks, in the order it walks them
+fn select(zset: &SortedSet, params: &ZRangeParams) -> Vec<(Bytes, f64)> {
+    let (start, end) = rank_range(zset, &params.by, params.rev);
+    let (offset, count) = match params.limit {
+        // A negative offset picks nothing at all
+        Some((offset, _)) if offset < 0 => return vec![],
+        Some((offset, count)) => (
+            offset as usize,
+            usize::try_from(count).unwrap_or(usize::MAX),
+        ),
+        None => (0, usize::MAX),
+    };
+    let wanted = (end - start).saturating_sub(offset).min(count);
+    if wanted == 0 {
+        return vec![];
+    }
+
+    let member = |(member, score): (&Bytes, f64)| (member.clone(), score);
+    if params.rev {
+        zset.iter_rev_from(end - 1 - offset)
+            .take(wanted)
+            .map(member)
+            .collect()
+    } else {
+        zset.iter_from(start + offset)
+            .take(wanted)
+            .map(member)
+            .collect()
+    }
+}
+
+// The ranks of the members within `by`, from the first up to but excluding the last, counting
+// from the lowest score. With `rev` a rank range counts from the highest score instead
+fn rank_range(zset: &SortedSet, by: &ZRangeBy, rev: bool) -> (usize, usize) {
+    let (start, end) = match by {
+        ZRangeBy::Rank(start, stop) => match resolve_range(*start, *stop, zset.len()) {
+            Some((start, stop)) if rev => (zset.len() - 1 - stop, zset.len() - start),
+            Some((start, stop)) => (start, stop + 1),
+            None => (0, 0),
+        },
+        ZRangeBy::Score(min, max) => (
+            zset.partition_point(|_, score| {
+                score < min.score || (min.exclusive && score == min.score)
+            }),
+            zset.partition_point(|_, score| {
+                score < max.score || (!max.exclusive && score == max.score)
+            }),
+        ),
+        // Only meaningful when every member has the same score, so scores are left out
+        ZRangeBy::Lex(min, max) => (
+            zset.partition_point(|member, _| match min {
+                LexBound::Min => false,
+                LexBound::Max => true,
+                LexBound::Inclusive(bound) => member < bound,
+                LexBound::Exclusive(bound) => member <= bound,
+            }),
+            zset.partition_point(|member, _| match max {
+                LexBound::Min => false,
+                LexBound::Max => true,
+                LexBound::Inclusive(bound) => member <= bound,
+                LexBound::Exclusive(bound) => member < bound,
+            }),
+        ),
+    };
+    (start, end.max(start))
+}
+
+// Members paired up with their scores: flat under RESP2, and as a pair per member under RESP3
+fn with_scores(pairs: Vec<(Bytes, f64)>, protocol: Protocol) -> Vec<RespValue> {
+    match protocol {
+        Protocol::Resp3 => pairs
+            .into_iter()
+            .map(|(member, score)| {
+                RespValue::Array(vec![
+                    RespValue::BulkString(member),
+                    RespValue::Double(score),
+                ])
+            })
+            .collect(),
+        Protocol::Resp2 => pairs
+            .into_iter()
+            .flat_map(|(member, score)| [RespValue::BulkString(member), RespValue::Double(score)])
+            .collect(),
+    }
+}
+
+/// Returns the sorted set stored at `key`, failing with WRONGTYPE if the key holds another kind
+/// of value.
+///
+/// The sorted set is shared with the keyspace, so it must be dropped before modifying the key
+/// or the whole sorted set gets copied.
+fn live_zset(
+    server: &Server,
+    index: usize,
+    key: &Bytes,
+) -> Result<Option<Arc<SortedSet>>, RedisError> {
+    match live_entry(server, index, key).map(|entry| entry.value) {
+        Some(Value::ZSet(zset)) => Ok(Some(zset)),
+        Some(_) => Err(RedisError::WrongType),
+        None => Ok(None),
+    }
+}
+
+fn zset_mut(value: &mut Value) -> Result<&mut SortedSet, RedisError> {
+    match value {
+        Value::ZSet(zset) => Ok(Arc::make_mut(zset)),
+        _ => Err(RedisError::WrongType),
+    }
+}
@@ -0,0 +1,182 @@
+use bytes::Bytes;
+use std::collections::VecDeque;
+
+// Most elements a chunk holds before an insertion splits it
+const CHUNK_SIZE: usize = 128;
+
+/// A list stored as a deque of small chunks, much like Redis' quicklist.
+///
+/// Pushing and popping at either end is cheap, and inserting or removing in the middle only
+/// shifts the elements of one chunk. Chunks are never empty.
+#[derive(Debug, Clone, Default)]
+pub struct QuickList {
+    chunks: VecDeque<VecDeque<Bytes>>,
+    len: usize,
+}
+
+impl QuickList {
+    pub fn new() -> QuickList {
+        QuickList::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn push_front(&mut self, value: Bytes) {
+        match self.chunks.front_mut() {
+            Some(chunk) if chunk.len() < CHUNK_SIZE => chunk.push_front(value),
+            _ => self.chunks.push_front(VecDeque::from([value])),
+        }
+        self.len += 1;
+    }
+
+    pub fn push_back(&mut self, value: Bytes) {
+        match self.chunks.back_mut() {
+            Some(chunk) if chunk.len() < CHUNK_SIZE => chunk.push_back(value),
+            _ => self.chunks.push_back(VecDeque::from([value])),
+        }
+        self.len += 1;
+    }
+
+    pub fn pop_front(&mut self) -> Option<Bytes> {
+        let chunk = self.chunks.front_mut()?;
+        let value = chunk.pop_front();
+        if chunk.is_empty() {
+            self.chunks.pop_front();
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn pop_back(&mut self) -> Option<Bytes> {
+        let chunk = self.chunks.back_mut()?;
+        let value = chunk.pop_back();
+        if chunk.is_empty() {
+            self.chunks.pop_back();
+        }
+        self.len -= 1;
+        value
+    }
+
+    pub fn get(&self, index: usize) -> Option<&Bytes> {
+        let (chunk, offset) = self.locate(index)?;
+        self.chunks[chunk].get(offset)
+    }
+
+    /// Replaces the element at `index`, returning false if it is out of range.
+    pub fn set(&mut self, index: usize, value: Bytes) -> bool {
+        let Some((chunk, offset)) = self.locate(index) else {
+            return false;
+        };
+        self.chunks[chunk][offset] = value;
+        true
+    }
+
+    /// Inserts `value` so it ends up at `index`, shifting what follows. `index` may be the length.
+    pub fn insert(&mut self, index: usize, value: Bytes) {
+        let Some((chunk, offset)) = self.locate(index) else {
+            return self.push_back(value);
+        };
+
+        let target = &mut self.chunks[chunk];
+        target.insert(offset, value);
+        if target.len() > CHUNK_SIZE {
+            let tail = target.split_off(target.len() / 2);
+            self.chunks.insert(chunk + 1, tail);
+        }
+        self.len += 1;
+    }
+
+    pub fn remove(&mut self, index: usize) -> Option<Bytes> {
+        let (chunk, offset) = self.locate(index)?;
+        let value = self.chunks[chunk].remove(offset);
+        if self.chunks[chunk].is_empty() {
+            self.chunks.remove(chunk);
+        }
+        self.len -= 1;
+        value
+    }
+
+    /// Drops the first `count` elements, whole chunks at a time where possible.
+    pub fn drop_front(&mut self, mut count: usize) {
+        count = count.min(self.len);
+        self.len -= count;
+        while count > 0 {
+            let chunk = self.chunks.front_mut().unwrap();
+            if chunk.len() <= count {
+                count -= chunk.len();
+                self.chunks.pop_front();
+            } else {
+                chunk.drain(..count);
+                count = 0;
+            }
+        }
+    }
+
+    /// Drops the last `count` elements, whole chunks at a time where possible.
+    pub fn drop_back(&mut self, mut count: usize) {
+        count = count.min(self.len);
+        self.len -= count;
+        while count > 0 {
+            let chunk = self.chunks.back_mut().unwrap();
+            if chunk.len() <= count {
+                count -= chunk.len();
+                self.chunks.pop_back();
+            } else {
+                chunk.truncate(chunk.len() - count);
+                count = 0;
+            }
+        }
+    }
+
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = &Bytes> {
+        self.chunks.iter().flatten()
+    }
+
+    /// Iterates from `index` onwards, skipping the chunks before it rather than their elements.
+    pub fn iter_from(&self, index: usize) -> impl Iterator<Item = &Bytes> {
+        let (chunk, offset) = self.locate(index).unwrap_or((self.chunks.len(), 0));
+        self.chunks.range(chunk..).flatten().skip(offset)
+    }
+
+    /// The chunk holding `index`, and the position of the element within it.
+    fn locate(&self, mut index: usize) -> Option<(usize, usize)> {
+        if index >= self.len {
+            return None;
+        }
+
+        // Walk from whichever end is closer
+        if index < self.len / 2 {
+            for (position, chunk) in self.chunks.iter().enumerate() {
+                if index < chunk.len() {
+                    return Some((position, index));
+                }
+                index -= chunk.len();
+            }
+        } else {
+            let mut from_back = self.len - 1 - index;
+            for (position, chunk) in self.chunks.iter().enumerate().rev() {
+                if from_back < chunk.len() {
+                    return Some((position, chunk.len() - 1 - from_back));
+                }
+                from_back -= chunk.len();
+            }
+        }
+        None
+    }
+}
+
+impl FromIterator<Bytes> for QuickList {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> QuickList {
+        let mut list = QuickList::new();
+        for value in iter {
+            list.push_back(value);
+        }
+        list
+    }
+}
@@ -1,216 +1,653 @@
-use dashmap::DashMap;
-use deku::bitvec::*;
-use deku::prelude::*;
-use std::fs::File;
-use std::ops::{Add, Sub};
+use crate::db::{Entry, Value};
+use crate::hash::Hash;
+use crate::listpack;
+use crate::models::format_double;
+use crate::processing::REDIS_VERSION;
+use crate::quicklist::QuickList;
+use crate::server::Server;
+use crate::set::Set;
+use crate::zset::SortedSet;
+use anyhow::{bail, Context};
+use bytes::Bytes;
 use std::path::Path;
 use std::sync::Arc;
 use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-#[derive(Debug, DekuRead)]
-struct RDB {
-    header: Header,
-    metadata: Metadata,
-    database: Database,
-}
+// Opcodes preceding the sections of the file, see rdb.h in the Redis sources
+const OPCODE_SLOT_INFO: u8 = 0xF4;
+const OPCODE_FUNCTION2: u8 = 0xF5;
+const OPCODE_MODULE_AUX: u8 = 0xF7;
+const OPCODE_IDLE: u8 = 0xF8;
+const OPCODE_FREQ: u8 = 0xF9;
+const OPCODE_AUX: u8 = 0xFA;
+const OPCODE_RESIZEDB: u8 = 0xFB;
+const OPCODE_EXPIRETIME_MS: u8 = 0xFC;
+const OPCODE_EXPIRETIME: u8 = 0xFD;
+const OPCODE_SELECTDB: u8 = 0xFE;
+const OPCODE_EOF: u8 = 0xFF;
 
-#[derive(Debug, DekuRead)]
-#[deku(magic = b"REDIS")]
-struct Header {
-    #[deku(bytes = "4")]
-    rd_version: [u8; 4],
-}
+const TYPE_STRING: u8 = 0;
+const TYPE_LIST: u8 = 1;
+const TYPE_SET: u8 = 2;
+const TYPE_ZSET: u8 = 3;
+const TYPE_HASH: u8 = 4;
+const TYPE_ZSET_2: u8 = 5;
+const TYPE_LIST_ZIPLIST: u8 = 10;
+const TYPE_SET_INTSET: u8 = 11;
+const TYPE_ZSET_ZIPLIST: u8 = 12;
+const TYPE_HASH_ZIPLIST: u8 = 13;
+const TYPE_LIST_QUICKLIST: u8 = 14;
+const TYPE_HASH_LISTPACK: u8 = 16;
+const TYPE_ZSET_LISTPACK: u8 = 17;
+const TYPE_LIST_QUICKLIST_2: u8 = 18;
+const TYPE_SET_LISTPACK: u8 = 20;
+
+// Hashes with fields that expire, as written by Redis 7.4
+const TYPE_HASH_METADATA: u8 = 24;
+const TYPE_HASH_LISTPACK_EX: u8 = 25;
+
+// How the nodes of a TYPE_LIST_QUICKLIST_2 list hold their elements
+const QUICKLIST_NODE_PLAIN: usize = 1;
+const QUICKLIST_NODE_PACKED: usize = 2;
 
-#[derive(Debug, DekuRead)]
-struct Metadata {
-    #[deku(until = "|v: &u8| *v == 0xFA")]
-    start: Vec<u8>,
+// Elements per listpack when saving lists, the same as a chunk of ours
+const LISTPACK_SIZE: usize = 128;
 
-    #[deku(bytes = "4")]
-    rd_version: [u8; 4],
+// Special encodings of strings, flagged by the top two bits of their length being set
+const ENC_INT8: u64 = 0;
+const ENC_INT16: u64 = 1;
+const ENC_INT32: u64 = 2;
+const ENC_LZF: u64 = 3;
 
-    #[deku(until = "|v: &u8| *v == 0xFA")]
-    values: Vec<u8>,
+/// A length as found in the file, which may instead announce a specially encoded string.
+enum Length {
+    Plain(u64),
+    Encoded(u64),
 }
 
-#[derive(Debug, DekuRead)]
-struct Database {
-    #[deku(until = "|v: &u8| *v == 0xFE")]
-    start: Vec<u8>,
+/// Cursor over the bytes of an RDB file.
+struct RdbReader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
 
-    index: u8,
+impl<'a> RdbReader<'a> {
+    fn read_bytes(&mut self, count: usize) -> anyhow::Result<&'a [u8]> {
+        let end = self
+            .pos
+            .checked_add(count)
+            .filter(|end| *end <= self.buf.len())
+            .context("Unexpected end of RDB file")?;
+        let bytes = &self.buf[self.pos..end];
+        self.pos = end;
+        Ok(bytes)
+    }
 
-    resize_db_flag: u8,
+    // A unix time in milliseconds
+    fn read_millis(&mut self) -> anyhow::Result<u64> {
+        Ok(u64::from_le_bytes(self.read_bytes(8)?.try_into()?))
+    }
 
-    // Size of the whole table
-    hash_table_size: EncodedInteger,
+    // A score of an old TYPE_ZSET, spelled out in ASCII after a length that also flags NaN and
+    // the infinities
+    fn read_double_string(&mut self) -> anyhow::Result<f64> {
+        Ok(match self.read_u8()? {
+            253 => f64::NAN,
+            254 => f64::INFINITY,
+            255 => f64::NEG_INFINITY,
+            length => std::str::from_utf8(self.read_bytes(length as usize)?)?.parse()?,
+        })
+    }
 
-    // The number of entries which expire within the above
-    // i.e. must be <= hash_table_size
-    hash_table_size_with_expiry: EncodedInteger,
+    fn read_u8(&mut self) -> anyhow::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
 
-    #[deku(count = "hash_table_size.value")]
-    kv_pairs: Vec<KeyValuePair>,
-}
+    fn read_length_or_encoding(&mut self) -> anyhow::Result<Length> {
+        let first = self.read_u8()?;
+        Ok(match first >> 6 {
+            0b00 => Length::Plain((first & 0x3F) as u64),
+            0b01 => Length::Plain((((first & 0x3F) as u64) << 8) | self.read_u8()? as u64),
+            0b10 => match first {
+                0x80 => Length::Plain(u32::from_be_bytes(self.read_bytes(4)?.try_into()?) as u64),
+                0x81 => Length::Plain(u64::from_be_bytes(self.read_bytes(8)?.try_into()?)),
+                _ => bail!("Invalid length encoding {:#04x}", first),
+            },
+            _ => Length::Encoded((first & 0x3F) as u64),
+        })
+    }
 
-#[derive(Debug, DekuRead)]
-struct KeyValuePair {
-    expiring_or_type_flag: u8,
+    fn read_length(&mut self) -> anyhow::Result<usize> {
+        match self.read_length_or_encoding()? {
+            Length::Plain(length) => Ok(length as usize),
+            Length::Encoded(_) => bail!("Expected a length, found a string encoding"),
+        }
+    }
 
-    #[deku(cond = "*(expiring_or_type_flag) == 0xFD")]
-    expiry_time_in_s: Option<u32>,
+    fn read_string(&mut self) -> anyhow::Result<Bytes> {
+        let length = match self.read_length_or_encoding()? {
+            Length::Plain(length) => length as usize,
+            Length::Encoded(ENC_INT8) => {
+                let value = self.read_u8()? as i8;
+                return Ok(Bytes::from(value.to_string()));
+            }
+            Length::Encoded(ENC_INT16) => {
+                let value = i16::from_le_bytes(self.read_bytes(2)?.try_into()?);
+                return Ok(Bytes::from(value.to_string()));
+            }
+            Length::Encoded(ENC_INT32) => {
+                let value = i32::from_le_bytes(self.read_bytes(4)?.try_into()?);
+                return Ok(Bytes::from(value.to_string()));
+            }
+            Length::Encoded(ENC_LZF) => {
+                let compressed_length = self.read_length()?;
+                let length = self.read_length()?;
+                let compressed = self.read_bytes(compressed_length)?;
+                return Ok(Bytes::from(lzf_decompress(compressed, length)?));
+            }
+            Length::Encoded(other) => bail!("Unknown string encoding {}", other),
+        };
 
-    #[deku(cond = "*(expiring_or_type_flag) == 0xFC")]
-    expiry_time_in_ms: Option<u64>,
+        Ok(Bytes::copy_from_slice(self.read_bytes(length)?))
+    }
 
-    #[deku(cond = "*(expiring_or_type_flag) == 0xFC || *(expiring_or_type_flag) == 0xFD")]
-    _type: u8,
+    fn read_value(&mut self, value_type: u8) -> anyhow::Result<Value> {
+        Ok(match value_type {
+            TYPE_STRING => Value::String(self.read_string()?),
+            TYPE_LIST => {
+                let length = self.read_length()?;
+                let list = (0..length)
+                    .map(|_| self.read_string())
+                    .collect::<anyhow::Result<QuickList>>()?;
+                Value::List(Arc::new(list))
+            }
+            TYPE_LIST_ZIPLIST => {
+                let list = listpack::decode_ziplist(&self.read_string()?)?;
+                Value::List(Arc::new(list.into_iter().collect()))
+            }
+            TYPE_LIST_QUICKLIST | TYPE_LIST_QUICKLIST_2 => {
+                let mut list = QuickList::new();
+                for _ in 0..self.read_length()? {
+                    let container = match value_type {
+                        TYPE_LIST_QUICKLIST => QUICKLIST_NODE_PACKED,
+                        _ => self.read_length()?,
+                    };
+                    let node = self.read_string()?;
+                    let elements = match (value_type, container) {
+                        (_, QUICKLIST_NODE_PLAIN) => vec![node],
+                        (TYPE_LIST_QUICKLIST, _) => listpack::decode_ziplist(&node)?,
+                        _ => listpack::decode(&node)?,
+                    };
+                    for element in elements {
+                        list.push_back(element);
+                    }
+                }
+                Value::List(Arc::new(list))
+            }
+            TYPE_SET => {
+                let length = self.read_length()?;
+                let set = (0..length)
+                    .map(|_| self.read_string())
+                    .collect::<anyhow::Result<Set>>()?;
+                Value::Set(Arc::new(set))
+            }
+            TYPE_SET_INTSET => {
+                let integers = decode_intset(&self.read_string()?)?;
+                let set = integers
+                    .into_iter()
+                    .map(|value| Bytes::from(value.to_string()))
+                    .collect();
+                Value::Set(Arc::new(set))
+            }
+            TYPE_SET_LISTPACK => {
+                let set = listpack::decode(&self.read_string()?)?
+                    .into_iter()
+                    .collect();
+                Value::Set(Arc::new(set))
+            }
+            TYPE_ZSET | TYPE_ZSET_2 => {
+                let mut zset = SortedSet::new();
+                for _ in 0..self.read_length()? {
+                    let member = self.read_string()?;
+                    let score = match value_type {
+                        TYPE_ZSET => self.read_double_string()?,
+                        _ => f64::from_le_bytes(self.read_bytes(8)?.try_into()?),
+                    };
+                    if score.is_nan() {
+                        bail!("Sorted set member with a NaN score");
+                    }
+                    zset.insert(member, score);
+                }
+                Value::ZSet(Arc::new(zset))
+            }
+            TYPE_ZSET_ZIPLIST | TYPE_ZSET_LISTPACK => {
+                let elements = match value_type {
+                    TYPE_ZSET_ZIPLIST => listpack::decode_ziplist(&self.read_string()?)?,
+                    _ => listpack::decode(&self.read_string()?)?,
+                };
+                // Members and scores alternate
+                if !elements.len().is_multiple_of(2) {
+                    bail!("Sorted set with a member missing its score");
+                }
+                let mut zset = SortedSet::new();
+                for pair in elements.chunks(2) {
+                    let score: f64 = std::str::from_utf8(&pair[1])?.parse()?;
+                    if score.is_nan() {
+                        bail!("Sorted set member with a NaN score");
+                    }
+                    zset.insert(pair[0].clone(), score);
+                }
+                Value::ZSet(Arc::new(zset))
+            }
+            TYPE_HASH => {
+                let length = self.read_length()?;
+                let hash = (0..length)
+                    .map(|_| Ok((self.read_string()?, self.read_string()?)))
+                    .collect::<anyhow::Result<Hash>>()?;
+                Value::Hash(Arc::new(hash))
+            }
+            TYPE_HASH_ZIPLIST | TYPE_HASH_LISTPACK => {
+                let elements = match value_type {
+                    TYPE_HASH_ZIPLIST => listpack::decode_ziplist(&self.read_string()?)?,
+                    _ => listpack::decode(&self.read_string()?)?,
+                };
+                // Fields and values alternate
+                if !elements.len().is_multiple_of(2) {
+                    bail!("Hash with a field missing its value");
+                }
+                let hash = elements
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect();
+                Value::Hash(Arc::new(hash))
+            }
+            TYPE_HASH_METADATA => {
+                let min_expire = self.read_millis()?;
+                let mut hash = Hash::new();
+                for _ in 0..self.read_length()? {
+                    // Deadlines are relative to the earliest one, with 0 meaning none
+                    let ttl = self.read_length()? as u64;
+                    let field = self.read_string()?;
+                    hash.insert(field.clone(), self.read_string()?);
+                    if ttl != 0 {
+                        let expire_at = UNIX_EPOCH + Duration::from_millis(ttl + min_expire - 1);
+                        hash.set_expiry(&field, Some(expire_at));
+                    }
+                }
+                Value::Hash(Arc::new(hash))
+            }
+            TYPE_HASH_LISTPACK_EX => {
+                self.read_millis()?;
+                let elements = listpack::decode(&self.read_string()?)?;
+                // Each field is followed by its value and deadline, which is 0 for none
+                if !elements.len().is_multiple_of(3) {
+                    bail!("Hash with a field missing its value or expiry");
+                }
+                let mut hash = Hash::new();
+                for triplet in elements.chunks(3) {
+                    let [field, value, expire_at] = triplet else {
+                        unreachable!();
+                    };
+                    hash.insert(field.clone(), value.clone());
+                    let millis: u64 = std::str::from_utf8(expire_at)?.parse()?;
+                    if millis != 0 {
+                        let expire_at = UNIX_EPOCH + Duration::from_millis(millis);
+                        hash.set_expiry(field, Some(expire_at));
+                    }
+                }
+                Value::Hash(Arc::new(hash))
+            }
+            other => bail!("Unsupported RDB value type {}", other),
+        })
+    }
+}
 
-    key: EncodedString,
-    value: EncodedString,
+/// Builds up an RDB file in memory.
+struct RdbWriter {
+    buf: Vec<u8>,
 }
 
-#[derive(Debug, DekuRead)]
-struct EncodedInteger {
-    #[deku(reader = "EncodedLength::read_length(deku::reader)")]
-    length: u8,
+impl RdbWriter {
+    fn write_length(&mut self, length: usize) {
+        if length < 1 << 6 {
+            self.buf.push(length as u8);
+        } else if length < 1 << 14 {
+            self.buf
+                .extend_from_slice(&[0x40 | (length >> 8) as u8, length as u8]);
+        } else if let Ok(length) = u32::try_from(length) {
+            self.buf.push(0x80);
+            self.buf.extend_from_slice(&length.to_be_bytes());
+        } else {
+            self.buf.push(0x81);
+            self.buf.extend_from_slice(&(length as u64).to_be_bytes());
+        }
+    }
 
-    #[deku(reader = "EncodedLength::read_value(deku::reader, *(length))")]
-    pub value: u32,
-}
+    fn write_string(&mut self, string: &[u8]) {
+        self.write_length(string.len());
+        self.buf.extend_from_slice(string);
+    }
 
-#[derive(Debug, DekuRead)]
-struct EncodedString {
-    #[deku(bytes = "1")]
-    length: u8,
+    // The value's type goes first, then the key and the value itself
+    fn write_key_value(&mut self, key: &[u8], value: &Value) {
+        self.buf.push(match value {
+            Value::String(_) => TYPE_STRING,
+            Value::List(_) => TYPE_LIST_QUICKLIST_2,
+            Value::Hash(hash) => match (hash.is_compact(), hash.next_expiry().is_some()) {
+                (true, false) => TYPE_HASH_LISTPACK,
+                (true, true) => TYPE_HASH_LISTPACK_EX,
+                (false, false) => TYPE_HASH,
+                (false, true) => TYPE_HASH_METADATA,
+            },
+            Value::Set(set) if set.integers().is_some() => TYPE_SET_INTSET,
+            Value::Set(_) => TYPE_SET,
+            Value::ZSet(zset) if zset.is_compact() => TYPE_ZSET_LISTPACK,
+            Value::ZSet(_) => TYPE_ZSET_2,
+        });
+        self.write_string(key);
 
-    #[deku(count = "length")]
-    value: Vec<u8>,
-}
+        match value {
+            Value::String(string) => self.write_string(string),
+            Value::List(list) => {
+                let elements: Vec<&Bytes> = list.iter().collect();
+                self.write_length(elements.len().div_ceil(LISTPACK_SIZE));
+                for node in elements.chunks(LISTPACK_SIZE) {
+                    self.write_length(QUICKLIST_NODE_PACKED);
+                    self.write_string(&listpack::encode(node.iter().copied()));
+                }
+            }
+            Value::Hash(hash) => {
+                let min_expire = hash.next_expiry().map(unix_millis);
+                if let Some(min_expire) = min_expire {
+                    self.buf.extend_from_slice(&min_expire.to_le_bytes());
+                }
 
-// run --package redis-starter-rust --bin redis-starter-rust -- --dir /home/lolletsoc/devel/pets/code_crafters/redis/test --dbfilename dump.rdb
-
-pub async fn read_rdb_from_bytes(
-    bytes: &[u8],
-    arc: Arc<DashMap<String, (String, Option<SystemTime>)>>,
-) -> anyhow::Result<()> {
-    match RDB::from_bytes((&bytes, 0)) {
-        Ok((_, rdb)) => {
-            rdb.database.kv_pairs.iter().for_each(|e| {
-                let expire_duration;
-                if let Some(ms_expiry) = e.expiry_time_in_ms {
-                    expire_duration = Some(Duration::from_millis(ms_expiry));
-                } else if let Some(s_expiry) = e.expiry_time_in_s {
-                    expire_duration = Some(Duration::from_secs(s_expiry as u64));
+                if hash.is_compact() {
+                    let mut elements = Vec::new();
+                    for (field, value, expire_at) in hash.iter_with_expiry() {
+                        elements.extend([field.clone(), value.clone()]);
+                        if min_expire.is_some() {
+                            let millis = expire_at.map_or(0, unix_millis);
+                            elements.push(Bytes::from(millis.to_string()));
+                        }
+                    }
+                    self.write_string(&listpack::encode(&elements));
                 } else {
-                    expire_duration = None;
-                }
-
-                arc.insert(
-                    String::from_utf8(e.key.value.clone()).unwrap(),
-                    (
-                        String::from_utf8(e.value.value.clone()).unwrap(),
-                        match expire_duration {
-                            Some(dur) => Some(UNIX_EPOCH.add(dur)),
-                            None => None,
-                        },
-                    ),
-                );
-            });
-            Ok(())
-        }
-        Err(err) => {
-            // Failed to parse
-            Err(anyhow::anyhow!(err))
+                    self.write_length(hash.len());
+                    for (field, value, expire_at) in hash.iter_with_expiry() {
+                        if let Some(min_expire) = min_expire {
+                            let ttl = expire_at
+                                .map_or(0, |expire_at| unix_millis(expire_at) - min_expire + 1);
+                            self.write_length(ttl as usize);
+                        }
+                        self.write_string(field);
+                        self.write_string(value);
+                    }
+                }
+            }
+            Value::Set(set) => match set.integers() {
+                Some(integers) => self.write_string(&encode_intset(integers)),
+                None => {
+                    self.write_length(set.len());
+                    for member in set.iter() {
+                        self.write_string(&member);
+                    }
+                }
+            },
+            Value::ZSet(zset) if zset.is_compact() => {
+                let elements: Vec<Bytes> = zset
+                    .iter()
+                    .flat_map(|(member, score)| [member.clone(), Bytes::from(format_double(score))])
+                    .collect();
+                self.write_string(&listpack::encode(&elements));
+            }
+            Value::ZSet(zset) => {
+                self.write_length(zset.len());
+                for (member, score) in zset.iter() {
+                    self.write_string(member);
+                    self.buf.extend_from_slice(&score.to_le_bytes());
+                }
+            }
         }
     }
 }
 
-pub async fn read_rdb(
-    dir: &str,
-    filename: &str,
-    arc: Arc<DashMap<String, (String, Option<SystemTime>)>>,
-) -> anyhow::Result<()> {
-    match File::open(Path::new(dir).join(filename)) {
-        Ok(mut file) => {
-            match RDB::from_reader((&mut file, 0)) {
-                Ok((_, rdb)) => {
-                    rdb.database.kv_pairs.iter().for_each(|e| {
-                        let expire_duration;
-                        if let Some(ms_expiry) = e.expiry_time_in_ms {
-                            expire_duration = Some(Duration::from_millis(ms_expiry));
-                        } else if let Some(s_expiry) = e.expiry_time_in_s {
-                            expire_duration = Some(Duration::from_secs(s_expiry as u64));
-                        } else {
-                            expire_duration = None;
-                        }
+fn unix_millis(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64
+}
 
-                        arc.insert(
-                            String::from_utf8(e.key.value.clone()).unwrap(),
-                            (
-                                String::from_utf8(e.value.value.clone()).unwrap(),
-                                match expire_duration {
-                                    Some(dur) => Some(UNIX_EPOCH.add(dur)),
-                                    None => None,
-                                },
-                            ),
-                        );
-                    });
-                    Ok(())
+/// Loads every database section of an RDB file into the matching database of `server`.
+pub fn read_rdb_from_bytes(bytes: &[u8], server: &Server) -> anyhow::Result<()> {
+    let mut reader = RdbReader { buf: bytes, pos: 0 };
+
+    let header = reader.read_bytes(9)?;
+    if &header[..5] != b"REDIS" {
+        bail!("Not an RDB file");
+    }
+
+    // Expired keys are dropped as they would be deleted right away, unless our master has
+    // yet to send the DEL for them
+    let now = SystemTime::now();
+    let mut db = server.db(0);
+    let mut expire_at = None;
+    loop {
+        match reader.read_u8()? {
+            OPCODE_EOF => return Ok(()),
+            OPCODE_SELECTDB => {
+                let index = reader.read_length()?;
+                if index >= server.db_count() {
+                    bail!(
+                        "RDB file holds database {} but only {} are configured",
+                        index,
+                        server.db_count()
+                    );
                 }
-                Err(err) => {
-                    // Failed to parse
-                    Err(anyhow::anyhow!(err))
+                db = server.db(index);
+            }
+            OPCODE_RESIZEDB => {
+                reader.read_length()?;
+                reader.read_length()?;
+            }
+            OPCODE_AUX => {
+                reader.read_string()?;
+                reader.read_string()?;
+            }
+            OPCODE_EXPIRETIME => {
+                let seconds = u32::from_le_bytes(reader.read_bytes(4)?.try_into()?);
+                expire_at = Some(UNIX_EPOCH + Duration::from_secs(seconds as u64));
+            }
+            OPCODE_EXPIRETIME_MS => {
+                let millis = reader.read_millis()?;
+                expire_at = Some(UNIX_EPOCH + Duration::from_millis(millis));
+            }
+            // Eviction hints we have no use for
+            OPCODE_IDLE => {
+                reader.read_length()?;
+            }
+            OPCODE_FREQ => {
+                reader.read_u8()?;
+            }
+            OPCODE_SLOT_INFO => {
+                reader.read_length()?;
+                reader.read_length()?;
+                reader.read_length()?;
+            }
+            OPCODE_MODULE_AUX | OPCODE_FUNCTION2 => {
+                bail!("RDB files with modules or functions are not supported")
+            }
+            value_type => {
+                let key = reader.read_string()?;
+                let mut value = reader.read_value(value_type)?;
+                if let Value::Hash(hash) = &mut value {
+                    if server.is_master() {
+                        Arc::make_mut(hash).remove_expired(now);
+                    }
+                    // Nothing left once every field expired
+                    if hash.is_empty() {
+                        expire_at = None;
+                        continue;
+                    }
+                }
+
+                let entry = Entry::new(value, expire_at.take());
+                if !server.is_master() || !entry.is_expired(now) {
+                    db.insert(key, entry);
                 }
             }
         }
+    }
+}
 
-        // Doesn't exist. Ignore and continue
-        Err(_) => Ok(()),
+/// Serializes every database of `server` in the RDB format.
+pub fn write_rdb(server: &Server) -> Vec<u8> {
+    let mut writer = RdbWriter {
+        buf: b"REDIS0011".to_vec(),
+    };
+    for (name, value) in [("redis-ver", REDIS_VERSION), ("redis-bits", "64")] {
+        writer.buf.push(OPCODE_AUX);
+        writer.write_string(name.as_bytes());
+        writer.write_string(value.as_bytes());
     }
+
+    for index in 0..server.db_count() {
+        let entries = server.db(index).entries();
+        if entries.is_empty() {
+            continue;
+        }
+
+        writer.buf.push(OPCODE_SELECTDB);
+        writer.write_length(index);
+        writer.buf.push(OPCODE_RESIZEDB);
+        writer.write_length(entries.len());
+        writer.write_length(
+            entries
+                .iter()
+                .filter(|(_, entry)| entry.expire_at.is_some())
+                .count(),
+        );
+
+        for (key, entry) in entries {
+            if let Some(expire_at) = entry.expire_at {
+                writer.buf.push(OPCODE_EXPIRETIME_MS);
+                writer
+                    .buf
+                    .extend_from_slice(&unix_millis(expire_at).to_le_bytes());
+            }
+            writer.write_key_value(&key, &entry.value);
+        }
+    }
+
+    // A zero checksum tells readers not to verify it
+    writer.buf.push(OPCODE_EOF);
+    writer.buf.extend_from_slice(&[0; 8]);
+    writer.buf
 }
 
-struct EncodedLength(());
-
-impl EncodedLength {
-    fn read_value<R: std::io::Read>(reader: &mut Reader<R>, length: u8) -> Result<u32, DekuError> {
-        let read_bits = reader.read_bits(length as usize).expect("");
-        let binding = read_bits.unwrap();
-
-        let i = binding.load_be::<u32>();
-        Ok(i)
-    }
-    fn read_length<R: std::io::Read>(reader: &mut Reader<R>) -> Result<u8, DekuError> {
-        let result = reader.read_bits(2).unwrap().unwrap();
-        let two_bit_specifier = result.as_raw_slice()[0];
-
-        match two_bit_specifier {
-            0b0000_0000 => Ok(6),
-            0b0100_0000 => Ok(14),
-            0b1000_0000 => {
-                reader.read_bits(6)?.unwrap();
-                Ok(32)
-            }
-            0b1100_0000 => {
-                let special_format_bits = reader.read_bits(6)?.unwrap().as_raw_slice()[0];
-                match special_format_bits {
-                    0b0000_0000 => Ok(8),
-                    0b1000_0000 => Ok(16),
-                    0b0100_0000 => Ok(32),
-                    0b1100_0000 => {
-                        // Complete `Compressed Strings` at some point
-                        Ok(1)
-                    }
-                    _ => {
-                        // Liam, fix this
-                        Ok(0)
-                    }
-                }
+/// Saves a snapshot of `server` to `dir/filename`, by way of a temporary file so a failed save
+/// never clobbers the previous one.
+pub async fn save(dir: &str, filename: &str, server: &Server) -> anyhow::Result<()> {
+    let bytes = write_rdb(server);
+    let temp = Path::new(dir).join(format!("temp-{}.rdb", std::process::id()));
+    tokio::fs::write(&temp, bytes).await?;
+    tokio::fs::rename(&temp, Path::new(dir).join(filename)).await?;
+    Ok(())
+}
+
+/// Loads `dir/filename` into `server`. A missing file simply means starting out empty.
+pub async fn read_rdb(dir: &str, filename: &str, server: &Server) -> anyhow::Result<()> {
+    match tokio::fs::read(Path::new(dir).join(filename)).await {
+        Ok(bytes) => read_rdb_from_bytes(&bytes, server),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err.into()),
+    }
+}
+
+/// Decodes an intset, a sorted array of integers all stored with the width the largest of them
+/// needs.
+fn decode_intset(buf: &[u8]) -> anyhow::Result<Vec<i64>> {
+    let header = buf.get(..8).context("Truncated intset")?;
+    let width = u32::from_le_bytes(header[..4].try_into()?) as usize;
+    let length = u32::from_le_bytes(header[4..].try_into()?) as usize;
+    if ![2, 4, 8].contains(&width) {
+        bail!("Unknown intset encoding {}", width);
+    }
+
+    let contents = buf.get(8..8 + width * length).context("Truncated intset")?;
+    Ok(contents
+        .chunks(width)
+        .map(|value| match width {
+            2 => i16::from_le_bytes([value[0], value[1]]) as i64,
+            4 => i32::from_le_bytes(value.try_into().unwrap()) as i64,
+            _ => i64::from_le_bytes(value.try_into().unwrap()),
+        })
+        .collect())
+}
+
+fn encode_intset(integers: &[i64]) -> Vec<u8> {
+    let width = if integers.iter().all(|&value| i16::try_from(value).is_ok()) {
+        2
+    } else if integers.iter().all(|&value| i32::try_from(value).is_ok()) {
+        4
+    } else {
+        8
+    };
+
+    let mut buf = Vec::with_capacity(8 + width * integers.len());
+    buf.extend_from_slice(&(width as u32).to_le_bytes());
+    buf.extend_from_slice(&(integers.len() as u32).to_le_bytes());
+    for value in integers {
+        buf.extend_from_slice(&value.to_le_bytes()[..width]);
+    }
+    buf
+}
+
+/// Inflates a string compressed with LZF, which Redis uses for long strings in RDB files.
+fn lzf_decompress(input: &[u8], length: usize) -> anyhow::Result<Vec<u8>> {
+    let mut output = Vec::with_capacity(length);
+    let mut i = 0;
+
+    while i < input.len() {
+        let control = input[i] as usize;
+        i += 1;
+
+        if control < 32 {
+            // A run of control + 1 literal bytes
+            let literal = input
+                .get(i..i + control + 1)
+                .context("Truncated LZF literal")?;
+            output.extend_from_slice(literal);
+            i += control + 1;
+        } else {
+            // A back reference, whose length may spill over into an extra byte
+            let mut run = control >> 5;
+            if run == 7 {
+                run += *input.get(i).context("Truncated LZF back reference")? as usize;
+                i += 1;
             }
-            _ => {
-                // Fix later
-                Ok(0)
+            let offset = ((control & 0x1F) << 8)
+                + *input.get(i).context("Truncated LZF back reference")? as usize
+                + 1;
+            i += 1;
+
+            let start = output
+                .len()
+                .checked_sub(offset)
+                .context("LZF back reference points before the start")?;
+            // Byte by byte, as the reference may overlap what it is producing
+            for position in start..start + run + 2 {
+                output.push(output[position]);
             }
         }
     }
+
+    if output.len() != length {
+        bail!(
+            "LZF string inflated to {} bytes instead of {}",
+            output.len(),
+            length
+        );
+    }
+    Ok(output)
 }
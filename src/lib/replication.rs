@@ -1,17 +1,24 @@
-use crate::models::Args;
-use crate::models::Command::{PSync, Ping, ReplConf};
-use crate::processing::{receive_ack, write_and_flush};
+use crate::models::RespValue;
+use crate::processing::write_and_flush;
 use crate::rdb::read_rdb_from_bytes;
+use crate::resp::RespReader;
+use crate::server::Server;
 use anyhow::Context;
-use dashmap::DashMap;
 use rand::distr::Alphanumeric;
 use rand::Rng;
 use std::sync::Arc;
-use std::time::SystemTime;
-use tokio::io::{AsyncReadExt, BufStream};
+use tokio::net::tcp::OwnedWriteHalf;
 use tokio::net::TcpStream;
 use tokio::sync::Mutex;
 
+/// A replica attached to us, which is sent every write we make.
+pub struct Replica {
+    pub stream: Arc<Mutex<OwnedWriteHalf>>,
+
+    // Database the link last selected, None until we send it a SELECT
+    pub selected_db: Option<usize>,
+}
+
 pub struct MasterReplicationInfo {
     pub replid: String,
     pub repl_offset: u8,
@@ -32,47 +39,68 @@ impl MasterReplicationInfo {
     }
 }
 
-pub async fn init_replication(replicaof: &str, args: &Arc<Args>) -> anyhow::Result<TcpStream> {
+impl Default for MasterReplicationInfo {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Performs the replication handshake with our master.
+///
+/// Returns both halves of the link, over which the master keeps streaming its writes.
+pub async fn init_replication(
+    replicaof: &str,
+    server: &Server,
+) -> anyhow::Result<(RespReader, OwnedWriteHalf)> {
     let split: Vec<&str> = replicaof.split(' ').collect();
 
-    let mut tcp_stream = TcpStream::connect(format!("{}:{}", split[0], split[1]))
+    let tcp_stream = TcpStream::connect(format!("{}:{}", split[0], split[1]))
         .await
         .with_context(|| "Failed to connect to replica")?;
+    let (read_half, mut write_half) = tcp_stream.into_split();
+    let mut reader = RespReader::new(read_half);
 
-    write_and_flush(&mut tcp_stream, Ping).await;
-    receive_ack(&mut tcp_stream).await?;
+    write_and_flush(&mut write_half, RespValue::command(["PING"])).await?;
+    receive_ack(&mut reader).await?;
 
+    let port = server.args.port.to_string();
     write_and_flush(
-        &mut tcp_stream,
-        ReplConf("listening-port".to_string(), args.port.to_string()),
+        &mut write_half,
+        RespValue::command(["REPLCONF", "listening-port", port.as_str()]),
     )
-    .await;
-    receive_ack(&mut tcp_stream).await?;
+    .await?;
+    receive_ack(&mut reader).await?;
 
     write_and_flush(
-        &mut tcp_stream,
-        ReplConf("capa".to_string(), "psync2".to_string()),
+        &mut write_half,
+        RespValue::command(["REPLCONF", "capa", "psync2"]),
     )
-    .await;
-    receive_ack(&mut tcp_stream).await?;
-
-    write_and_flush(&mut tcp_stream, PSync("?".to_string(), "-1".to_string())).await;
+    .await?;
+    receive_ack(&mut reader).await?;
 
-    // This is a hack to deal with FULLRESYNC and the empty RDB file.
-    // Will go back to this at a later stage - only requires proper use of BufStream
-    let mut bytes = [0; 58];
-    tcp_stream
-        .read_exact(&mut bytes)
-        .await
-        .with_context(|| "Failed to receive bytes")?;
+    write_and_flush(&mut write_half, RespValue::command(["PSYNC", "?", "-1"])).await?;
+    match reader.read_value().await? {
+        Some(RespValue::SimpleString(reply)) if reply.starts_with("FULLRESYNC") => {}
+        reply => anyhow::bail!("Unexpected reply to PSYNC: {:?}", reply),
+    }
 
-    let mut bytes = [0; 93];
-    tcp_stream
-        .read_exact(&mut bytes)
-        .await
-        .with_context(|| "Failed to receive bytes")?;
+    let snapshot = reader
+        .read_rdb()
+        .await?
+        .with_context(|| "Master closed the connection before sending its snapshot")?;
+    read_rdb_from_bytes(&snapshot, server).with_context(|| "Failed to load master's snapshot")?;
 
     // From here on, we will receive all replication commands
 
-    Ok(tcp_stream)
+    Ok((reader, write_half))
+}
+
+async fn receive_ack(reader: &mut RespReader) -> anyhow::Result<()> {
+    match reader.read_value().await? {
+        Some(RespValue::SimpleString(_)) => Ok(()),
+        reply => Err(anyhow::Error::msg(format!(
+            "Received unexpected ACK: {:?}",
+            reply
+        ))),
+    }
 }
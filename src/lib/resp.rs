@@ -0,0 +1,320 @@
+use crate::models::RespValue;
+use bytes::{Buf, Bytes, BytesMut};
+use thiserror::Error;
+use tokio::io::AsyncReadExt;
+use tokio::net::tcp::OwnedReadHalf;
+
+// Same limits Redis applies to client requests
+const MAX_MULTIBULK_LENGTH: i64 = 1024 * 1024;
+const MAX_BULK_LENGTH: i64 = 512 * 1024 * 1024;
+const MAX_INLINE_LENGTH: usize = 64 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ProtocolError {
+    #[error("Protocol error: invalid multibulk length")]
+    InvalidMultibulkLength,
+
+    #[error("Protocol error: invalid bulk length")]
+    InvalidBulkLength,
+
+    #[error("Protocol error: expected '$', got '{0}'")]
+    ExpectedBulk(char),
+
+    #[error("Protocol error: too big inline request")]
+    InlineTooBig,
+
+    #[error("Protocol error: unbalanced quotes in request")]
+    UnbalancedQuotes,
+
+    #[error("Protocol error: bulk payload is not terminated by CRLF")]
+    UnterminatedBulk,
+
+    #[error("Protocol error: unexpected type byte '{0}'")]
+    UnexpectedType(char),
+
+    #[error("Protocol error: invalid value")]
+    InvalidValue,
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+/// A single decoded request along with the number of bytes it occupied on the wire.
+/// An empty `args` means a null or empty multibulk, which Redis silently skips.
+#[derive(Debug)]
+pub struct Frame {
+    pub args: Vec<Bytes>,
+    pub len: usize,
+}
+
+/// Decodes one request from the start of `buf`, either multibulk or inline.
+///
+/// Returns `Ok(None)` when `buf` does not yet hold a complete request, so the caller
+/// can read more bytes and try again without anything being consumed.
+pub fn decode_request(buf: &[u8]) -> Result<Option<Frame>, ProtocolError> {
+    match buf.first() {
+        None => Ok(None),
+        Some(b'*') => decode_multibulk(buf),
+        Some(_) => decode_inline(buf),
+    }
+}
+
+fn decode_multibulk(buf: &[u8]) -> Result<Option<Frame>, ProtocolError> {
+    let mut pos = 0;
+
+    let Some(header) = read_line(buf, &mut pos) else {
+        return Ok(None);
+    };
+
+    let num_of_elems = parse_length(&header[1..]).ok_or(ProtocolError::InvalidMultibulkLength)?;
+    if num_of_elems > MAX_MULTIBULK_LENGTH {
+        return Err(ProtocolError::InvalidMultibulkLength);
+    }
+    if num_of_elems <= 0 {
+        return Ok(Some(Frame {
+            args: vec![],
+            len: pos,
+        }));
+    }
+
+    let mut args = Vec::with_capacity(num_of_elems as usize);
+    for _ in 0..num_of_elems {
+        let Some(bulk_header) = read_line(buf, &mut pos) else {
+            return Ok(None);
+        };
+        match bulk_header.first() {
+            Some(b'$') => {}
+            Some(other) => return Err(ProtocolError::ExpectedBulk(*other as char)),
+            None => return Err(ProtocolError::ExpectedBulk(' ')),
+        }
+
+        let length = parse_length(&bulk_header[1..]).ok_or(ProtocolError::InvalidBulkLength)?;
+        if !(0..=MAX_BULK_LENGTH).contains(&length) {
+            return Err(ProtocolError::InvalidBulkLength);
+        }
+
+        let length = length as usize;
+        // Payload plus the trailing \r\n
+        if buf.len() < pos + length + 2 {
+            return Ok(None);
+        }
+        if &buf[pos + length..pos + length + 2] != b"\r\n" {
+            return Err(ProtocolError::UnterminatedBulk);
+        }
+
+        args.push(Bytes::copy_from_slice(&buf[pos..pos + length]));
+        pos += length + 2;
+    }
+
+    Ok(Some(Frame { args, len: pos }))
+}
+
+/// Decodes a telnet-style request: space separated arguments on a single line.
+fn decode_inline(buf: &[u8]) -> Result<Option<Frame>, ProtocolError> {
+    let Some(newline) = buf.iter().position(|c| *c == b'\n') else {
+        if buf.len() > MAX_INLINE_LENGTH {
+            return Err(ProtocolError::InlineTooBig);
+        }
+        return Ok(None);
+    };
+
+    // Plain \n is accepted too, as netcat and friends don't send \r\n
+    let line = buf[..newline]
+        .strip_suffix(b"\r")
+        .unwrap_or(&buf[..newline]);
+    Ok(Some(Frame {
+        args: split_args(line)?,
+        len: newline + 1,
+    }))
+}
+
+/// Splits a line into arguments honouring quotes, following the rules of Redis' `sdssplitargs`.
+///
+/// Double quoted arguments support the usual escapes (`\n`, `\"`, `\xHH`...), single quoted
+/// ones only `\'`. A closing quote must be followed by a space or the end of the line.
+fn split_args(line: &[u8]) -> Result<Vec<Bytes>, ProtocolError> {
+    let mut args = Vec::new();
+    let mut i = 0;
+
+    loop {
+        while i < line.len() && line[i].is_ascii_whitespace() {
+            i += 1;
+        }
+        if i == line.len() {
+            return Ok(args);
+        }
+
+        let mut arg = Vec::new();
+        let mut in_double_quotes = false;
+        let mut in_single_quotes = false;
+        loop {
+            let Some(&c) = line.get(i) else {
+                if in_double_quotes || in_single_quotes {
+                    return Err(ProtocolError::UnbalancedQuotes);
+                }
+                break;
+            };
+
+            if in_double_quotes {
+                match c {
+                    b'\\'
+                        if i + 3 < line.len()
+                            && line[i + 1] == b'x'
+                            && line[i + 2].is_ascii_hexdigit()
+                            && line[i + 3].is_ascii_hexdigit() =>
+                    {
+                        let hex = std::str::from_utf8(&line[i + 2..i + 4]).unwrap();
+                        arg.push(u8::from_str_radix(hex, 16).unwrap());
+                        i += 3;
+                    }
+                    b'\\' if i + 1 < line.len() => {
+                        i += 1;
+                        arg.push(match line[i] {
+                            b'n' => b'\n',
+                            b'r' => b'\r',
+                            b't' => b'\t',
+                            b'b' => 0x08,
+                            b'a' => 0x07,
+                            other => other,
+                        });
+                    }
+                    b'"' => {
+                        if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                            return Err(ProtocolError::UnbalancedQuotes);
+                        }
+                        i += 1;
+                        break;
+                    }
+                    _ => arg.push(c),
+                }
+            } else if in_single_quotes {
+                match c {
+                    b'\\' if line.get(i + 1) == Some(&b'\'') => {
+                        i += 1;
+                        arg.push(b'\'');
+                    }
+                    b'\'' => {
+                        if line.get(i + 1).is_some_and(|c| !c.is_ascii_whitespace()) {
+                            return Err(ProtocolError::UnbalancedQuotes);
+                        }
+                        i += 1;
+                        break;
+                    }
+                    _ => arg.push(c),
+                }
+            } else {
+                match c {
+                    c if c.is_ascii_whitespace() => break,
+                    b'"' => in_double_quotes = true,
+                    b'\'' => in_single_quotes = true,
+                    _ => arg.push(c),
+                }
+            }
+            i += 1;
+        }
+
+        args.push(Bytes::from(arg));
+    }
+}
+
+/// Returns the line starting at `pos` without its \r\n, advancing `pos` past it.
+pub(crate) fn read_line<'a>(buf: &'a [u8], pos: &mut usize) -> Option<&'a [u8]> {
+    let start = *pos;
+    let end = buf[start..].windows(2).position(|w| w == b"\r\n")? + start;
+    *pos = end + 2;
+    Some(&buf[start..end])
+}
+
+pub(crate) fn parse_length(digits: &[u8]) -> Option<i64> {
+    std::str::from_utf8(digits).ok()?.parse().ok()
+}
+
+/// Reads requests or replies off a connection, buffering partial ones until they are complete.
+pub struct RespReader {
+    stream: OwnedReadHalf,
+    buffer: BytesMut,
+}
+
+impl RespReader {
+    pub fn new(stream: OwnedReadHalf) -> RespReader {
+        RespReader {
+            stream,
+            buffer: BytesMut::with_capacity(16 * 1024),
+        }
+    }
+
+    /// Returns the next request, or `None` once the peer has closed the connection.
+    pub async fn read_frame(&mut self) -> Result<Option<Frame>, ProtocolError> {
+        loop {
+            if let Some(frame) = self.next_buffered()? {
+                return Ok(Some(frame));
+            }
+
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                // EOF, dropping whatever partial request is left
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Returns the next request if it has already been received in full, without waiting on the socket.
+    pub fn next_buffered(&mut self) -> Result<Option<Frame>, ProtocolError> {
+        let frame = decode_request(&self.buffer)?;
+        if let Some(frame) = &frame {
+            self.buffer.advance(frame.len);
+        }
+        Ok(frame)
+    }
+
+    /// Waits for the peer to close the connection, buffering whatever it sends meanwhile.
+    pub async fn closed(&mut self) {
+        while let Ok(1..) = self.stream.read_buf(&mut self.buffer).await {}
+    }
+
+    /// Returns the next reply, or `None` once the peer has closed the connection.
+    pub async fn read_value(&mut self) -> Result<Option<RespValue>, ProtocolError> {
+        loop {
+            if let Some((value, len)) = RespValue::decode(&self.buffer)? {
+                self.buffer.advance(len);
+                return Ok(Some(value));
+            }
+
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Ok(None);
+            }
+        }
+    }
+
+    /// Reads an RDB snapshot, which is framed like a bulk string without the trailing \r\n.
+    pub async fn read_rdb(&mut self) -> Result<Option<Bytes>, ProtocolError> {
+        loop {
+            let mut pos = 0;
+            if let Some(header) = read_line(&self.buffer, &mut pos) {
+                match header.split_first() {
+                    Some((b'$', _)) => {}
+                    Some((other, _)) => return Err(ProtocolError::ExpectedBulk(*other as char)),
+                    None => return Err(ProtocolError::ExpectedBulk(' ')),
+                }
+                let length = parse_length(&header[1..])
+                    .filter(|length| *length >= 0)
+                    .ok_or(ProtocolError::InvalidBulkLength)? as usize;
+
+                if self.buffer.len() >= pos + length {
+                    self.buffer.advance(pos);
+                    return Ok(Some(self.buffer.split_to(length).freeze()));
+                }
+            }
+
+            if self.stream.read_buf(&mut self.buffer).await? == 0 {
+                return Ok(None);
+            }
+        }
+    }
+}
+
+/// Wire protocol spoken on a connection, negotiated with HELLO.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Protocol {
+    Resp2,
+    Resp3,
+}
@@ -0,0 +1,211 @@
+use crate::blocking::BlockedClients;
+use crate::db::Db;
+use crate::models::{Args, RedisError, RespValue};
+use crate::processing::write_and_flush;
+use crate::replication::{MasterReplicationInfo, Replica};
+use bytes::Bytes;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::mpsc::{UnboundedReceiver, UnboundedSender};
+use tokio::sync::{mpsc, Mutex};
+
+// Keys looked at per round of the active expiry cycle. Another round follows straight away
+// when all of them had expired, as there are likely many more
+const ACTIVE_EXPIRE_KEYS_PER_LOOP: usize = 20;
+
+// Share of each tick the active expiry cycle may spend deleting keys
+const ACTIVE_EXPIRE_CYCLE_PERCENT: u32 = 25;
+
+/// State shared by every connection handled by this server.
+pub struct Server {
+    pub args: Args,
+    pub rep_info: MasterReplicationInfo,
+    pub replicas: Mutex<Vec<Replica>>,
+    pub tx: UnboundedSender<(usize, Vec<Bytes>)>,
+    pub blocked: BlockedClients,
+
+    // Behind a lock only so SWAPDB can exchange two of them
+    dbs: RwLock<Vec<Arc<Db>>>,
+
+    client_ids: AtomicU64,
+
+    // How many times per second background tasks such as active expiry run
+    hz: AtomicU32,
+}
+
+impl Server {
+    /// Creates the server state and spawns the task forwarding writes to replicas.
+    pub fn start(args: Args) -> Arc<Server> {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let hz = AtomicU32::new(clamp_hz(args.hz));
+        let dbs = (0..args.databases.max(1)).map(|_| Arc::new(Db::new()));
+        let server = Arc::new(Server {
+            args,
+            rep_info: MasterReplicationInfo::new(),
+            replicas: Mutex::new(Vec::new()),
+            tx,
+            blocked: BlockedClients::default(),
+            dbs: RwLock::new(dbs.collect()),
+            client_ids: AtomicU64::new(0),
+            hz,
+        });
+
+        tokio::spawn(propagate(server.clone(), rx));
+        tokio::spawn(active_expire(server.clone()));
+        server
+    }
+
+    pub fn is_master(&self) -> bool {
+        self.args.replicaof.is_none()
+    }
+
+    pub fn next_client_id(&self) -> u64 {
+        self.client_ids.fetch_add(1, Ordering::Relaxed) + 1
+    }
+
+    pub fn hz(&self) -> u32 {
+        self.hz.load(Ordering::Relaxed)
+    }
+
+    pub fn set_hz(&self, hz: i64) {
+        self.hz.store(clamp_hz(hz), Ordering::Relaxed);
+    }
+
+    pub fn db(&self, index: usize) -> Arc<Db> {
+        self.dbs.read().unwrap()[index].clone()
+    }
+
+    pub fn db_count(&self) -> usize {
+        self.dbs.read().unwrap().len()
+    }
+
+    /// Validates a database index given by a client.
+    pub fn db_index(&self, index: i64) -> Result<usize, RedisError> {
+        usize::try_from(index)
+            .ok()
+            .filter(|index| *index < self.db_count())
+            .ok_or_else(|| RedisError::Err("DB index is out of range".to_string()))
+    }
+
+    pub fn swap_dbs(&self, first: usize, second: usize) {
+        self.dbs.write().unwrap().swap(first, second);
+    }
+
+    /// Queues a write made to database `index` for our replicas.
+    ///
+    /// `argv` is what replicas should execute, which isn't necessarily what the client sent:
+    /// relative expiries, for instance, are resolved so every replica expires the key at once.
+    pub fn replicate(&self, index: usize, argv: Vec<Bytes>) {
+        // The write itself went through, so a client isn't failed for it
+        if self.tx.send((index, argv)).is_err() {
+            println!("Replication channel is closed, the write won't reach replicas");
+        }
+    }
+
+    /// Deletes `key` from database `index` if it has expired, or else the hash fields of it
+    /// that have, telling replicas about it.
+    ///
+    /// Replicas never expire keys themselves, they wait for the DEL or HDEL from their master.
+    pub fn expire_if_needed(&self, index: usize, key: &Bytes) {
+        if !self.is_master() {
+            return;
+        }
+
+        let db = self.db(index);
+        if db.has_expired(key) {
+            db.remove(key);
+            self.replicate(index, vec![Bytes::from_static(b"DEL"), key.clone()]);
+            return;
+        }
+
+        let fields = db.remove_expired_fields(key, SystemTime::now());
+        if !fields.is_empty() {
+            let argv = [Bytes::from_static(b"HDEL"), key.clone()]
+                .into_iter()
+                .chain(fields);
+            self.replicate(index, argv.collect());
+        }
+    }
+}
+
+// Drains the replication channel even with no replicas attached,
+// otherwise writers would stall once the channel fills up.
+// Each replica is sent a SELECT whenever the next write is for another database, and is
+// dropped once a write to it fails
+async fn propagate(server: Arc<Server>, mut rx: UnboundedReceiver<(usize, Vec<Bytes>)>) {
+    while let Some((index, argv)) = rx.recv().await {
+        println!("Received command for replication: {:?}", argv);
+        let command: Vec<u8> = RespValue::command(argv).into();
+        let mut replicas = server.replicas.lock().await;
+        let mut connected = Vec::with_capacity(replicas.len());
+        for mut replica in replicas.drain(..) {
+            let mut bytes = Vec::new();
+            if replica.selected_db != Some(index) {
+                let select = RespValue::command(["SELECT", index.to_string().as_str()]);
+                bytes.extend(Vec::<u8>::from(select));
+                replica.selected_db = Some(index);
+            }
+            bytes.extend_from_slice(&command);
+
+            let written = {
+                let mut stream = replica.stream.lock().await;
+                write_and_flush(&mut *stream, bytes).await
+            };
+            match written {
+                Ok(bytes_written) => {
+                    println!("Wrote {} bytes for replication", bytes_written);
+                    connected.push(replica);
+                }
+                Err(err) => println!("Dropping replica after a failed write: {}", err),
+            }
+        }
+        *replicas = connected;
+    }
+}
+
+// Deletes expired keys and hash fields nobody accesses anymore, which lazy expiry would never
+// get to.
+// Runs `hz` times per second, but never for longer than a quarter of each tick
+async fn active_expire(server: Arc<Server>) {
+    loop {
+        let tick = Duration::from_millis(1000 / server.hz() as u64);
+        tokio::time::sleep(tick).await;
+        if !server.is_master() {
+            continue;
+        }
+
+        let started = Instant::now();
+        let budget = tick * ACTIVE_EXPIRE_CYCLE_PERCENT / 100;
+        for index in 0..server.db_count() {
+            let db = server.db(index);
+            loop {
+                let keys = db.expired_keys(SystemTime::now(), ACTIVE_EXPIRE_KEYS_PER_LOOP);
+                for key in &keys {
+                    server.expire_if_needed(index, key);
+                }
+
+                if keys.len() < ACTIVE_EXPIRE_KEYS_PER_LOOP || started.elapsed() >= budget {
+                    break;
+                }
+            }
+
+            // Hash fields get the same treatment
+            loop {
+                let keys = db.due_hashes(SystemTime::now(), ACTIVE_EXPIRE_KEYS_PER_LOOP);
+                for key in &keys {
+                    server.expire_if_needed(index, key);
+                }
+
+                if keys.len() < ACTIVE_EXPIRE_KEYS_PER_LOOP || started.elapsed() >= budget {
+                    break;
+                }
+            }
+        }
+    }
+}
+
+// Same bounds Redis enforces on its `hz` setting
+fn clamp_hz(hz: i64) -> u32 {
+    hz.clamp(1, 500) as u32
+}
@@ -0,0 +1,220 @@
+use crate::db::scan_hash;
+use bytes::Bytes;
+use std::collections::{BTreeSet, HashMap};
+
+// Limit past which a set of integers leaves its compact form, the default of Redis'
+// set-max-intset-entries
+const INTSET_MAX_ENTRIES: usize = 512;
+
+/// A set, stored much like Redis does.
+///
+/// Small sets holding nothing but integers are a sorted array of them, which is binary searched
+/// and takes little memory. Once a set outgrows that or gets any other member it becomes a
+/// table, and stays one.
+///
+/// Either way members can be reached by position, so picking some at random doesn't take
+/// copying the set.
+#[derive(Debug, Clone)]
+pub struct Set {
+    encoding: Encoding,
+}
+
+#[derive(Debug, Clone)]
+enum Encoding {
+    IntSet(Vec<i64>),
+    Table {
+        // Every member in no particular order, kept dense by moving the last member into the
+        // place of a removed one
+        members: Vec<Bytes>,
+        positions: HashMap<Bytes, usize>,
+
+        // Every member ordered by its scan hash, which SSCAN cursors point into
+        scan_order: BTreeSet<(u64, Bytes)>,
+    },
+}
+
+impl Default for Set {
+    fn default() -> Set {
+        Set {
+            encoding: Encoding::IntSet(Vec::new()),
+        }
+    }
+}
+
+impl Set {
+    pub fn new() -> Set {
+        Set::default()
+    }
+
+    pub fn len(&self) -> usize {
+        match &self.encoding {
+            Encoding::IntSet(integers) => integers.len(),
+            Encoding::Table { members, .. } => members.len(),
+        }
+    }
+
+    /// The member at `position` in an order of the set's choosing, which stays put until the
+    /// set is next modified.
+    pub fn get(&self, position: usize) -> Option<Bytes> {
+        match &self.encoding {
+            Encoding::IntSet(integers) => integers
+                .get(position)
+                .map(|value| Bytes::from(value.to_string())),
+            Encoding::Table { members, .. } => members.get(position).cloned(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// The members in ascending order if the set is still an array of integers.
+    pub fn integers(&self) -> Option<&[i64]> {
+        match &self.encoding {
+            Encoding::IntSet(integers) => Some(integers),
+            Encoding::Table { .. } => None,
+        }
+    }
+
+    pub fn contains(&self, member: &[u8]) -> bool {
+        match &self.encoding {
+            Encoding::IntSet(integers) => {
+                as_integer(member).is_some_and(|value| integers.binary_search(&value).is_ok())
+            }
+            Encoding::Table { positions, .. } => positions.contains_key(member),
+        }
+    }
+
+    /// Adds `member`, returning whether it wasn't there yet.
+    pub fn insert(&mut self, member: Bytes) -> bool {
+        if let Encoding::IntSet(integers) = &mut self.encoding {
+            if let Some(value) = as_integer(&member) {
+                let Err(position) = integers.binary_search(&value) else {
+                    return false;
+                };
+                if integers.len() < INTSET_MAX_ENTRIES {
+                    integers.insert(position, value);
+                    return true;
+                }
+            }
+            self.convert_to_table();
+        }
+
+        let Encoding::Table {
+            members,
+            positions,
+            scan_order,
+        } = &mut self.encoding
+        else {
+            unreachable!();
+        };
+        if positions.contains_key(&member) {
+            return false;
+        }
+        positions.insert(member.clone(), members.len());
+        members.push(member.clone());
+        scan_order.insert((scan_hash(&member), member));
+        true
+    }
+
+    pub fn remove(&mut self, member: &[u8]) -> bool {
+        match &mut self.encoding {
+            Encoding::IntSet(integers) => {
+                let Some(position) =
+                    as_integer(member).and_then(|value| integers.binary_search(&value).ok())
+                else {
+                    return false;
+                };
+                integers.remove(position);
+                true
+            }
+            Encoding::Table {
+                members,
+                positions,
+                scan_order,
+            } => {
+                let Some((member, position)) = positions.remove_entry(member) else {
+                    return false;
+                };
+                members.swap_remove(position);
+                if let Some(moved) = members.get(position) {
+                    positions.insert(moved.clone(), position);
+                }
+                scan_order.remove(&(scan_hash(&member), member));
+                true
+            }
+        }
+    }
+
+    pub fn iter(&self) -> Box<dyn Iterator<Item = Bytes> + '_> {
+        match &self.encoding {
+            Encoding::IntSet(integers) => {
+                Box::new(integers.iter().map(|value| Bytes::from(value.to_string())))
+            }
+            Encoding::Table { members, .. } => Box::new(members.iter().cloned()),
+        }
+    }
+
+    /// A copy of every member.
+    pub fn members(&self) -> Vec<Bytes> {
+        self.iter().collect()
+    }
+
+    /// Returns about `count` members starting at `cursor`, and the cursor the next call should
+    /// pass.
+    ///
+    /// Works like `Db::scan`. An array of integers is small enough to be returned whole, as
+    /// Redis does too.
+    pub fn scan(&self, cursor: u64, count: usize) -> (u64, Vec<Bytes>) {
+        let Encoding::Table { scan_order, .. } = &self.encoding else {
+            return (0, self.members());
+        };
+
+        let mut members = Vec::with_capacity(count);
+        let mut last_hash = None;
+        for (hash, member) in scan_order.range((cursor, Bytes::new())..) {
+            // Members sharing a hash can't be told apart by a cursor, so they go out together
+            if members.len() >= count && last_hash != Some(*hash) {
+                return (*hash, members);
+            }
+            last_hash = Some(*hash);
+            members.push(member.clone());
+        }
+        (0, members)
+    }
+
+    fn convert_to_table(&mut self) {
+        let members: Vec<Bytes> = self.iter().collect();
+        let positions = members
+            .iter()
+            .enumerate()
+            .map(|(position, member)| (member.clone(), position))
+            .collect();
+        let scan_order = members
+            .iter()
+            .map(|member| (scan_hash(member), member.clone()))
+            .collect();
+        self.encoding = Encoding::Table {
+            members,
+            positions,
+            scan_order,
+        };
+    }
+}
+
+impl FromIterator<Bytes> for Set {
+    fn from_iter<I: IntoIterator<Item = Bytes>>(iter: I) -> Set {
+        let mut set = Set::new();
+        for member in iter {
+            set.insert(member);
+        }
+        set
+    }
+}
+
+// Only members spelled exactly the way Redis would print the number fit in the array, so they
+// come back byte for byte
+fn as_integer(member: &[u8]) -> Option<i64> {
+    let value: i64 = std::str::from_utf8(member).ok()?.parse().ok()?;
+    (value.to_string().as_bytes() == member).then_some(value)
+}
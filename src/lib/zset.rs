@@ -0,0 +1,410 @@
+use bytes::Bytes;
+use rand::Rng;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+
+// Levels a skiplist node may have, and the odds of a node reaching each next one, as in Redis
+const MAX_LEVEL: usize = 32;
+const LEVEL_PROBABILITY: f64 = 0.25;
+
+// Limits past which a sorted set is no longer saved in its compact form, the defaults of
+// Redis' zset-max-listpack-entries and zset-max-listpack-value
+const COMPACT_MAX_ENTRIES: usize = 128;
+const COMPACT_MAX_VALUE: usize = 64;
+
+// The skiplist's first node, which holds no member and has every level
+const HEADER: usize = 0;
+
+/// A sorted set: members looked up by name, and kept ordered by score then name.
+///
+/// Scores are in a table. The order lives in a skiplist like Redis', where each link also
+/// counts the members it skips, so finding a member, a rank or a score takes logarithmic time.
+/// Nodes are kept in a vector and link to each other by position, removed ones leaving their
+/// slot for the next insertion.
+#[derive(Debug, Clone)]
+pub struct SortedSet {
+    scores: HashMap<Bytes, f64>,
+    nodes: Vec<Node>,
+    free: Vec<usize>,
+    tail: Option<usize>,
+
+    // Levels in use by at least one node, and at least 1
+    level: usize,
+
+    // Whether the set is still small enough for a listpack, which it stops being for good
+    compact: bool,
+}
+
+#[derive(Debug, Clone)]
+struct Node {
+    member: Bytes,
+    score: f64,
+    backward: Option<usize>,
+    levels: Vec<Link>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Link {
+    forward: Option<usize>,
+
+    // Members the link steps over, the one it lands on included
+    span: usize,
+}
+
+impl Default for SortedSet {
+    fn default() -> SortedSet {
+        let header = Node {
+            member: Bytes::new(),
+            score: 0.0,
+            backward: None,
+            levels: vec![
+                Link {
+                    forward: None,
+                    span: 0,
+                };
+                MAX_LEVEL
+            ],
+        };
+        SortedSet {
+            scores: HashMap::new(),
+            nodes: vec![header],
+            free: Vec::new(),
+            tail: None,
+            level: 1,
+            compact: true,
+        }
+    }
+}
+
+impl SortedSet {
+    pub fn new() -> SortedSet {
+        SortedSet::default()
+    }
+
+    pub fn len(&self) -> usize {
+        self.scores.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scores.is_empty()
+    }
+
+    pub fn is_compact(&self) -> bool {
+        self.compact
+    }
+
+    pub fn score(&self, member: &[u8]) -> Option<f64> {
+        self.scores.get(member).copied()
+    }
+
+    /// Sets the score of `member`, returning the score it had before if it was there.
+    pub fn insert(&mut self, member: Bytes, score: f64) -> Option<f64> {
+        let previous = self.remove(&member);
+        if member.len() > COMPACT_MAX_VALUE || self.len() >= COMPACT_MAX_ENTRIES {
+            self.compact = false;
+        }
+
+        // The last node before the new one on each level, and its rank
+        let mut update = [HEADER; MAX_LEVEL];
+        let mut rank = [0; MAX_LEVEL];
+        let mut x = HEADER;
+        for i in (0..self.level).rev() {
+            rank[i] = if i == self.level - 1 { 0 } else { rank[i + 1] };
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                let node = &self.nodes[next];
+                if compare((node.score, &node.member), (score, &member)).is_ge() {
+                    break;
+                }
+                rank[i] += self.nodes[x].levels[i].span;
+                x = next;
+            }
+            update[i] = x;
+        }
+
+        let level = random_level();
+        if level > self.level {
+            for i in self.level..level {
+                self.nodes[HEADER].levels[i].span = self.len();
+            }
+            self.level = level;
+        }
+
+        let node = self.allocate(Node {
+            member: member.clone(),
+            score,
+            backward: (update[0] != HEADER).then_some(update[0]),
+            levels: Vec::with_capacity(level),
+        });
+        for i in 0..level {
+            let before = self.nodes[update[i]].levels[i];
+            let skipped = rank[0] - rank[i];
+            self.nodes[node].levels.push(Link {
+                forward: before.forward,
+                span: before.span - skipped,
+            });
+            self.nodes[update[i]].levels[i] = Link {
+                forward: Some(node),
+                span: skipped + 1,
+            };
+        }
+        // Higher links now step over the new node too
+        for (i, &before) in update.iter().enumerate().take(self.level).skip(level) {
+            self.nodes[before].levels[i].span += 1;
+        }
+        match self.nodes[node].levels[0].forward {
+            Some(next) => self.nodes[next].backward = Some(node),
+            None => self.tail = Some(node),
+        }
+
+        self.scores.insert(member, score);
+        previous
+    }
+
+    /// Removes `member`, returning its score.
+    pub fn remove(&mut self, member: &[u8]) -> Option<f64> {
+        let score = self.scores.remove(member)?;
+
+        let mut update = [HEADER; MAX_LEVEL];
+        let mut x = HEADER;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                let node = &self.nodes[next];
+                if compare((node.score, &node.member), (score, member)).is_ge() {
+                    break;
+                }
+                x = next;
+            }
+            update[i] = x;
+        }
+        let node = self.nodes[x].levels[0]
+            .forward
+            .expect("member missing from the order of its sorted set");
+
+        for (i, &before) in update.iter().enumerate().take(self.level) {
+            let removed = self.nodes[node].levels.get(i).copied();
+            let link = &mut self.nodes[before].levels[i];
+            match removed {
+                Some(removed) if link.forward == Some(node) => {
+                    link.forward = removed.forward;
+                    link.span = link.span + removed.span - 1;
+                }
+                _ => link.span -= 1,
+            }
+        }
+        let backward = self.nodes[node].backward;
+        match self.nodes[node].levels[0].forward {
+            Some(next) => self.nodes[next].backward = backward,
+            None => self.tail = backward,
+        }
+        while self.level > 1 && self.nodes[HEADER].levels[self.level - 1].forward.is_none() {
+            self.level -= 1;
+        }
+
+        self.nodes[node].member = Bytes::new();
+        self.nodes[node].levels.clear();
+        self.free.push(node);
+        Some(score)
+    }
+
+    /// The position of `member` counting from the lowest score.
+    pub fn rank(&self, member: &[u8]) -> Option<usize> {
+        let score = self.score(member)?;
+        Some(self.partition_point(|m, s| compare((s, m), (score, member)).is_lt()))
+    }
+
+    /// The number of leading members for which `before` holds. It must hold for the members up
+    /// to some point and for none after, like the predicate of `slice::partition_point`.
+    pub fn partition_point(&self, before: impl Fn(&Bytes, f64) -> bool) -> usize {
+        let mut rank = 0;
+        let mut x = HEADER;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                let node = &self.nodes[next];
+                if !before(&node.member, node.score) {
+                    break;
+                }
+                rank += self.nodes[x].levels[i].span;
+                x = next;
+            }
+        }
+        rank
+    }
+
+    /// Every member with its score, from the lowest score up.
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            set: self,
+            front: self.nodes[HEADER].levels[0].forward,
+            back: self.tail,
+            remaining: self.len(),
+        }
+    }
+
+    /// Iterates up from the member at `rank`.
+    pub fn iter_from(&self, rank: usize) -> impl Iterator<Item = (&Bytes, f64)> {
+        Iter {
+            set: self,
+            front: self.node_at(rank),
+            back: self.tail,
+            remaining: self.len().saturating_sub(rank),
+        }
+    }
+
+    /// Iterates down from the member at `rank`, which must exist.
+    pub fn iter_rev_from(&self, rank: usize) -> impl Iterator<Item = (&Bytes, f64)> {
+        let node = self
+            .node_at(rank)
+            .expect("rank past the end of the sorted set");
+        Iter {
+            set: self,
+            front: self.nodes[HEADER].levels[0].forward,
+            back: Some(node),
+            remaining: rank + 1,
+        }
+        .rev()
+    }
+
+    /// The node holding the member at `rank`.
+    fn node_at(&self, rank: usize) -> Option<usize> {
+        if rank >= self.len() {
+            return None;
+        }
+
+        // Spans count the header as rank 0
+        let target = rank + 1;
+        let mut traversed = 0;
+        let mut x = HEADER;
+        for i in (0..self.level).rev() {
+            while let Some(next) = self.nodes[x].levels[i].forward {
+                let span = self.nodes[x].levels[i].span;
+                if traversed + span > target {
+                    break;
+                }
+                traversed += span;
+                x = next;
+            }
+            if traversed == target {
+                return Some(x);
+            }
+        }
+        None
+    }
+
+    fn allocate(&mut self, node: Node) -> usize {
+        match self.free.pop() {
+            Some(slot) => {
+                self.nodes[slot] = node;
+                slot
+            }
+            None => {
+                self.nodes.push(node);
+                self.nodes.len() - 1
+            }
+        }
+    }
+}
+
+impl FromIterator<(Bytes, f64)> for SortedSet {
+    fn from_iter<I: IntoIterator<Item = (Bytes, f64)>>(iter: I) -> SortedSet {
+        let mut set = SortedSet::new();
+        for (member, score) in iter {
+            set.insert(member, score);
+        }
+        set
+    }
+}
+
+/// Walks a sorted set's members with their scores, from either end.
+pub struct Iter<'a> {
+    set: &'a SortedSet,
+    front: Option<usize>,
+    back: Option<usize>,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = (&'a Bytes, f64);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.set.nodes[self.front?];
+        self.front = node.levels[0].forward;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl DoubleEndedIterator for Iter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let node = &self.set.nodes[self.back?];
+        self.back = node.backward;
+        self.remaining -= 1;
+        Some((&node.member, node.score))
+    }
+}
+
+// Orders by score, then by member for equal scores. Scores are never NaN
+fn compare(a: (f64, &[u8]), b: (f64, &[u8])) -> Ordering {
+    a.0.partial_cmp(&b.0).unwrap().then_with(|| a.1.cmp(b.1))
+}
+
+fn random_level() -> usize {
+    let mut rng = rand::thread_rng();
+    let mut level = 1;
+    while level < MAX_LEVEL && rng.gen_bool(LEVEL_PROBABILITY) {
+        level += 1;
+    }
+    level
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_ranks_in_order_through_inserts_and_removals() {
+        let mut rng = rand::thread_rng();
+        let mut set = SortedSet::new();
+        let mut model: Vec<(f64, Bytes)> = Vec::new();
+
+        for _ in 0..5000 {
+            let member = Bytes::from(format!("m{}", rng.gen_range(0..500)));
+            if rng.gen_bool(0.3) {
+                let removed = set.remove(&member);
+                let position = model.iter().position(|(_, m)| *m == member);
+                assert_eq!(removed, position.map(|position| model.remove(position).0));
+            } else {
+                let score = rng.gen_range(0..50) as f64;
+                set.insert(member.clone(), score);
+                model.retain(|(_, m)| *m != member);
+                model.push((score, member));
+                model.sort_by(|a, b| compare((a.0, &a.1), (b.0, &b.1)));
+            }
+        }
+
+        let expected: Vec<(&Bytes, f64)> = model.iter().map(|(s, m)| (m, *s)).collect();
+        assert_eq!(set.len(), model.len());
+        assert_eq!(set.iter().collect::<Vec<_>>(), expected);
+        assert!(set.iter().rev().eq(expected.iter().rev().copied()));
+        for (rank, (score, member)) in model.iter().enumerate() {
+            assert_eq!(set.rank(member), Some(rank));
+            assert_eq!(set.iter_from(rank).next(), Some((member, *score)));
+            assert!(set
+                .iter_rev_from(rank)
+                .eq(expected[..=rank].iter().rev().copied()));
+        }
+        assert_eq!(set.iter_from(model.len()).next(), None);
+        assert_eq!(set.partition_point(|_, score| score < 25.0), {
+            model.iter().filter(|(score, _)| *score < 25.0).count()
+        });
+    }
+}
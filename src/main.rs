@@ -1,95 +1,29 @@
 use clap::Parser;
-use dashmap::DashMap;
-use redis_starter_rust::models::{to_command, Args, BaseError, Command};
-use redis_starter_rust::processing::{process_command, write_and_flush};
+use redis_starter_rust::connection::handle_connection;
+use redis_starter_rust::models::Args;
 use redis_starter_rust::rdb::read_rdb;
-use redis_starter_rust::replication::{init_replication, MasterReplicationInfo};
-use std::sync::atomic::{AtomicIsize, AtomicU64, AtomicUsize};
-use std::sync::Arc;
-use std::time::SystemTime;
-use tokio::io::BufStream;
-use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::mpsc::{Receiver, Sender};
-use tokio::sync::{mpsc, Mutex};
+use redis_starter_rust::replication::init_replication;
+use redis_starter_rust::resp::RespReader;
+use redis_starter_rust::server::Server;
+use tokio::net::TcpListener;
 
 #[tokio::main(flavor = "current_thread")]
 async fn main() -> Result<(), anyhow::Error> {
-    let args = Arc::new(Args::parse());
-    let map: Arc<DashMap<String, (String, Option<SystemTime>)>> = Arc::new(DashMap::new());
-    let master_rep_info = Arc::new(MasterReplicationInfo::new());
-    let replicas = Arc::new(Mutex::new(Vec::new()));
-    let (tx, rx): (Sender<Command>, Receiver<Command>) = mpsc::channel(100);
-    let tx = Arc::new(tx);
-    let rx = Arc::new(Mutex::new(rx));
+    let server = Server::start(Args::parse());
 
-    if let (Some(dir), Some(filename)) = (&args.dir, &args.dbfilename) {
-        read_rdb(dir, filename, map.clone()).await?;
+    if let (Some(dir), Some(filename)) = (&server.args.dir, &server.args.dbfilename) {
+        read_rdb(dir, filename, &server).await?;
     }
 
-    let listener = TcpListener::bind(format!("127.0.0.1:{}", args.port)).await?;
-    println!("Listening on {}", args.port);
+    let listener = TcpListener::bind(format!("127.0.0.1:{}", server.args.port)).await?;
+    println!("Listening on {}", server.args.port);
 
-    if let Some(repinfo) = &args.replicaof {
-        let replication_stream = init_replication(repinfo, &args)
+    if let Some(repinfo) = &server.args.replicaof {
+        let (reader, write_half) = init_replication(repinfo, &server)
             .await
             .expect("Replication init failed");
 
-        let map_ref = map.clone();
-        let args_ref = args.clone();
-        let rep_ref = master_rep_info.clone();
-        let replicas = replicas.clone();
-        let tx = tx.clone();
-        let rx = rx.clone();
-
-        let std_stream = replication_stream.into_std().unwrap();
-        let cloned_stream = std_stream.try_clone().unwrap();
-
-        let cloned_tcp_stream = TcpStream::from_std(cloned_stream).unwrap();
-        let cloned_buf_stream = BufStream::new(cloned_tcp_stream);
-        let buf_stream = Arc::new(Mutex::new(cloned_buf_stream));
-
-        let arc_stream = Arc::new(Mutex::new(TcpStream::from_std(std_stream).unwrap()));
-
-        let offset = AtomicUsize::new(0);
-        tokio::spawn(async move {
-            loop {
-                let binding = buf_stream.clone();
-                let mut guard = binding.lock().await;
-                let request = to_command(&mut guard, &offset).await;
-                match request {
-                    Ok(Some(request)) => {
-                        process_command(
-                            request.command,
-                            &args_ref,
-                            &rep_ref,
-                            &map_ref,
-                            arc_stream.clone(),
-                            &replicas,
-                            &tx,
-                            &rx,
-                            &offset,
-                        )
-                        .await;
-                    }
-                    Ok(None) => {
-                        // EOF
-                        println!("No more data");
-                        break;
-                    }
-                    Err(err) => {
-                        let arc = arc_stream.clone();
-                        let mut stream_guard = arc.lock().await;
-                        write_and_flush(
-                            &mut stream_guard,
-                            BaseError {
-                                message: err.to_string(),
-                            },
-                        )
-                        .await;
-                    }
-                }
-            }
-        });
+        tokio::spawn(handle_connection(server.clone(), reader, write_half, true));
 
         println!("Initialized replication with master");
     }
@@ -98,63 +32,9 @@ async fn main() -> Result<(), anyhow::Error> {
         match listener.accept().await {
             Ok((stream, addr)) => {
                 println!("New connection from {}", addr);
-
-                let map_ref = map.clone();
-                let args_ref = args.clone();
-                let rep_ref = master_rep_info.clone();
-                let replicas = replicas.clone();
-                let tx = tx.clone();
-                let rx = rx.clone();
-
-                let std_stream = stream.into_std().unwrap();
-                let cloned_stream = std_stream.try_clone().unwrap();
-
-                let cloned_tcp_stream = TcpStream::from_std(cloned_stream).unwrap();
-                let cloned_buf_stream = BufStream::new(cloned_tcp_stream);
-                let buf_stream = Arc::new(Mutex::new(cloned_buf_stream));
-
-                let offset = AtomicUsize::new(0);
-
-                let arc_stream = Arc::new(Mutex::new(TcpStream::from_std(std_stream).unwrap()));
-                tokio::spawn(async move {
-                    loop {
-                        let binding = buf_stream.clone();
-                        let mut guard = binding.lock().await;
-                        let request = to_command(&mut guard, &offset).await;
-                        match request {
-                            Ok(Some(request)) => {
-                                process_command(
-                                    request.command,
-                                    &args_ref,
-                                    &rep_ref,
-                                    &map_ref,
-                                    arc_stream.clone(),
-                                    &replicas,
-                                    &tx,
-                                    &rx,
-                                    &offset,
-                                )
-                                .await;
-                            }
-                            Ok(None) => {
-                                // EOF
-                                println!("No more data");
-                                break;
-                            }
-                            Err(err) => {
-                                let arc = arc_stream.clone();
-                                let mut stream_guard = arc.lock().await;
-                                write_and_flush(
-                                    &mut stream_guard,
-                                    BaseError {
-                                        message: err.to_string(),
-                                    },
-                                )
-                                .await;
-                            }
-                        }
-                    }
-                });
+                let (read_half, write_half) = stream.into_split();
+                let reader = RespReader::new(read_half);
+                tokio::spawn(handle_connection(server.clone(), reader, write_half, false));
             }
             Err(err) => {
                 println!("Error establishing connection: {}", err);